@@ -0,0 +1,36 @@
+//! Old closed todos moved out of the database, for `todo archive`.
+
+use crate::storage::{FlatFileStorage, Storage};
+use crate::todo::Todo;
+use std::collections::HashSet;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// The archive of a database is a text database next to it, e.g.
+/// `todos_archive.txt` for `todos.db`.
+pub fn path(db_path: &Path) -> PathBuf {
+    let stem = db_path.file_stem().unwrap_or_default().to_string_lossy();
+
+    db_path.with_file_name(format!("{}_archive.txt", stem))
+}
+
+pub fn load(db_path: &Path) -> io::Result<Vec<Todo>> {
+    FlatFileStorage::new(path(db_path)).load_todos()
+}
+
+/// Adds `todos` to the archive. Todos already in it, e.g. from an archive run
+/// that was interrupted, are not added twice.
+pub fn append(db_path: &Path, todos: &[Todo]) -> io::Result<()> {
+    let mut storage = FlatFileStorage::new(path(db_path));
+    let mut metadata = storage.load_metadata()?;
+    let mut archived = storage.load_todos()?;
+
+    let uuids: HashSet<_> = archived.iter().map(|t| t.uuid).collect();
+    archived.extend(todos.iter().filter(|t| !uuids.contains(&t.uuid)).cloned());
+    metadata.seq_id = archived
+        .iter()
+        .map(|t| t.id)
+        .fold(metadata.seq_id, u32::max);
+
+    storage.save_todos(&metadata, &archived)
+}
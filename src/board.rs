@@ -0,0 +1,165 @@
+//! Kanban board with a column per stage, for `todo board`.
+
+use crate::database::Database;
+use crate::error::TodoError;
+use crate::journal::Operation;
+use crate::todo::{Status, Todo};
+use chrono::prelude::*;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::Line;
+use ratatui::widgets::{Block, List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::cmp::Reverse;
+
+const HELP: &str = "h/l column  j/k card  H/L move card  q quit";
+
+/// The columns from left to right, with the status a card moved there gets.
+/// Blocked todos are shown with the open ones and cancelled with the done.
+const COLUMNS: [(&str, Status); 3] = [
+    ("Open", Status::Open),
+    ("In progress", Status::InProgress),
+    ("Done", Status::Done),
+];
+
+fn column_of(status: Status) -> usize {
+    match status {
+        Status::Open | Status::Blocked => 0,
+        Status::InProgress => 1,
+        Status::Done | Status::Cancelled => 2,
+    }
+}
+
+struct Board {
+    /// The column with the selection
+    column: usize,
+    lists: [ListState; 3],
+}
+
+pub fn run(db: &mut Database) -> Result<(), TodoError> {
+    let mut terminal = ratatui::try_init()?;
+    let mut board = Board {
+        column: 0,
+        lists: Default::default(),
+    };
+    let result = board.run(&mut terminal, db);
+    ratatui::restore();
+
+    result?;
+    Ok(db.save()?)
+}
+
+impl Board {
+    fn run(&mut self, terminal: &mut DefaultTerminal, db: &mut Database) -> Result<(), TodoError> {
+        loop {
+            let columns = cards(db);
+            for (list, ids) in self.lists.iter_mut().zip(&columns) {
+                match list.selected() {
+                    _ if ids.is_empty() => list.select(None),
+                    None => list.select(Some(0)),
+                    Some(index) if index >= ids.len() => list.select(Some(ids.len() - 1)),
+                    Some(_) => {}
+                }
+            }
+            terminal.draw(|frame| self.draw(frame, db, &columns))?;
+
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            let selected = self.lists[self.column]
+                .selected()
+                .and_then(|index| columns[self.column].get(index).copied());
+
+            match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(()),
+                KeyCode::Char('c') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    return Ok(())
+                }
+                KeyCode::Char('h') | KeyCode::Left => self.column = self.column.saturating_sub(1),
+                KeyCode::Char('l') | KeyCode::Right => self.column = (self.column + 1).min(2),
+                KeyCode::Char('j') | KeyCode::Down => self.lists[self.column].select_next(),
+                KeyCode::Char('k') | KeyCode::Up => self.lists[self.column].select_previous(),
+                KeyCode::Char('H') if self.column > 0 => self.move_card(db, selected, -1)?,
+                KeyCode::Char('L') if self.column < 2 => self.move_card(db, selected, 1)?,
+                _ => {}
+            }
+        }
+    }
+
+    /// Moves the card to the next column in `direction` and selects it there.
+    fn move_card(
+        &mut self,
+        db: &mut Database,
+        id: Option<u32>,
+        direction: isize,
+    ) -> Result<(), TodoError> {
+        let Some(mut todo) = id.and_then(|id| db.find(id)).cloned() else {
+            return Ok(());
+        };
+        self.column = self.column.saturating_add_signed(direction);
+        todo.set_status(COLUMNS[self.column].1, Local::now());
+        db.apply(Operation::Update { todo })?;
+
+        let index = cards(db)[self.column].iter().position(|&i| Some(i) == id);
+        self.lists[self.column].select(index);
+
+        Ok(())
+    }
+
+    fn draw(&mut self, frame: &mut Frame, db: &Database, columns: &[Vec<u32>; 3]) {
+        let [board_area, status_area] =
+            Layout::vertical([Constraint::Min(3), Constraint::Length(1)]).areas(frame.area());
+        let areas: [_; 3] = Layout::horizontal([Constraint::Ratio(1, 3); 3]).areas(board_area);
+
+        for (i, area) in areas.into_iter().enumerate() {
+            let title = format!(" {} ({}) ", COLUMNS[i].0, columns[i].len());
+            let mut block = Block::bordered().title(title);
+            let mut highlight = Style::new();
+            if i == self.column {
+                block = block.border_style(Style::new().fg(Color::Cyan));
+                highlight = highlight.add_modifier(Modifier::REVERSED);
+            }
+
+            let items: Vec<ListItem> = columns[i]
+                .iter()
+                .filter_map(|&id| db.find(id))
+                .map(card)
+                .collect();
+            let list = List::new(items).block(block).highlight_style(highlight);
+            frame.render_stateful_widget(list, area, &mut self.lists[i]);
+        }
+
+        frame.render_widget(Paragraph::new(Line::from(HELP).dim()), status_area);
+    }
+}
+
+/// Ids of the todos in each column, the most important first.
+fn cards(db: &Database) -> [Vec<u32>; 3] {
+    let mut todos: Vec<&Todo> = db.todos.iter().collect();
+    todos.sort_by_key(|t| (!t.pinned, Reverse(t.priority), t.id));
+
+    let mut columns: [Vec<u32>; 3] = Default::default();
+    for todo in todos {
+        columns[column_of(todo.status)].push(todo.id);
+    }
+
+    columns
+}
+
+fn card(todo: &Todo) -> ListItem<'static> {
+    let mut text = format!("{}: {}", todo.id, todo.text);
+    for tag in &todo.tags {
+        text.push_str(&format!(" +{}", tag));
+    }
+
+    let style = match todo.status {
+        Status::Blocked => Style::new().fg(Color::Red),
+        Status::Cancelled => Style::new().dim().crossed_out(),
+        _ => Style::new(),
+    };
+    ListItem::new(text).style(style)
+}
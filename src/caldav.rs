@@ -0,0 +1,393 @@
+//! Two-way sync with a task list on a CalDAV server, like Nextcloud Tasks or
+//! Radicale, for `todo sync caldav`. Every todo is stored as a calendar
+//! object with a single VTODO.
+
+use crate::config::CaldavConfig;
+use crate::database::Database;
+use crate::error::TodoError;
+use crate::ical::{self, Vtodo};
+use crate::journal::Operation;
+use crate::output;
+use crate::todo::{self, Status, Todo};
+use base64::Engine;
+use chrono::prelude::*;
+use regex::Regex;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Asks for the ETag and data of every calendar object with a VTODO.
+const QUERY: &str = r#"<?xml version="1.0" encoding="utf-8"?>
+<c:calendar-query xmlns:d="DAV:" xmlns:c="urn:ietf:params:xml:ns:caldav">
+  <d:prop><d:getetag/><c:calendar-data/></d:prop>
+  <c:filter>
+    <c:comp-filter name="VCALENDAR"><c:comp-filter name="VTODO"/></c:comp-filter>
+  </c:filter>
+</c:calendar-query>"#;
+
+/// A calendar object on the server.
+struct Resource {
+    href: String,
+    etag: String,
+    vtodo: Vtodo,
+}
+
+/// Where a synced todo is stored, as of the last sync.
+#[derive(Clone, Serialize, Deserialize)]
+struct Synced {
+    href: String,
+    uid: String,
+    /// Not every server reports the ETag of a new object
+    etag: Option<String>,
+}
+
+/// What the last sync left behind, kept next to the database as
+/// `<db>.caldav.json`.
+#[derive(Default, Serialize, Deserialize)]
+struct SyncState {
+    synced_at: Option<DateTime<Local>>,
+    /// By the uuid of the todo
+    resources: HashMap<Uuid, Synced>,
+}
+
+impl SyncState {
+    fn path(db_path: &Path) -> PathBuf {
+        let mut path = db_path.as_os_str().to_owned();
+        path.push(".caldav.json");
+        PathBuf::from(path)
+    }
+
+    fn load(path: &Path) -> Result<Self, TodoError> {
+        match fs::read(path) {
+            Ok(contents) => serde_json::from_slice(&contents)
+                .map_err(|e| TodoError::parse(e.to_string()).in_file(path)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(SyncState::default()),
+            Err(e) => Err(TodoError::from(e).in_file(path)),
+        }
+    }
+
+    fn save(&self, path: &Path) -> io::Result<()> {
+        fs::write(path, serde_json::to_vec_pretty(self)?)
+    }
+}
+
+struct Client {
+    agent: ureq::Agent,
+    /// URL of the task list, ending in a slash
+    url: String,
+    authorization: Option<String>,
+}
+
+impl Client {
+    fn new(config: &CaldavConfig) -> Self {
+        let agent = ureq::Agent::config_builder()
+            .timeout_global(Some(Duration::from_secs(30)))
+            .allow_non_standard_methods(true)
+            .build()
+            .into();
+        let authorization = config.username.as_ref().map(|username| {
+            let credentials = format!("{}:{}", username, config.password.as_deref().unwrap_or(""));
+            format!(
+                "Basic {}",
+                base64::engine::general_purpose::STANDARD.encode(credentials)
+            )
+        });
+
+        Client {
+            agent,
+            url: format!("{}/", config.url.trim_end_matches('/')),
+            authorization,
+        }
+    }
+
+    fn request(&self, method: &str, href: &str) -> ureq::http::request::Builder {
+        let mut request = ureq::http::Request::builder()
+            .method(method)
+            .uri(self.resolve(href));
+        if let Some(authorization) = &self.authorization {
+            request = request.header("Authorization", authorization);
+        }
+
+        request
+    }
+
+    /// Makes an href from the server, usually an absolute path, into a URL.
+    fn resolve(&self, href: &str) -> String {
+        if href.contains("://") {
+            return href.to_string();
+        }
+        let Some(path) = href.strip_prefix('/') else {
+            return format!("{}{}", self.url, href);
+        };
+        let host_end = self.url.find("://").map_or(0, |i| i + 3);
+        let origin_end = self.url[host_end..]
+            .find('/')
+            .map_or(self.url.len(), |i| host_end + i);
+
+        format!("{}/{}", &self.url[..origin_end], path)
+    }
+
+    fn list(&self) -> Result<Vec<Resource>, TodoError> {
+        let request = self
+            .request("REPORT", &self.url)
+            .header("Depth", "1")
+            .header("Content-Type", "application/xml; charset=utf-8")
+            .body(QUERY)
+            .map_err(|e| TodoError::Remote(e.to_string()))?;
+        let body = self
+            .agent
+            .run(request)
+            .and_then(|mut response| response.body_mut().read_to_string())
+            .map_err(remote_error)?;
+
+        let mut resources = Vec::new();
+        for response in elements(&body, "response") {
+            let href = elements(response, "href").into_iter().next();
+            let etag = elements(response, "getetag").into_iter().next();
+            let data = elements(response, "calendar-data").into_iter().next();
+            let (Some(href), Some(etag), Some(data)) = (href, etag, data) else {
+                continue;
+            };
+
+            for vtodo in ical::parse(&xml_text(data)) {
+                resources.push(Resource {
+                    href: xml_text(href),
+                    etag: xml_text(etag),
+                    vtodo,
+                });
+            }
+        }
+
+        Ok(resources)
+    }
+
+    /// Stores the calendar object, unless it was changed on the server since
+    /// it had `etag`, or was created there when there is none. Returns the new
+    /// ETag, if the server tells it.
+    fn put(
+        &self,
+        href: &str,
+        calendar: String,
+        etag: Option<&str>,
+    ) -> Result<Option<String>, TodoError> {
+        let request = self
+            .request("PUT", href)
+            .header("Content-Type", "text/calendar; charset=utf-8");
+        let request = match etag {
+            Some(etag) => request.header("If-Match", etag),
+            None => request.header("If-None-Match", "*"),
+        };
+        let request = request
+            .body(calendar)
+            .map_err(|e| TodoError::Remote(e.to_string()))?;
+        let response = self.agent.run(request).map_err(remote_error)?;
+
+        Ok(response
+            .headers()
+            .get("ETag")
+            .and_then(|etag| etag.to_str().ok())
+            .map(String::from))
+    }
+
+    fn delete(&self, href: &str, etag: Option<&str>) -> Result<(), TodoError> {
+        let mut request = self.request("DELETE", href);
+        if let Some(etag) = etag {
+            request = request.header("If-Match", etag);
+        }
+        let request = request
+            .body(())
+            .map_err(|e| TodoError::Remote(e.to_string()))?;
+
+        match self.agent.run(request) {
+            Ok(_) | Err(ureq::Error::StatusCode(404)) => Ok(()),
+            Err(e) => Err(remote_error(e)),
+        }
+    }
+}
+
+fn remote_error(e: ureq::Error) -> TodoError {
+    TodoError::Remote(format!("CalDAV: {}", e))
+}
+
+/// The contents of the XML elements with the local name, whatever their
+/// namespace prefix.
+fn elements<'a>(xml: &'a str, name: &str) -> Vec<&'a str> {
+    let pattern = format!(
+        r"(?s)<(?:[\w-]+:)?{0}(?:\s[^>]*)?>(.*?)</(?:[\w-]+:)?{0}>",
+        regex::escape(name)
+    );
+    let re = Regex::new(&pattern).expect("the element pattern is valid");
+
+    re.captures_iter(xml)
+        .filter_map(|captures| captures.get(1))
+        .map(|content| content.as_str())
+        .collect()
+}
+
+/// Undoes the escaping of XML text, including CDATA sections.
+fn xml_text(text: &str) -> String {
+    let text = text.trim();
+    if let Some(cdata) = text
+        .strip_prefix("<![CDATA[")
+        .and_then(|rest| rest.strip_suffix("]]>"))
+    {
+        return cdata.to_string();
+    }
+
+    text.replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&#13;", "\r")
+        .replace("&#xD;", "\r")
+        .replace("&amp;", "&")
+}
+
+/// The uuid of the todo for a UID; UIDs that are not UUIDs always map to
+/// the same one.
+fn uuid_of(uid: &str) -> Uuid {
+    uid.parse()
+        .unwrap_or_else(|_| Uuid::new_v5(&Uuid::NAMESPACE_URL, uid.as_bytes()))
+}
+
+#[derive(Default)]
+struct Counts {
+    pushed: usize,
+    pulled: usize,
+}
+
+/// Stores the todos changed since the last sync on the server and takes over
+/// those changed there. When a todo changed on both sides, the newer change
+/// wins.
+pub fn sync(db: &mut Database, db_path: &Path, config: &CaldavConfig) -> Result<(), TodoError> {
+    let state_path = SyncState::path(db_path);
+    let mut state = SyncState::load(&state_path)?;
+    let client = Client::new(config);
+    let changed_since_sync =
+        |time: DateTime<Local>| state.synced_at.is_none_or(|synced_at| time > synced_at);
+
+    let mut remote: HashMap<Uuid, Resource> = client
+        .list()?
+        .into_iter()
+        .map(|resource| (uuid_of(&resource.vtodo.uid), resource))
+        .collect();
+    let mut counts = Counts::default();
+    let mut resources = HashMap::new();
+
+    for todo in db.todos.clone() {
+        let uuid = todo.uuid;
+        let known = state.resources.get(&todo.uuid);
+        let local_changed = known.is_none() || changed_since_sync(todo.updated_at);
+
+        let synced = match (remote.remove(&todo.uuid), known) {
+            (Some(resource), _) => {
+                let remote_changed = known.is_none_or(|k| k.etag.as_ref() != Some(&resource.etag));
+                let remote_newer = resource.vtodo.todo.updated_at > todo.updated_at;
+                let mut synced = Synced {
+                    href: resource.href.clone(),
+                    uid: resource.vtodo.uid.clone(),
+                    etag: Some(resource.etag.clone()),
+                };
+
+                if remote_changed && (!local_changed || remote_newer) {
+                    db.apply(Operation::Update {
+                        todo: pulled(todo, &resource.vtodo.todo, db.max_text_length),
+                    })?;
+                    counts.pulled += 1;
+                } else if local_changed {
+                    let calendar = ical::calendar(&todo, &synced.uid);
+                    synced.etag = client.put(&synced.href, calendar, Some(&resource.etag))?;
+                    counts.pushed += 1;
+                }
+                Some(synced)
+            }
+            // Deleted on the server; kept if it changed here since
+            (None, Some(_)) if !changed_since_sync(todo.updated_at) => {
+                db.apply(Operation::delete(todo.id))?;
+                counts.pulled += 1;
+                None
+            }
+            (None, known) => {
+                let uid = known.map_or_else(|| todo.uuid.to_string(), |k| k.uid.clone());
+                let href = format!("{}{}.ics", client.url, todo.uuid);
+                let etag = client.put(&href, ical::calendar(&todo, &uid), None)?;
+                counts.pushed += 1;
+                Some(Synced { href, uid, etag })
+            }
+        };
+        resources.extend(synced.map(|synced| (uuid, synced)));
+    }
+
+    for (uuid, resource) in remote {
+        // Trashed or archived here since the last sync
+        if state.resources.contains_key(&uuid) {
+            client.delete(&resource.href, Some(&resource.etag))?;
+            counts.pushed += 1;
+            continue;
+        }
+        if db.trash.iter().any(|t| t.uuid == uuid) {
+            continue;
+        }
+
+        let mut todo = db.new_todo(&task_text(&resource.vtodo.todo, db.max_text_length))?;
+        todo.uuid = uuid;
+        todo.created_at = resource.vtodo.todo.created_at;
+        let todo = pulled(todo, &resource.vtodo.todo, db.max_text_length);
+        resources.insert(
+            uuid,
+            Synced {
+                href: resource.href,
+                uid: resource.vtodo.uid,
+                etag: Some(resource.etag),
+            },
+        );
+        db.apply(Operation::Add { todo })?;
+        counts.pulled += 1;
+    }
+
+    state.resources = resources;
+    state.synced_at = Some(Local::now());
+    state
+        .save(&state_path)
+        .map_err(|e| TodoError::from(e).in_file(&state_path))?;
+    output::status(format!(
+        "Stored {} change(s) on the server and took over {}",
+        counts.pushed, counts.pulled
+    ));
+
+    Ok(db.save()?)
+}
+
+/// The summary of the VTODO, shortened to what a todo can hold.
+fn task_text(remote: &Todo, max_text_length: usize) -> String {
+    let text: String = remote.text.chars().take(max_text_length).collect();
+
+    todo::sanitize_text(&text, max_text_length).unwrap_or_else(|_| "(no text)".to_string())
+}
+
+/// The todo with what the VTODO says about it. Blocked has no equivalent in
+/// iCalendar, so blocked todos stay blocked while the VTODO needs action.
+fn pulled(mut todo: Todo, remote: &Todo, max_text_length: usize) -> Todo {
+    todo.text = task_text(remote, max_text_length);
+    todo.notes = remote.notes.clone();
+    todo.priority = remote.priority;
+    todo.tags = remote.tags.clone();
+    todo.due_at = remote.due_at;
+    todo.scheduled_at = remote.scheduled_at;
+    if !(todo.status == Status::Blocked && remote.status == Status::Open) {
+        todo.status = remote.status;
+    }
+    todo.completed_at = match todo.status {
+        Status::Done => remote
+            .completed_at
+            .or(todo.completed_at)
+            .or(Some(Local::now())),
+        _ => None,
+    };
+
+    todo
+}
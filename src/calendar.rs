@@ -0,0 +1,76 @@
+//! Month grid with the number of todos due per day, for `todo calendar`.
+
+use crate::todo::Todo;
+use chrono::prelude::*;
+use colored::Colorize;
+use std::collections::HashMap;
+use std::io::{self, Write};
+
+/// Width of a day in the grid: the day, a dot, two digits and a space.
+const CELL_WIDTH: usize = 6;
+
+/// Parses a month given as `YYYY-MM` into its first day.
+pub fn parse_month(s: &str) -> Result<NaiveDate, String> {
+    NaiveDate::parse_from_str(&format!("{}-01", s.trim()), "%Y-%m-%d")
+        .map_err(|_| format!("'{}' is not a month, expected YYYY-MM", s))
+}
+
+/// Writes the month starting at `first` with weeks from Monday to Sunday.
+/// Days with open todos due show their number, like `15·2`; past ones are
+/// red and today is underlined.
+pub fn write_month(
+    out: &mut dyn Write,
+    todos: &[Todo],
+    first: NaiveDate,
+    today: NaiveDate,
+) -> io::Result<()> {
+    let mut due: HashMap<NaiveDate, usize> = HashMap::new();
+    for todo in todos.iter().filter(|t| !t.status.is_closed()) {
+        if let Some(due_at) = todo.due_at {
+            *due.entry(due_at.date_naive()).or_default() += 1;
+        }
+    }
+
+    let title = first.format("%B %Y").to_string();
+    let indent = (CELL_WIDTH * 7 - 4).saturating_sub(title.len()) / 2;
+    writeln!(out, "{}{}", " ".repeat(indent), title.bold())?;
+    writeln!(out, "Mo    Tu    We    Th    Fr    Sa    Su")?;
+
+    // Padding is written before a day so that lines do not end in spaces
+    let offset = first.weekday().num_days_from_monday() as usize;
+    let mut padding = offset * CELL_WIDTH;
+    let mut line_open = false;
+    for day in first.iter_days().take_while(|d| d.month() == first.month()) {
+        let mut cell = format!("{:>2}", day.day());
+        if let Some(count) = due.get(&day) {
+            cell.push_str(&format!("·{}", count));
+        }
+        let width = cell.chars().count();
+
+        let mut styled = cell.normal();
+        if due.contains_key(&day) {
+            styled = if day < today {
+                styled.red()
+            } else {
+                styled.yellow()
+            };
+        }
+        if day == today {
+            styled = styled.bold().underline();
+        }
+        write!(out, "{}{}", " ".repeat(padding), styled)?;
+
+        line_open = day.weekday() != Weekday::Sun;
+        if line_open {
+            padding = CELL_WIDTH.saturating_sub(width).max(1);
+        } else {
+            writeln!(out)?;
+            padding = 0;
+        }
+    }
+    if line_open {
+        writeln!(out)?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,977 @@
+use crate::archive;
+use crate::calendar;
+use crate::config::GithubConfig;
+use crate::database::Database;
+use crate::dates;
+use crate::editor;
+use crate::error::TodoError;
+use crate::export::{self, ExportFormat};
+use crate::filter::{self, Filter};
+use crate::github;
+use crate::group::{Group, GroupBy};
+use crate::journal::{Journal, Operation};
+use crate::output::{self, Column, Format, Layout};
+use crate::prompt;
+use crate::query;
+use crate::render::Renderer;
+use crate::report;
+use crate::selection::{self, IdSelector};
+use crate::storage::Backend;
+use crate::todo::{self, Priority, Status, Todo};
+use chrono::prelude::*;
+use chrono::Duration;
+use std::fs::{self, File};
+use std::io::{self, BufRead, BufWriter, IsTerminal, Write};
+use std::path::Path;
+use std::str::FromStr;
+use std::thread;
+use std::time;
+
+/// Creates a new database at `path`, e.g. a local one for a project.
+pub fn init(backend: Backend, path: &Path) -> Result<(), TodoError> {
+    if path.exists() {
+        let message = format!("{} already exists", path.display());
+        return Err(io::Error::new(io::ErrorKind::AlreadyExists, message).into());
+    }
+
+    let mut db = Database::open(backend, path, false, true, false)
+        .map_err(|e| TodoError::from(e).in_file(path))?;
+    db.save()?;
+    output::status(format!("Created database {}", path.display()));
+
+    Ok(())
+}
+
+pub fn add(
+    db: &mut Database,
+    text: &str,
+    due_at: Option<DateTime<Local>>,
+    scheduled_at: Option<DateTime<Local>>,
+    priority: Priority,
+    parent: Option<u32>,
+) -> Result<(), TodoError> {
+    if let Some(parent) = parent {
+        db.find(parent).ok_or(TodoError::NotFound(parent))?;
+    }
+
+    let mut todo = db.new_todo(text)?;
+    todo.due_at = due_at;
+    todo.scheduled_at = scheduled_at;
+    todo.priority = priority;
+    todo.parent = parent;
+    output::status(format!("Created todo {}", todo.id));
+
+    db.apply(Operation::Add { todo })?;
+    Ok(db.save()?)
+}
+
+/// Creates an open todo with the text, tags and priority of another one, e.g.
+/// for a chore that comes up again.
+pub fn clone(db: &mut Database, id: u32) -> Result<(), TodoError> {
+    let original = db.find(id).ok_or(TodoError::NotFound(id))?.clone();
+
+    let mut todo = db.new_todo(&original.text)?;
+    todo.tags = original.tags;
+    todo.priority = original.priority;
+    todo.list = original.list;
+    output::status(format!("Created todo {} from {}", todo.id, id));
+
+    db.apply(Operation::Add { todo })?;
+    Ok(db.save()?)
+}
+
+/// Creates one todo per non-empty line on stdin, e.g. for
+/// `cat tasks.txt | todo add --stdin`.
+pub fn add_from_stdin(db: &mut Database) -> Result<(), TodoError> {
+    for line in io::stdin().lock().lines() {
+        add_line(db, &line?)?;
+    }
+
+    Ok(db.save()?)
+}
+
+/// Creates one todo per non-empty line of the text in the clipboard, e.g. a
+/// list copied from an email. Bullets like `-` or `*` are left out.
+pub fn add_from_clipboard(db: &mut Database) -> Result<(), TodoError> {
+    let text = arboard::Clipboard::new()
+        .and_then(|mut clipboard| clipboard.get_text())
+        .map_err(|e| io::Error::other(format!("could not read the clipboard: {}", e)))?;
+
+    for line in text.lines() {
+        let line = line.trim_start();
+        let line = ["- [ ] ", "- ", "* ", "• "]
+            .iter()
+            .find_map(|bullet| line.strip_prefix(bullet))
+            .unwrap_or(line);
+        add_line(db, line)?;
+    }
+
+    Ok(db.save()?)
+}
+
+fn add_line(db: &mut Database, line: &str) -> Result<(), TodoError> {
+    let text = line.trim();
+    if text.is_empty() {
+        return Ok(());
+    }
+
+    let todo = db.new_todo(text)?;
+    output::status(format!("Created todo {}", todo.id));
+
+    Ok(db.apply(Operation::Add { todo })?)
+}
+
+pub fn list(
+    db: &Database,
+    renderer: &Renderer,
+    filter: &Filter,
+    format: Format,
+    layout: &Layout,
+) -> Result<(), TodoError> {
+    Ok(renderer.print_todos(db.active_todos(), filter, format, layout)?)
+}
+
+/// Lists the todos like `list` and again whenever the database changes, or
+/// after a minute for the relative dates, until interrupted. The database is
+/// only open, and locked, while listing, so other instances can change it.
+pub fn watch(
+    backend: Backend,
+    path: &Path,
+    lenient: bool,
+    renderer: &Renderer,
+    filter: &Filter,
+    format: Format,
+    layout: &Layout,
+) -> Result<(), TodoError> {
+    const POLL_INTERVAL: time::Duration = time::Duration::from_millis(500);
+    const REFRESH_INTERVAL: time::Duration = time::Duration::from_secs(60);
+
+    let journal = Journal::for_database(path);
+    let modified =
+        || [path, journal.path()].map(|path| fs::metadata(path).and_then(|m| m.modified()).ok());
+    let mut last_modified = None;
+    let mut listed_at = time::Instant::now();
+
+    loop {
+        if last_modified != Some(modified()) || listed_at.elapsed() >= REFRESH_INTERVAL {
+            let db = Database::open(backend, path, true, true, lenient)
+                .map_err(|e| TodoError::from(e).in_file(path))?;
+            if io::stdout().is_terminal() {
+                // Clear the screen and move to its top
+                print!("\x1b[2J\x1b[H");
+            }
+            renderer.print_todos(db.active_todos(), filter, format, layout)?;
+            io::stdout().flush()?;
+            drop(db);
+
+            // Opening can write the database itself, e.g. to fold in the
+            // journal
+            last_modified = Some(modified());
+            listed_at = time::Instant::now();
+        }
+
+        thread::sleep(POLL_INTERVAL);
+    }
+}
+
+/// Lists the todos containing `query` in their text or notes, with the
+/// matches in the text highlighted.
+pub fn search(
+    db: &Database,
+    renderer: &Renderer,
+    layout: &Layout,
+    query: &str,
+) -> Result<(), TodoError> {
+    let layout = Layout {
+        highlight: Some(query.to_lowercase()),
+        ..layout.clone()
+    };
+
+    let candidates = db.search_candidates(query);
+    Ok(renderer.print_todos(candidates, &Filter::search(query), Format::Plain, &layout)?)
+}
+
+/// Completes the selected todos, or all matching `filter` after asking for
+/// confirmation unless `force` is set. With `github`, the GitHub issues
+/// linked to them are closed as well.
+pub fn done(
+    db: &mut Database,
+    selectors: &[IdSelector],
+    filter: Option<&Filter>,
+    dry_run: bool,
+    force: bool,
+    github: Option<&GithubConfig>,
+) -> Result<(), TodoError> {
+    let ids = match filter {
+        Some(filter) => matching_ids(db, filter),
+        None => selection::resolve_ids(db, selectors)?,
+    };
+    if dry_run {
+        print_dry_run(db, &ids, "complete");
+        return Ok(());
+    }
+    if filter.is_some() && !force && !confirm_all(db, &ids, "Complete")? {
+        output::status("Nothing completed");
+        return Ok(());
+    }
+
+    for &id in &ids {
+        db.apply(Operation::complete(id))?;
+    }
+    db.save()?;
+
+    match github {
+        Some(config) => github::close_issues(db, config, &ids),
+        None => Ok(()),
+    }
+}
+
+/// Sets done or cancelled todos back to open, e.g. when completed by mistake.
+pub fn reopen(db: &mut Database, selectors: &[IdSelector]) -> Result<(), TodoError> {
+    for id in selection::resolve_ids(db, selectors)? {
+        let mut todo = db.find(id).ok_or(TodoError::NotFound(id))?.clone();
+        if !todo.status.is_closed() {
+            output::status(format!("Todo {} is already {}", id, todo.status.as_str()));
+            continue;
+        }
+
+        todo.set_status(Status::Open, Local::now());
+        db.apply(Operation::Update { todo })?;
+    }
+
+    Ok(db.save()?)
+}
+
+/// Completes open todos and reopens closed ones, e.g. for a key binding.
+pub fn toggle(db: &mut Database, selectors: &[IdSelector]) -> Result<(), TodoError> {
+    for id in selection::resolve_ids(db, selectors)? {
+        let todo = db.find(id).ok_or(TodoError::NotFound(id))?;
+        db.apply(Operation::toggle(todo))?;
+    }
+
+    Ok(db.save()?)
+}
+
+/// Deletes the selected todos, or all matching `filter`, after asking for
+/// confirmation unless `force` is set.
+pub fn rm(
+    db: &mut Database,
+    selectors: &[IdSelector],
+    filter: Option<&Filter>,
+    dry_run: bool,
+    force: bool,
+) -> Result<(), TodoError> {
+    let ids = match filter {
+        Some(filter) => matching_ids(db, filter),
+        None => selection::resolve_ids(db, selectors)?,
+    };
+    if dry_run {
+        print_dry_run(db, &ids, "delete");
+        return Ok(());
+    }
+    if !force && !confirm_all(db, &ids, "Delete")? {
+        output::status("Nothing deleted");
+        return Ok(());
+    }
+
+    if !ids.is_empty() {
+        output::status(format!(
+            "Moved {} todo(s) to the trash, `todo restore <id>` brings them back",
+            ids.len()
+        ));
+    }
+    for id in ids {
+        db.apply(Operation::delete(id))?;
+    }
+
+    Ok(db.save()?)
+}
+
+/// Moves the todos closed longer than `older_than` ago from the database to
+/// its archive.
+pub fn archive(db: &mut Database, db_path: &Path, older_than: Duration) -> Result<(), TodoError> {
+    let cutoff = dates::ago(older_than).map_err(TodoError::parse)?;
+    let todos: Vec<Todo> = db
+        .todos
+        .iter()
+        .filter(|t| t.status.is_closed() && t.completed_at.unwrap_or(t.updated_at) < cutoff)
+        .cloned()
+        .collect();
+    if todos.is_empty() {
+        output::status("Nothing to archive");
+        return Ok(());
+    }
+
+    // Written first, so an interruption cannot lose the todos
+    archive::append(db_path, &todos)?;
+    for todo in &todos {
+        db.apply(Operation::Archive { id: todo.id })?;
+    }
+    output::status(format!(
+        "Archived {} todo(s) to {}",
+        todos.len(),
+        archive::path(db_path).display()
+    ));
+
+    Ok(db.save()?)
+}
+
+/// Lists the archived todos of the database at `db_path`.
+pub fn list_archived(
+    db_path: &Path,
+    renderer: &Renderer,
+    filter: &Filter,
+    format: Format,
+    layout: &Layout,
+) -> Result<(), TodoError> {
+    let todos = archive::load(db_path)?;
+
+    Ok(renderer.print_todos(&todos, filter, format, layout)?)
+}
+
+/// Shows a month with the number of open todos due per day, by default the
+/// current one. With `day` the todos due on that day of the month are listed
+/// below it.
+pub fn calendar(
+    db: &Database,
+    renderer: &Renderer,
+    layout: &Layout,
+    month: Option<NaiveDate>,
+    day: Option<u32>,
+) -> Result<(), TodoError> {
+    let today = Local::now().date_naive();
+    let first = month.unwrap_or_else(|| today.with_day(1).unwrap_or(today));
+    let todos: Vec<Todo> = db.active_todos().cloned().collect();
+    calendar::write_month(&mut io::stdout().lock(), &todos, first, today)?;
+
+    let Some(day) = day else {
+        return Ok(());
+    };
+    let date = first
+        .with_day(day)
+        .ok_or_else(|| TodoError::parse(format!("{} has no day {}", first.format("%B %Y"), day)))?;
+    let filter = Filter {
+        expression: Some(query::parse(&format!("due:{}", date)).map_err(TodoError::parse)?),
+        ..Filter::default()
+    };
+    println!();
+
+    Ok(renderer.print_todos(db.active_todos(), &filter, Format::Plain, layout)?)
+}
+
+/// Shows what needs attention today: the overdue todos, those due today,
+/// those scheduled or snoozed until today and the pinned ones, each open todo
+/// in the first of these sections it belongs to and the more urgent ones
+/// first.
+pub fn today(db: &Database, renderer: &Renderer, layout: &Layout) -> Result<(), TodoError> {
+    let now = Local::now();
+    let today = now.date_naive();
+    let is_today = |time: Option<DateTime<Local>>| time.is_some_and(|t| t.date_naive() == today);
+
+    let mut todos: Vec<&Todo> = db
+        .active_todos()
+        .filter(|t| !t.status.is_closed())
+        // Todos deferred beyond today are out of sight until then
+        .filter(|t| !t.is_snoozed(now) || is_today(t.snoozed_until))
+        .filter(|t| !t.is_scheduled_later(now) || is_today(t.scheduled_at))
+        .collect();
+    todos.sort_by(|a, b| output::compare_urgency(a, b, &layout.urgency, now));
+
+    let section = |t: &Todo| {
+        if t.is_overdue(now) {
+            Some("Overdue")
+        } else if is_today(t.due_at) {
+            Some("Due today")
+        } else if is_today(t.scheduled_at) || is_today(t.snoozed_until) {
+            Some("Scheduled today")
+        } else if t.pinned {
+            Some("Pinned")
+        } else {
+            None
+        }
+    };
+    let groups: Vec<Group> = ["Overdue", "Due today", "Scheduled today", "Pinned"]
+        .into_iter()
+        .map(|heading| {
+            let todos = todos
+                .iter()
+                .copied()
+                .filter(|t| section(t) == Some(heading));
+            (heading.to_string(), todos.collect::<Vec<_>>())
+        })
+        .filter(|(_, todos)| !todos.is_empty())
+        .collect();
+    if groups.is_empty() {
+        output::status("Nothing to do today");
+        return Ok(());
+    }
+
+    let layout = Layout {
+        columns: Some(vec![
+            Column::Id,
+            Column::Priority,
+            Column::Due,
+            Column::Text,
+        ]),
+        dates: layout.dates,
+        urgency: layout.urgency.clone(),
+        ..Layout::default()
+    };
+
+    Ok(renderer.print_groups(&groups, &layout)?)
+}
+
+/// Shows the seven days from `from` on, by default today, each with the open
+/// todos due or scheduled on it.
+pub fn week(
+    db: &Database,
+    renderer: &Renderer,
+    layout: &Layout,
+    from: Option<DateTime<Local>>,
+) -> Result<(), TodoError> {
+    let now = Local::now();
+    let first = from.unwrap_or(now).date_naive();
+    let mut todos: Vec<&Todo> = db
+        .active_todos()
+        .filter(|t| !t.status.is_closed())
+        .collect();
+    todos.sort_by(|a, b| output::compare_urgency(a, b, &layout.urgency, now));
+
+    let on = |day: NaiveDate, time: Option<DateTime<Local>>| {
+        time.is_some_and(|time| time.date_naive() == day)
+    };
+    let groups: Vec<Group> = first
+        .iter_days()
+        .take(7)
+        .map(|day| {
+            let todos = todos
+                .iter()
+                .copied()
+                .filter(|t| on(day, t.due_at) || on(day, t.scheduled_at));
+            (day.format("%A, %d.%m.%Y").to_string(), todos.collect())
+        })
+        .collect();
+    if groups.iter().all(|(_, todos)| todos.is_empty()) {
+        let last = first + Duration::days(6);
+        output::status(format!(
+            "Nothing is due or scheduled from {} to {}",
+            first.format("%d.%m.%Y"),
+            last.format("%d.%m.%Y")
+        ));
+        return Ok(());
+    }
+
+    let layout = Layout {
+        columns: Some(vec![
+            Column::Id,
+            Column::Priority,
+            Column::Due,
+            Column::Text,
+            Column::Status,
+        ]),
+        dates: layout.dates,
+        urgency: layout.urgency.clone(),
+        ..Layout::default()
+    };
+
+    Ok(renderer.print_groups(&groups, &layout)?)
+}
+
+/// Lists the todos in the trash, the most recently deleted first.
+pub fn trash(db: &Database) -> Result<(), TodoError> {
+    if db.trash.is_empty() {
+        output::status("The trash is empty");
+        return Ok(());
+    }
+
+    let now = Local::now();
+    let mut todos: Vec<&Todo> = db.trash.iter().collect();
+    todos.sort_by_key(|t| std::cmp::Reverse(t.deleted_at));
+    for todo in todos {
+        let deleted_at = todo.deleted_at.unwrap_or(now);
+        println!(
+            "{}: {} (deleted {})",
+            todo.id,
+            todo.text,
+            dates::format_relative(deleted_at, now)
+        );
+    }
+
+    Ok(())
+}
+
+/// Moves todos out of the trash again.
+pub fn restore(db: &mut Database, ids: &[u32]) -> Result<(), TodoError> {
+    for &id in ids {
+        if !db.trash.iter().any(|t| t.id == id) {
+            return Err(TodoError::NotFound(id));
+        }
+        if db.find(id).is_some() {
+            return Err(TodoError::parse(format!(
+                "todo {} exists already, run `todo repair` first",
+                id
+            )));
+        }
+
+        db.apply(Operation::Restore { id })?;
+        output::status(format!("Restored todo {}", id));
+    }
+
+    Ok(db.save()?)
+}
+
+/// Deletes the todos in the trash for good, or only those deleted longer than
+/// `older_than` ago.
+pub fn empty_trash(
+    db: &mut Database,
+    older_than: Option<Duration>,
+    force: bool,
+) -> Result<(), TodoError> {
+    let cutoff = older_than
+        .map(dates::ago)
+        .transpose()
+        .map_err(TodoError::parse)?;
+    let ids: Vec<u32> = db
+        .trash
+        .iter()
+        .filter(|t| match (cutoff, t.deleted_at) {
+            (Some(cutoff), Some(deleted_at)) => deleted_at < cutoff,
+            _ => true,
+        })
+        .map(|t| t.id)
+        .collect();
+    if ids.is_empty() {
+        output::status("Nothing to delete");
+        return Ok(());
+    }
+
+    let question = format!("Delete {} todo(s) in the trash for good?", ids.len());
+    if !force && !prompt::confirm(&question)? {
+        output::status("Nothing deleted");
+        return Ok(());
+    }
+    for id in ids {
+        db.apply(Operation::Purge { id })?;
+    }
+
+    Ok(db.save()?)
+}
+
+/// Sets or, with `None`, clears the due date of a todo.
+pub fn due(db: &mut Database, id: u32, due_at: Option<DateTime<Local>>) -> Result<(), TodoError> {
+    let mut todo = db.find(id).ok_or(TodoError::NotFound(id))?.clone();
+    todo.due_at = due_at;
+    db.apply(Operation::Update { todo })?;
+
+    Ok(db.save()?)
+}
+
+pub fn priority(db: &mut Database, id: u32, priority: Priority) -> Result<(), TodoError> {
+    let mut todo = db.find(id).ok_or(TodoError::NotFound(id))?.clone();
+    todo.priority = priority;
+    db.apply(Operation::Update { todo })?;
+
+    Ok(db.save()?)
+}
+
+pub fn status(db: &mut Database, id: u32, status: Status) -> Result<(), TodoError> {
+    let mut todo = db.find(id).ok_or(TodoError::NotFound(id))?.clone();
+    todo.set_status(status, Local::now());
+    db.apply(Operation::Update { todo })?;
+
+    Ok(db.save()?)
+}
+
+pub fn summary(db: &Database) -> Result<(), TodoError> {
+    let todos: Vec<&Todo> = db.active_todos().collect();
+    println!("{}", output::summary(&todos, Local::now()));
+
+    Ok(())
+}
+
+/// Prints only the number of todos matching `filter`, e.g. for a status bar.
+pub fn count(db: &Database, filter: &Filter) -> Result<(), TodoError> {
+    println!(
+        "{}",
+        db.active_todos().filter(|t| filter.matches(t)).count()
+    );
+
+    Ok(())
+}
+
+/// Prints a line about the open todos due within `within`, overdue ones
+/// included, and returns whether there are any. Snoozed and scheduled todos
+/// wait until they are back.
+pub fn check(db: &Database, within: Duration) -> Result<bool, TodoError> {
+    let now = Local::now();
+    let mut due: Vec<&Todo> = db
+        .active_todos()
+        .filter(|t| !t.status.is_closed() && !t.is_deferred(now))
+        .filter(|t| t.due_at.is_some_and(|due_at| due_at <= now + within))
+        .collect();
+    due.sort_by_key(|t| t.due_at);
+
+    let Some((next, others)) = due.split_first() else {
+        output::status("Nothing is due soon");
+        return Ok(false);
+    };
+    let due_at = next.due_at.unwrap_or(now);
+    let mut summary = format!(
+        "{} todo(s) due soon: {} (due {})",
+        due.len(),
+        next.text,
+        dates::format_relative(due_at, now)
+    );
+    if !others.is_empty() {
+        summary.push_str(&format!(" and {} more", others.len()));
+    }
+    output::status(summary);
+
+    Ok(true)
+}
+
+/// Hides the todo from the listings until `until`, or shows it again without.
+pub fn snooze(db: &mut Database, id: u32, until: Option<DateTime<Local>>) -> Result<(), TodoError> {
+    let mut todo = db.find(id).ok_or(TodoError::NotFound(id))?.clone();
+    if let Some(until) = until {
+        if until <= Local::now() {
+            return Err(TodoError::parse(format!(
+                "{} has already passed, snooze until a later time",
+                until.format("%d.%m.%Y %H:%M")
+            )));
+        }
+        output::status(format!(
+            "Snoozed todo {} until {}",
+            id,
+            until.format("%d.%m.%Y %H:%M")
+        ));
+    }
+    todo.snoozed_until = until;
+    db.apply(Operation::Update { todo })?;
+
+    Ok(db.save()?)
+}
+
+/// Sets when work on the todo can start, or removes the start date without
+/// `start`.
+pub fn schedule(
+    db: &mut Database,
+    id: u32,
+    start: Option<DateTime<Local>>,
+) -> Result<(), TodoError> {
+    let mut todo = db.find(id).ok_or(TodoError::NotFound(id))?.clone();
+    if let Some(start) = start {
+        output::status(format!(
+            "Scheduled todo {} for {}",
+            id,
+            start.format("%d.%m.%Y %H:%M")
+        ));
+    }
+    todo.scheduled_at = start;
+    db.apply(Operation::Update { todo })?;
+
+    Ok(db.save()?)
+}
+
+pub fn move_to_list(db: &mut Database, id: u32, list: Option<String>) -> Result<(), TodoError> {
+    let mut todo = db.find(id).ok_or(TodoError::NotFound(id))?.clone();
+    match &list {
+        Some(list) => output::status(format!("Moved todo {} to the list {}", id, list)),
+        None => output::status(format!("Took todo {} off its list", id)),
+    }
+    todo.list = list;
+    db.apply(Operation::Update { todo })?;
+
+    Ok(db.save()?)
+}
+
+pub fn pin(db: &mut Database, id: u32, pinned: bool) -> Result<(), TodoError> {
+    let mut todo = db.find(id).ok_or(TodoError::NotFound(id))?.clone();
+    todo.pinned = pinned;
+    db.apply(Operation::Update { todo })?;
+
+    Ok(db.save()?)
+}
+
+/// Refuses parents that are the todo itself or one of its subtasks, which
+/// would leave them out of the tree.
+pub fn set_parent(db: &mut Database, id: u32, parent: Option<u32>) -> Result<(), TodoError> {
+    let mut todo = db.find(id).ok_or(TodoError::NotFound(id))?.clone();
+
+    // Bounded in case the todos already form a cycle
+    let mut ancestor = parent;
+    for _ in 0..db.todos.len() {
+        let Some(ancestor_id) = ancestor else {
+            break;
+        };
+        if ancestor_id == id {
+            return Err(TodoError::parse(format!(
+                "todo {} cannot be a subtask of itself or its subtasks",
+                id
+            )));
+        }
+        ancestor = db
+            .find(ancestor_id)
+            .ok_or(TodoError::NotFound(ancestor_id))?
+            .parent;
+    }
+
+    todo.parent = parent;
+    db.apply(Operation::Update { todo })?;
+
+    Ok(db.save()?)
+}
+
+/// Merges the todo `duplicate` into `id` and deletes it; its subtasks become
+/// subtasks of `id`.
+pub fn merge(db: &mut Database, id: u32, duplicate: u32) -> Result<(), TodoError> {
+    if id == duplicate {
+        return Err(TodoError::parse("cannot merge a todo with itself"));
+    }
+    let mut todo = db.find(id).ok_or(TodoError::NotFound(id))?.clone();
+    let other = db
+        .find(duplicate)
+        .ok_or(TodoError::NotFound(duplicate))?
+        .clone();
+
+    todo.merge(&other);
+    if todo.parent == Some(duplicate) {
+        todo.parent = other.parent;
+    }
+    db.apply(Operation::Update { todo })?;
+
+    let subtasks: Vec<Todo> = db
+        .todos
+        .iter()
+        .filter(|t| t.parent == Some(duplicate))
+        .cloned()
+        .collect();
+    for mut subtask in subtasks {
+        subtask.parent = Some(id);
+        db.apply(Operation::Update { todo: subtask })?;
+    }
+
+    db.apply(Operation::delete(duplicate))?;
+    output::status(format!("Merged todo {} into {}", duplicate, id));
+
+    Ok(db.save()?)
+}
+
+/// `+tag` to add a tag to a todo or `-tag` to remove it.
+#[derive(Debug, Clone)]
+pub enum TagChange {
+    Add(String),
+    Remove(String),
+}
+
+impl TagChange {
+    pub fn apply(&self, todo: &mut Todo) {
+        match self {
+            TagChange::Add(tag) => todo.add_tag(tag),
+            TagChange::Remove(tag) => todo.remove_tag(tag),
+        }
+    }
+}
+
+impl FromStr for TagChange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if let Some(tag) = s.strip_prefix('-') {
+            return filter::parse_tag(&format!("+{}", tag)).map(TagChange::Remove);
+        }
+
+        filter::parse_tag(s)
+            .map(TagChange::Add)
+            .map_err(|_| format!("'{}' is not a tag change, expected +tag or -tag", s))
+    }
+}
+
+/// `key:value` to set a custom field of a todo or `key:` to remove it.
+#[derive(Debug, Clone)]
+pub struct FieldChange {
+    key: String,
+    value: Option<String>,
+}
+
+impl FromStr for FieldChange {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, value) = filter::parse_field(s)?;
+
+        Ok(FieldChange {
+            key,
+            value: Some(value).filter(|v| !v.is_empty()),
+        })
+    }
+}
+
+pub fn field(db: &mut Database, id: u32, changes: &[FieldChange]) -> Result<(), TodoError> {
+    let mut todo = db.find(id).ok_or(TodoError::NotFound(id))?.clone();
+    for change in changes {
+        match &change.value {
+            Some(value) => todo.fields.insert(change.key.clone(), value.clone()),
+            None => todo.fields.remove(&change.key),
+        };
+    }
+    db.apply(Operation::Update { todo })?;
+
+    Ok(db.save()?)
+}
+
+pub fn tag(db: &mut Database, id: u32, changes: &[TagChange]) -> Result<(), TodoError> {
+    let mut todo = db.find(id).ok_or(TodoError::NotFound(id))?.clone();
+    for change in changes {
+        change.apply(&mut todo);
+    }
+    db.apply(Operation::Update { todo })?;
+
+    Ok(db.save()?)
+}
+
+/// Replaces the text of a todo, keeping everything else. Without `text` the
+/// current text is opened in the editor.
+pub fn edit(db: &mut Database, id: u32, text: Option<&str>) -> Result<(), TodoError> {
+    let mut todo = db.find(id).ok_or(TodoError::NotFound(id))?.clone();
+    let text = match text {
+        Some(text) => text.to_string(),
+        None => editor::edit_text(&todo.text)?,
+    };
+    let text = todo::sanitize_text(&text, db.max_text_length).map_err(TodoError::parse)?;
+    if text == todo.text {
+        output::status("Text unchanged");
+        return Ok(());
+    }
+
+    todo.set_text(&text);
+    db.apply(Operation::Update { todo })?;
+
+    Ok(db.save()?)
+}
+
+/// Opens the notes of a todo in the editor.
+pub fn note(db: &mut Database, id: u32) -> Result<(), TodoError> {
+    let mut todo = db.find(id).ok_or(TodoError::NotFound(id))?.clone();
+    let notes = editor::edit_text(&todo.notes)?;
+    if notes == todo.notes {
+        output::status("Notes unchanged");
+        return Ok(());
+    }
+
+    todo.notes = notes;
+    db.apply(Operation::Update { todo })?;
+
+    Ok(db.save()?)
+}
+
+/// Renumbers todos with duplicate ids.
+pub fn repair(db: &mut Database) -> Result<(), TodoError> {
+    let renumbered = db.repair_ids()?;
+    if renumbered.is_empty() {
+        output::status("No duplicate ids found");
+    }
+    for (old_id, new_id) in renumbered {
+        let text = db.find(new_id).map(|t| t.text.as_str()).unwrap_or_default();
+        output::status(format!(
+            "Renumbered \"{}\" from {} to {}",
+            text, old_id, new_id
+        ));
+    }
+
+    Ok(())
+}
+
+/// Makes the ids sequential again after many deletions, after asking for
+/// confirmation unless `force` is set.
+pub fn renumber(db: &mut Database, force: bool) -> Result<(), TodoError> {
+    let duplicates = db.duplicate_ids();
+    if !duplicates.is_empty() {
+        return Err(TodoError::parse(format!(
+            "several todos share the ids {:?}, run `todo repair` first",
+            duplicates
+        )));
+    }
+
+    let count = db.todos.len() + db.trash.len();
+    let question = format!("Give {} todo(s) the ids 1 to {}?", count, count);
+    if !force && !prompt::confirm(&question)? {
+        output::status("Nothing renumbered");
+        return Ok(());
+    }
+
+    let renumbered = db.renumber()?;
+    if renumbered.is_empty() {
+        output::status("The ids are sequential already");
+        return Ok(());
+    }
+    for (old_id, new_id) in &renumbered {
+        log::info!("Renumbered todo {} to {}", old_id, new_id);
+    }
+    output::status(format!("Renumbered {} todo(s)", renumbered.len()));
+
+    Ok(())
+}
+
+pub fn show(db: &Database, id: u32) -> Result<(), TodoError> {
+    let todo = db.find(id).ok_or(TodoError::NotFound(id))?;
+
+    Ok(output::print_todo_details(todo)?)
+}
+
+pub fn export(db: &Database, format: ExportFormat, group_by: GroupBy) -> Result<(), TodoError> {
+    let mut out = io::stdout().lock();
+
+    let todos: Vec<Todo> = db.active_todos().cloned().collect();
+    Ok(export::write_todos(&mut out, &todos, format, group_by)?)
+}
+
+pub fn report(db: &Database, path: &Path) -> Result<(), TodoError> {
+    let write = || -> io::Result<()> {
+        let mut out = BufWriter::new(File::create(path)?);
+        let todos: Vec<Todo> = db.active_todos().cloned().collect();
+        report::write_html(&mut out, &todos)?;
+        out.flush()
+    };
+    write().map_err(|e| TodoError::from(e).in_file(path))?;
+    output::status(format!("Wrote the report to {}", path.display()));
+
+    Ok(())
+}
+
+/// Ids of the todos on the active list matching a filter for a bulk change.
+fn matching_ids(db: &Database, filter: &Filter) -> Vec<u32> {
+    let ids: Vec<u32> = db
+        .active_todos()
+        .filter(|t| filter.matches(t))
+        .map(|t| t.id)
+        .collect();
+    if ids.is_empty() {
+        output::status("No todos match");
+    }
+
+    ids
+}
+
+/// Lists the todos and asks whether to `action` all of them. Nothing is asked
+/// when there are none.
+fn confirm_all(db: &Database, ids: &[u32], action: &str) -> io::Result<bool> {
+    if ids.is_empty() {
+        return Ok(true);
+    }
+    for todo in ids.iter().filter_map(|&id| db.find(id)) {
+        println!("{}: {}", todo.id, todo.text);
+    }
+
+    prompt::confirm(&format!("{} {} todo(s)?", action, ids.len()))
+}
+
+fn print_dry_run(db: &Database, ids: &[u32], action: &str) {
+    for todo in ids.iter().filter_map(|&id| db.find(id)) {
+        println!("Would {} todo {}: {}", action, todo.id, todo.text);
+    }
+}
+
+pub fn find_todo_id(db: &Database, id: u32) -> Result<u32, TodoError> {
+    db.find(id).map(|t| t.id).ok_or(TodoError::NotFound(id))
+}
@@ -0,0 +1,275 @@
+use crate::error::TodoError;
+use crate::output::{Column, DateStyle};
+use crate::todo::{self, Priority};
+use crate::urgency;
+use crate::webhook::Event;
+use clap::ValueEnum;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::PathBuf;
+
+/// User settings read from `$XDG_CONFIG_HOME/rust-todo/config.toml`.
+///
+/// ```toml
+/// [aliases]
+/// a = "add"
+/// ls = "list --open"
+/// ```
+///
+/// `max_text_length` limits the length of todo texts, `columns` picks the
+/// columns of `todo list` and `dates` how they show dates. `[filters]` names
+/// filters for `todo list @name`, `[todoist]`, `[caldav]` and `[git]` where
+/// to sync with, `[github]` how to reach GitHub for `todo import github`,
+/// `[webhook]` where to announce changes, `[reminders]` when `todo remind`
+/// notifies of due todos, `[urgency]` how urgent todos are and the
+/// `[[escalation]]` rules what happens to todos that stay overdue.
+#[derive(Debug, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Config {
+    /// Shorthands for commands, expanded before the arguments are parsed
+    pub aliases: HashMap<String, String>,
+    /// Longest text a todo may have, in characters
+    pub max_text_length: usize,
+    /// Columns of the table when `--columns` is not given
+    pub columns: Option<Vec<Column>>,
+    /// How the table shows dates when `--dates` is not given
+    pub dates: DateStyle,
+    /// Saved filter expressions, used as `@name`
+    pub filters: HashMap<String, String>,
+    /// Account for `todo sync todoist`
+    pub todoist: Option<TodoistConfig>,
+    /// Task list for `todo sync caldav`
+    pub caldav: Option<CaldavConfig>,
+    /// Repository for `todo sync git`
+    pub git: Option<GitConfig>,
+    /// Account for `todo import github` and `todo done --close-issues`
+    pub github: Option<GithubConfig>,
+    /// Where to post created, completed and deleted todos
+    pub webhook: Option<WebhookConfig>,
+    pub reminders: RemindersConfig,
+    /// Weights of what makes todos urgent
+    pub urgency: urgency::Weights,
+    pub escalation: Vec<EscalationRule>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TodoistConfig {
+    /// API token, found in the Todoist settings under Integrations
+    pub token: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CaldavConfig {
+    /// URL of the calendar collection holding the tasks
+    pub url: String,
+    pub username: Option<String>,
+    pub password: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GitConfig {
+    /// A clone of the repository, with the remote to push to as upstream
+    pub repository: PathBuf,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct GithubConfig {
+    /// Personal access token; public repositories can be imported without
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WebhookConfig {
+    pub url: String,
+    /// The changes to post, by default all
+    #[serde(default = "all_events")]
+    pub events: Vec<Event>,
+    /// Sent with every request, e.g. `Authorization`
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+}
+
+fn all_events() -> Vec<Event> {
+    Event::value_variants().to_vec()
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct RemindersConfig {
+    /// How long before their due date todos without a `remind` field are
+    /// reminded of, e.g. `15m`; by default when they are due
+    pub lead_time: Option<String>,
+}
+
+/// What to do to todos overdue for a while:
+///
+/// ```toml
+/// [[escalation]]
+/// overdue = "3d"
+/// priority = "high"
+/// tags = ["late"]
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EscalationRule {
+    /// How long the todo has to be overdue, e.g. `3d`
+    pub overdue: String,
+    /// Raised to this priority, never lowered
+    pub priority: Option<Priority>,
+    /// Tags to add
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Config {
+            aliases: HashMap::new(),
+            max_text_length: todo::DEFAULT_MAX_TEXT_LENGTH,
+            columns: None,
+            dates: DateStyle::default(),
+            filters: HashMap::new(),
+            todoist: None,
+            caldav: None,
+            git: None,
+            github: None,
+            webhook: None,
+            reminders: RemindersConfig::default(),
+            urgency: urgency::Weights::default(),
+            escalation: Vec::new(),
+        }
+    }
+}
+
+impl Config {
+    pub fn path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("rust-todo").join("config.toml"))
+    }
+
+    /// Loads the config file, or the defaults when there is none.
+    pub fn load() -> Result<Config, TodoError> {
+        let Some(path) = Config::path() else {
+            return Ok(Config::default());
+        };
+
+        let contents = match fs::read_to_string(&path) {
+            Ok(contents) => contents,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Config::default()),
+            Err(e) => return Err(e.into()),
+        };
+
+        toml::from_str(&contents).map_err(|e| TodoError::parse(e.to_string()).in_file(path))
+    }
+
+    /// Replaces the `@name` words of a filter expression with the saved
+    /// filters, in parentheses. Saved filters may refer to each other, as
+    /// long as they do not form a cycle.
+    pub fn expand_filters(&self, expression: &str) -> Result<String, String> {
+        self.expand_filters_within(expression, &mut Vec::new())
+    }
+
+    fn expand_filters_within<'a>(
+        &'a self,
+        expression: &str,
+        expanding: &mut Vec<&'a str>,
+    ) -> Result<String, String> {
+        let mut words = Vec::new();
+        for word in expression.split_whitespace() {
+            // Allow parentheses around the name, like `(@today or +work)`
+            let start = word.len() - word.trim_start_matches('(').len();
+            let end = word.trim_end_matches(')').len().max(start);
+            let Some(name) = word[start..end].strip_prefix('@') else {
+                words.push(word.to_string());
+                continue;
+            };
+
+            let Some((name, filter)) = self.filters.get_key_value(name) else {
+                return Err(format!("there is no saved filter called '{}'", name));
+            };
+            if expanding.contains(&name.as_str()) {
+                return Err(format!("the saved filter '{}' refers to itself", name));
+            }
+            expanding.push(name);
+            let expansion = self.expand_filters_within(filter, expanding)?;
+            expanding.pop();
+
+            words.push(format!("{}({}){}", &word[..start], expansion, &word[end..]));
+        }
+
+        Ok(words.join(" "))
+    }
+
+    /// Replaces an alias in the command position with its expansion. Like
+    /// in git, an alias cannot shadow one of the subcommands of `cli`.
+    pub fn expand_aliases(&self, mut args: Vec<String>, cli: &clap::Command) -> Vec<String> {
+        // Global options taking a value, given without `=`
+        let taking_value: Vec<String> = cli
+            .get_arguments()
+            .filter(|arg| arg.get_action().takes_values())
+            .flat_map(|arg| {
+                let long = arg.get_long().map(|long| format!("--{}", long));
+                let short = arg.get_short().map(|short| format!("-{}", short));
+                long.into_iter().chain(short)
+            })
+            .collect();
+
+        let mut i = 1;
+        while i < args.len() {
+            match args[i].as_str() {
+                arg if taking_value.iter().any(|option| option == arg) => i += 2,
+                arg if arg.starts_with('-') => i += 1,
+                _ => break,
+            }
+        }
+
+        if let Some(expansion) = args
+            .get(i)
+            .filter(|command| cli.find_subcommand(command.as_str()).is_none())
+            .and_then(|command| self.aliases.get(command))
+        {
+            let expansion: Vec<String> = expansion.split_whitespace().map(String::from).collect();
+            args.splice(i..=i, expansion);
+        }
+
+        args
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use clap::CommandFactory;
+
+    #[test]
+    fn expands_aliases_after_global_options() {
+        let mut config = Config::default();
+        config
+            .aliases
+            .insert("ls".to_string(), "list --open".to_string());
+        config
+            .aliases
+            .insert("list".to_string(), "today".to_string());
+        let expand = |args: &[&str]| {
+            let args = args.iter().map(|arg| arg.to_string()).collect();
+            config.expand_aliases(args, &crate::Cli::command())
+        };
+
+        assert_eq!(
+            expand(&["todo", "--tz", "UTC", "ls"]),
+            ["todo", "--tz", "UTC", "list", "--open"]
+        );
+        assert_eq!(
+            expand(&["todo", "--db", "a.db", "-q", "ls", "+work"]),
+            ["todo", "--db", "a.db", "-q", "list", "--open", "+work"]
+        );
+        // Subcommands always win
+        assert_eq!(expand(&["todo", "list"]), ["todo", "list"]);
+    }
+}
@@ -0,0 +1,91 @@
+//! The parts of RFC 4180 needed by the CSV output and the legacy text format.
+
+/// Quotes a field when it contains a delimiter, quote or line break.
+pub fn quote(field: &str) -> String {
+    if field.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", field.replace('"', "\"\""))
+    } else {
+        field.to_string()
+    }
+}
+
+/// Splits a record into its fields, undoing `quote`. Quotes inside a field
+/// that does not start with one are kept as they are, like older versions
+/// wrote them. `None` means a quoted field is not closed properly.
+pub fn split(record: &str) -> Option<Vec<String>> {
+    let mut fields = Vec::new();
+    let mut chars = record.chars().peekable();
+
+    loop {
+        let mut field = String::new();
+
+        if chars.next_if_eq(&'"').is_some() {
+            loop {
+                match chars.next()? {
+                    '"' if chars.next_if_eq(&'"').is_some() => field.push('"'),
+                    '"' => break,
+                    c => field.push(c),
+                }
+            }
+
+            // The closing quote has to end the field
+            if chars.peek().is_some_and(|&c| c != ',') {
+                return None;
+            }
+        } else {
+            while let Some(c) = chars.next_if(|&c| c != ',') {
+                field.push(c);
+            }
+        }
+
+        fields.push(field);
+        if chars.next().is_none() {
+            return Some(fields);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn splits_plain_fields() {
+        assert_eq!(split("a,b,,c").unwrap(), ["a", "b", "", "c"]);
+        assert_eq!(split("").unwrap(), [""]);
+    }
+
+    #[test]
+    fn splits_quoted_fields() {
+        assert_eq!(split(r#"1,"a, ""b""",c"#).unwrap(), ["1", r#"a, "b""#, "c"]);
+        assert_eq!(split("\"line\nbreak\",x").unwrap(), ["line\nbreak", "x"]);
+        assert_eq!(split(r#""""#).unwrap(), [""]);
+    }
+
+    #[test]
+    fn keeps_quotes_inside_unquoted_fields() {
+        assert_eq!(split(r#"say "hi",x"#).unwrap(), [r#"say "hi""#, "x"]);
+    }
+
+    #[test]
+    fn rejects_broken_quoting() {
+        assert_eq!(split(r#""open"#), None);
+        assert_eq!(split(r#""closed"early,x"#), None);
+    }
+
+    #[test]
+    fn quote_round_trips() {
+        for field in [
+            "",
+            "plain",
+            "a,b",
+            r#"""#,
+            "\"quoted\"",
+            "two\r\nlines",
+            "ümlaut, ✓",
+        ] {
+            let record = format!("{},{}", quote(field), quote(field));
+            assert_eq!(split(&record).unwrap(), [field, field]);
+        }
+    }
+}
@@ -0,0 +1,487 @@
+use crate::error::TodoError;
+use crate::index::SearchIndex;
+use crate::journal::{self, Journal, Operation};
+use crate::lock::DatabaseLock;
+use crate::prompt;
+use crate::storage::{self, Backend, Storage};
+use crate::todo::{self, Metadata, Todo};
+use crate::webhook::{Event, Webhook};
+use chrono::prelude::*;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process;
+use std::sync::{Arc, Mutex, MutexGuard};
+
+/// An open database: the loaded todos together with the storage, journal and
+/// lock backing them. All changes go through `apply` so they are journaled.
+pub struct Database {
+    pub metadata: Metadata,
+    pub todos: Vec<Todo>,
+    /// Deleted todos, until they are restored or the trash is emptied
+    pub trash: Vec<Todo>,
+    storage: Box<dyn Storage>,
+    journal: Journal,
+    autosave: bool,
+    /// Without autosave, the changes since the last save; shared with the
+    /// handler installed by `save_on_interrupt`
+    unsaved: Arc<Mutex<Vec<Operation>>>,
+    /// Longest text a todo may have, in characters
+    pub max_text_length: usize,
+    /// Kept up to date by `apply`, for `search`
+    index: SearchIndex,
+    /// Told about the changes made with `apply`
+    pub webhook: Option<Webhook>,
+    /// The list new todos are put on and listings show; all lists without
+    pub active_list: Option<String>,
+    _lock: DatabaseLock,
+}
+
+impl Database {
+    pub fn open(
+        backend: Backend,
+        path: &Path,
+        wait: bool,
+        autosave: bool,
+        lenient: bool,
+    ) -> io::Result<Self> {
+        if let Some(dir) = path.parent().filter(|d| !d.as_os_str().is_empty()) {
+            fs::create_dir_all(dir)?;
+        }
+
+        let lock = DatabaseLock::acquire(path, wait)?;
+        let storage = storage::open(backend, path, lenient)?;
+        let mut metadata = storage.load_metadata()?;
+        let (mut trash, mut todos): (Vec<Todo>, Vec<Todo>) = storage
+            .load_todos()?
+            .into_iter()
+            .partition(|t| t.deleted_at.is_some());
+        log::debug!(
+            "Loaded {} todos and {} in the trash, seq_id {}",
+            todos.len(),
+            trash.len(),
+            metadata.seq_id
+        );
+
+        // Recover whatever an interrupted session left in the journal
+        let mut journal = Journal::for_database(path);
+        journal.replay(&mut metadata, &mut todos, &mut trash)?;
+
+        let index = SearchIndex::build(&todos);
+        let mut db = Database {
+            metadata,
+            todos,
+            trash,
+            storage,
+            journal,
+            autosave,
+            unsaved: Arc::default(),
+            max_text_length: todo::DEFAULT_MAX_TEXT_LENGTH,
+            index,
+            webhook: None,
+            active_list: None,
+            _lock: lock,
+        };
+        if !db.journal.is_empty() {
+            log::info!("Recovered {} changes from the journal", db.journal.len());
+            db.save()?;
+        }
+
+        let duplicates = db.duplicate_ids();
+        if !duplicates.is_empty() {
+            log::warn!(
+                "Several todos share the ids {:?}, run `todo repair` to renumber them",
+                duplicates
+            );
+        }
+
+        Ok(db)
+    }
+
+    /// Ids used by more than one todo, e.g. after hand-editing the file.
+    pub fn duplicate_ids(&self) -> Vec<u32> {
+        let mut seen = HashSet::new();
+        let mut duplicates: Vec<u32> = self
+            .todos
+            .iter()
+            .map(|t| t.id)
+            .filter(|&id| !seen.insert(id))
+            .collect();
+        duplicates.sort_unstable();
+        duplicates.dedup();
+
+        duplicates
+    }
+
+    /// Gives every todo sharing its id with an earlier one a new id, those in
+    /// the trash after all others, and makes sure new todos never get an id in
+    /// use. Returns the old and new ids of the renumbered todos.
+    pub fn repair_ids(&mut self) -> io::Result<Vec<(u32, u32)>> {
+        let max_id = self.todos.iter().chain(&self.trash).map(|t| t.id).max();
+        let max_id = max_id.unwrap_or(0);
+        self.metadata.seq_id = self.metadata.seq_id.max(max_id);
+
+        let mut seen = HashSet::new();
+        let mut renumbered = Vec::new();
+        for todo in self.todos.iter_mut().chain(&mut self.trash) {
+            if !seen.insert(todo.id) {
+                self.metadata.seq_id += 1;
+                renumbered.push((todo.id, self.metadata.seq_id));
+                todo.id = self.metadata.seq_id;
+                todo.updated_at = Local::now();
+            }
+        }
+
+        self.index = SearchIndex::build(&self.todos);
+
+        // Journal operations address todos by id, so persist the new ids
+        // right away
+        self.save()?;
+
+        Ok(renumbered)
+    }
+
+    /// Gives the todos the ids from 1 on in the order of their current ids,
+    /// followed by those in the trash, and updates the references between
+    /// them; parents that are gone are dropped. Returns the old and new ids of
+    /// the todos whose id changed. The ids have to be unique, see
+    /// `repair_ids`.
+    pub fn renumber(&mut self) -> io::Result<Vec<(u32, u32)>> {
+        self.todos.sort_by_key(|t| t.id);
+        self.trash.sort_by_key(|t| t.id);
+
+        let new_ids: HashMap<u32, u32> = self
+            .todos
+            .iter()
+            .chain(&self.trash)
+            .zip(1..)
+            .map(|(todo, new_id)| (todo.id, new_id))
+            .collect();
+        let mut renumbered: Vec<(u32, u32)> = new_ids
+            .iter()
+            .map(|(&old_id, &new_id)| (old_id, new_id))
+            .filter(|(old_id, new_id)| old_id != new_id)
+            .collect();
+        renumbered.sort_unstable();
+
+        let now = Local::now();
+        for todo in self.todos.iter_mut().chain(&mut self.trash) {
+            let id = new_ids[&todo.id];
+            // A parent that is gone could otherwise end up as another todo
+            let parent = todo.parent.and_then(|p| new_ids.get(&p).copied());
+            if (id, parent) != (todo.id, todo.parent) {
+                todo.id = id;
+                todo.parent = parent;
+                todo.updated_at = now;
+            }
+        }
+        self.metadata.seq_id = new_ids.len() as u32;
+        self.index = SearchIndex::build(&self.todos);
+
+        // Like for `repair_ids`, the journal must not see the old ids
+        self.save()?;
+
+        Ok(renumbered)
+    }
+
+    /// Creates a todo with the next free id from the sanitized text, taking
+    /// `+tag` words out of it. It is only added to the database once it is
+    /// applied with `Operation::Add`.
+    pub fn new_todo(&mut self, text: &str) -> Result<Todo, TodoError> {
+        let text = todo::sanitize_text(text, self.max_text_length).map_err(TodoError::parse)?;
+        self.metadata.seq_id += 1;
+
+        let mut todo = Todo::with_tags_from_text(self.metadata.seq_id, &text);
+        todo.list = self.active_list.clone();
+        Ok(todo)
+    }
+
+    /// The todos on the active list, or all without one.
+    pub fn active_todos(&self) -> impl Iterator<Item = &Todo> {
+        let list = self.active_list.as_deref();
+        self.todos.iter().filter(move |t| t.in_list(list))
+    }
+
+    pub fn find(&self, id: u32) -> Option<&Todo> {
+        self.todos.iter().find(|t| t.id == id)
+    }
+
+    /// The todos on the active list that can contain `query` in their text or
+    /// notes according to the index; they still need to be checked with
+    /// `Filter::search`.
+    pub fn search_candidates(&self, query: &str) -> Vec<&Todo> {
+        match self.index.candidates(query) {
+            Some(ids) => self
+                .active_todos()
+                .filter(|t| ids.contains(&t.id))
+                .collect(),
+            None => self.active_todos().collect(),
+        }
+    }
+
+    pub fn apply(&mut self, mut operation: Operation) -> io::Result<()> {
+        if let Operation::Update { todo } = &mut operation {
+            todo.updated_at = Local::now();
+        }
+
+        // Autosaving goes through the journal, which is much cheaper than
+        // rewriting the whole database after every change
+        if self.autosave {
+            log::debug!("Journaling {:?}", operation);
+            self.journal.append(&operation)?;
+        } else {
+            lock_unsaved(&self.unsaved).push(operation.clone());
+        }
+        self.update_index(&operation);
+        let event = Event::of(&operation, &self.todos);
+        operation.apply(&mut self.metadata, &mut self.todos, &mut self.trash);
+
+        if let (Some(webhook), Some((event, id))) = (&self.webhook, event) {
+            let todo = self.todos.iter().chain(&self.trash).find(|t| t.id == id);
+            if let Some(todo) = todo {
+                webhook.send(event, todo);
+            }
+        }
+
+        if self.journal.len() >= journal::COMPACT_THRESHOLD {
+            self.save()?;
+        }
+
+        Ok(())
+    }
+
+    /// Changes the index for `operation`, before it is applied.
+    fn update_index(&mut self, operation: &Operation) {
+        match operation {
+            Operation::Add { todo } => self.index.add(todo),
+            Operation::Complete { .. } | Operation::Purge { .. } => {}
+            Operation::Delete { id, .. } | Operation::Archive { id } => {
+                if let Some(todo) = self.todos.iter().find(|t| t.id == *id) {
+                    self.index.remove(todo);
+                }
+            }
+            Operation::Restore { id } => {
+                if let Some(todo) = self.trash.iter().find(|t| t.id == *id) {
+                    self.index.add(todo);
+                }
+            }
+            Operation::Update { todo } => {
+                if let Some(old) = self.todos.iter().find(|t| t.id == todo.id) {
+                    self.index.remove(old);
+                }
+                self.index.add(todo);
+            }
+        }
+    }
+
+    /// Writes the todos to the storage and empties the journal.
+    pub fn save(&mut self) -> io::Result<()> {
+        log::debug!("Saving {} todos", self.todos.len());
+        let todos: Vec<Todo> = self.todos.iter().chain(&self.trash).cloned().collect();
+        self.storage.save_todos(&self.metadata, &todos)?;
+        lock_unsaved(&self.unsaved).clear();
+        self.journal.clear()
+    }
+
+    /// Whether there are changes that are neither saved nor journaled.
+    pub fn has_unsaved_changes(&self) -> bool {
+        !lock_unsaved(&self.unsaved).is_empty()
+    }
+
+    /// Makes Ctrl-C and SIGTERM write the unsaved changes to the journal
+    /// before exiting, so they are recovered on the next start.
+    pub fn save_on_interrupt(&self) -> io::Result<()> {
+        let unsaved = Arc::clone(&self.unsaved);
+        let journal_path = self.journal.path().to_path_buf();
+
+        ctrlc::set_handler(move || {
+            let unsaved = lock_unsaved(&unsaved);
+            let mut journal = Journal::new(&journal_path);
+            let result = unsaved.iter().try_for_each(|op| journal.append(op));
+
+            match result {
+                Ok(()) if !unsaved.is_empty() => {
+                    eprintln!("\nInterrupted, kept {} unsaved change(s)", unsaved.len())
+                }
+                Ok(()) => {}
+                Err(e) => eprintln!("\nInterrupted, could not keep the unsaved changes: {}", e),
+            }
+            process::exit(130);
+        })
+        .map_err(io::Error::other)
+    }
+}
+
+impl Drop for Database {
+    /// Closing the database without saving, e.g. by quitting the menu with
+    /// autosave disabled, asks whether to save first.
+    fn drop(&mut self) {
+        if !self.has_unsaved_changes() {
+            return;
+        }
+
+        let save = prompt::confirm_with_default("You have unsaved changes, save now?", true)
+            .and_then(|save| if save { self.save() } else { Ok(()) });
+        if let Err(e) = save {
+            eprintln!("Error: could not save the changes: {}", e);
+        }
+    }
+}
+
+/// The list stays usable even if a thread panicked while holding the lock.
+fn lock_unsaved(unsaved: &Mutex<Vec<Operation>>) -> MutexGuard<'_, Vec<Operation>> {
+    unsaved.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::commands;
+    use std::env;
+    use std::path::PathBuf;
+
+    /// A JSON database in a directory of its own, removed first if an
+    /// earlier run left it behind.
+    fn open(name: &str) -> (Database, PathBuf) {
+        let dir = env::temp_dir().join(format!("rust-todo-{}-{}", name, process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        let path = dir.join("todos.json");
+        let db = Database::open(Backend::Json, &path, false, true, false).unwrap();
+
+        (db, path)
+    }
+
+    fn add(db: &mut Database, text: &str) -> u32 {
+        let todo = db.new_todo(text).unwrap();
+        let id = todo.id;
+        db.apply(Operation::Add { todo }).unwrap();
+
+        id
+    }
+
+    #[test]
+    fn compacts_the_journal_at_the_threshold() {
+        let (mut db, path) = open("compact");
+        for i in 1..journal::COMPACT_THRESHOLD {
+            add(&mut db, &format!("todo {}", i));
+        }
+        assert_eq!(db.journal.len(), journal::COMPACT_THRESHOLD - 1);
+        assert!(db.journal.path().exists());
+
+        add(&mut db, "one more");
+        assert!(db.journal.is_empty());
+        assert!(!db.journal.path().exists());
+        drop(db);
+
+        let db = Database::open(Backend::Json, &path, false, true, false).unwrap();
+        assert_eq!(db.todos.len(), journal::COMPACT_THRESHOLD);
+        assert_eq!(db.metadata.seq_id, journal::COMPACT_THRESHOLD as u32);
+        drop(db);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn trashed_todos_can_be_restored_and_purged() {
+        let (mut db, path) = open("trash");
+        add(&mut db, "pay rent");
+        let id = add(&mut db, "call mom");
+
+        db.apply(Operation::delete(id)).unwrap();
+        assert!(db.find(id).is_none());
+        assert_eq!(db.trash[0].id, id);
+        assert!(db.trash[0].deleted_at.is_some());
+        // The id stays taken while the todo is in the trash
+        assert_eq!(add(&mut db, "water plants"), 3);
+
+        db.apply(Operation::Restore { id }).unwrap();
+        assert!(db.trash.is_empty());
+        assert!(db.find(id).unwrap().deleted_at.is_none());
+
+        db.apply(Operation::delete(id)).unwrap();
+        db.apply(Operation::Purge { id }).unwrap();
+        db.save().unwrap();
+        drop(db);
+
+        let db = Database::open(Backend::Json, &path, false, true, false).unwrap();
+        let ids: Vec<u32> = db.todos.iter().map(|t| t.id).collect();
+        assert_eq!(ids, [1, 3]);
+        assert!(db.trash.is_empty());
+        drop(db);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn restoring_a_todo_whose_id_was_reused_needs_a_repair() {
+        let (mut db, path) = open("reused");
+        let id = add(&mut db, "pay rent");
+        db.apply(Operation::delete(id)).unwrap();
+        // E.g. a todo added to the file by hand
+        let todo = Todo::new(id, "call mom");
+        db.apply(Operation::Add { todo }).unwrap();
+
+        assert!(commands::restore(&mut db, &[id]).is_err());
+        assert_eq!(db.trash.len(), 1);
+        assert_eq!(db.find(id).unwrap().text, "call mom");
+
+        assert_eq!(db.repair_ids().unwrap(), [(id, 2)]);
+        commands::restore(&mut db, &[2]).unwrap();
+        assert!(db.trash.is_empty());
+        assert_eq!(db.find(2).unwrap().text, "pay rent");
+        assert_eq!(db.find(id).unwrap().text, "call mom");
+        drop(db);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+
+    #[test]
+    fn renumber_rewrites_parents_trash_and_seq_id() {
+        let (mut db, path) = open("renumber");
+        for text in ["gone", "trip", "gone too", "pack", "book hotel", "old"] {
+            add(&mut db, text);
+        }
+        let mut pack = db.find(4).unwrap().clone();
+        pack.parent = Some(2);
+        db.apply(Operation::Update { todo: pack }).unwrap();
+        let mut book = db.find(5).unwrap().clone();
+        book.parent = Some(2);
+        db.apply(Operation::Update { todo: book }).unwrap();
+        // Its parent is purged, and 3 becomes the id of another todo
+        let mut old = db.find(6).unwrap().clone();
+        old.parent = Some(3);
+        db.apply(Operation::Update { todo: old }).unwrap();
+        for id in [1, 3] {
+            db.apply(Operation::delete(id)).unwrap();
+            db.apply(Operation::Purge { id }).unwrap();
+        }
+        db.apply(Operation::delete(6)).unwrap();
+
+        let renumbered = db.renumber().unwrap();
+        assert_eq!(renumbered, [(2, 1), (4, 2), (5, 3), (6, 4)]);
+        let todos: Vec<(u32, &str, Option<u32>)> = db
+            .todos
+            .iter()
+            .map(|t| (t.id, t.text.as_str(), t.parent))
+            .collect();
+        assert_eq!(
+            todos,
+            [
+                (1, "trip", None),
+                (2, "pack", Some(1)),
+                (3, "book hotel", Some(1))
+            ]
+        );
+        assert_eq!((db.trash[0].id, db.trash[0].text.as_str()), (4, "old"));
+        assert_eq!(db.trash[0].parent, None);
+        assert_eq!(db.metadata.seq_id, 4);
+        assert_eq!(add(&mut db, "new"), 5);
+        drop(db);
+
+        // Saved right away, so the journal only has the new todo
+        let db = Database::open(Backend::Json, &path, false, true, false).unwrap();
+        assert_eq!(db.find(3).unwrap().parent, Some(1));
+        assert_eq!(db.trash[0].id, 4);
+        assert_eq!(db.metadata.seq_id, 5);
+        drop(db);
+        fs::remove_dir_all(path.parent().unwrap()).unwrap();
+    }
+}
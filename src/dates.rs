@@ -0,0 +1,504 @@
+use chrono::prelude::*;
+use chrono::Duration;
+
+/// Parses a date given on the command line. Accepted are `YYYY-MM-DD`, which
+/// means the end of that day, `YYYY-MM-DD HH:MM` and RFC 3339 timestamps.
+pub fn parse_datetime(s: &str) -> Result<DateTime<Local>, String> {
+    let s = s.trim();
+
+    if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        let end_of_day = date.and_time(NaiveTime::MIN) + Duration::days(1) - Duration::seconds(1);
+        return from_local(end_of_day, s);
+    }
+
+    if let Ok(datetime) = NaiveDateTime::parse_from_str(s, "%Y-%m-%d %H:%M") {
+        return from_local(datetime, s);
+    }
+
+    DateTime::parse_from_rfc3339(s)
+        .map(|datetime| datetime.with_timezone(&Local))
+        .map_err(|_| {
+            format!(
+                "'{}' is not a date, expected YYYY-MM-DD or YYYY-MM-DD HH:MM",
+                s
+            )
+        })
+}
+
+/// Parses a due date given on the command line: anything `parse_datetime`
+/// accepts, or words like `tomorrow`, `friday`, `next friday 5pm`, `in 3
+/// days` or `in 2 hours`. Days without a time mean the end of the day, like
+/// `YYYY-MM-DD`.
+pub fn parse_due(s: &str) -> Result<DateTime<Local>, String> {
+    if let Ok(datetime) = parse_datetime(s) {
+        return Ok(datetime);
+    }
+
+    match parse_words(s, Local::now().naive_local())? {
+        When::Day(day) => {
+            let end_of_day =
+                day.and_time(NaiveTime::MIN) + Duration::days(1) - Duration::seconds(1);
+            from_local(end_of_day, s)
+        }
+        When::Time(datetime) => from_local(datetime, s),
+    }
+}
+
+/// A point in time given in words, as a whole day or to the minute.
+#[derive(Debug, PartialEq)]
+enum When {
+    Day(NaiveDate),
+    Time(NaiveDateTime),
+}
+
+/// Parses a day and a time in words, relative to `now`. The day is `today`,
+/// `tomorrow`, a weekday, which is the next one from today on, `next
+/// <weekday>`, the next one after today, `next week` or `next month`, which
+/// mean their first day, or `in <amount> <unit>`. The time is like `5pm`,
+/// `5:30 pm`, `17:00` or `noon`, optionally after `at`; a time alone is the
+/// next time it is that late. Hours without `am`, `pm` or minutes are
+/// rejected as ambiguous, the same as dates like `03/04`.
+fn parse_words(s: &str, now: NaiveDateTime) -> Result<When, String> {
+    let lowercase = s.trim().to_lowercase();
+    let mut words: Vec<&str> = lowercase.split_whitespace().collect();
+    let not_understood = || {
+        format!(
+            "'{}' is not a date, expected e.g. YYYY-MM-DD, tomorrow, next friday 5pm or in 3 days",
+            s.trim()
+        )
+    };
+    let today = now.date();
+
+    // The time comes last, like `tomorrow 5pm` or `friday at 10:30 am`
+    let mut time = None;
+    if let [.., hour, meridiem @ ("am" | "pm")] = words[..] {
+        time = Some(parse_time(&format!("{}{}", hour, meridiem), s)?);
+        words.truncate(words.len() - 2);
+    } else if let Some(last) = words.last().filter(|word| looks_like_time(word)) {
+        time = Some(parse_time(last, s)?);
+        words.pop();
+    }
+    if time.is_some() && words.last() == Some(&"at") {
+        words.pop();
+    }
+
+    let weekday_from = |name: &str, skip_today: bool| -> Option<NaiveDate> {
+        let weekday = name.parse::<Weekday>().ok()?.num_days_from_monday();
+        let today_weekday = today.weekday().num_days_from_monday();
+        let mut days_ahead = (weekday + 7 - today_weekday) % 7;
+        if skip_today && days_ahead == 0 {
+            days_ahead = 7;
+        }
+        Some(today + Duration::days(days_ahead.into()))
+    };
+
+    let when = match words[..] {
+        [] => match time {
+            Some(time) if time > now.time() => When::Time(today.and_time(time)),
+            Some(time) => When::Time(today.succ_opt().ok_or_else(not_understood)?.and_time(time)),
+            None => return Err(not_understood()),
+        },
+        ["today"] => When::Day(today),
+        ["tomorrow"] => When::Day(today.succ_opt().ok_or_else(not_understood)?),
+        ["next", "week"] => {
+            let monday = weekday_from("monday", true).ok_or_else(not_understood)?;
+            When::Day(monday)
+        }
+        ["next", "month"] => {
+            let (year, month) = match today.month() {
+                12 => (today.year() + 1, 1),
+                month => (today.year(), month + 1),
+            };
+            When::Day(NaiveDate::from_ymd_opt(year, month, 1).ok_or_else(not_understood)?)
+        }
+        ["next", name] => When::Day(weekday_from(name, true).ok_or_else(not_understood)?),
+        ["this", name] | [name] => match weekday_from(name, false) {
+            Some(day) => When::Day(day),
+            None if name.contains('/') => {
+                return Err(format!(
+                    "'{}' is ambiguous, write the date as YYYY-MM-DD",
+                    s.trim()
+                ))
+            }
+            None => return Err(not_understood()),
+        },
+        ["in", amount, unit] => {
+            let amount: i64 = match amount {
+                "a" | "an" => 1,
+                amount => amount.parse().map_err(|_| not_understood())?,
+            };
+            let duration = parse_duration(&format!("{}{}", amount, unit));
+            match (duration, unit) {
+                (_, "month" | "months") => When::Day(
+                    u32::try_from(amount)
+                        .ok()
+                        .and_then(|months| today.checked_add_months(chrono::Months::new(months)))
+                        .ok_or_else(not_understood)?,
+                ),
+                (Some(duration), _) if duration < Duration::days(1) => {
+                    if time.is_some() {
+                        return Err(format!(
+                            "'{}' gives the time twice, leave out one of them",
+                            s.trim()
+                        ));
+                    }
+                    When::Time(now + duration)
+                }
+                (Some(duration), _) => When::Day(
+                    now.checked_add_signed(duration)
+                        .ok_or_else(|| format!("'{}' is too far in the future", s.trim()))?
+                        .date(),
+                ),
+                (None, _) => return Err(not_understood()),
+            }
+        }
+        _ => return Err(not_understood()),
+    };
+
+    Ok(match (when, time) {
+        (When::Day(day), Some(time)) => When::Time(day.and_time(time)),
+        (when, _) => when,
+    })
+}
+
+/// Whether a word is meant as a time of day, including ambiguous ones like
+/// a bare `5`, which `parse_time` rejects.
+fn looks_like_time(word: &str) -> bool {
+    let digits = word
+        .trim_end_matches("am")
+        .trim_end_matches("pm")
+        .replace(':', "");
+
+    word == "noon" || (!digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()))
+}
+
+/// Parses a time of day like `5pm`, `5:30am`, `17:00` or `noon`.
+fn parse_time(word: &str, input: &str) -> Result<NaiveTime, String> {
+    let invalid = || format!("'{}' is not a time of day in '{}'", word, input.trim());
+    if word == "noon" {
+        return NaiveTime::from_hms_opt(12, 0, 0).ok_or_else(invalid);
+    }
+
+    let (clock, meridiem) = match (word.strip_suffix("am"), word.strip_suffix("pm")) {
+        (Some(clock), _) => (clock, Some(0)),
+        (_, Some(clock)) => (clock, Some(12)),
+        _ => (word, None),
+    };
+    let (hour, minute) = match clock.split_once(':') {
+        Some((hour, minute)) if minute.len() == 2 => (hour, minute),
+        Some(_) => return Err(invalid()),
+        None if meridiem.is_none() => {
+            return Err(format!(
+                "'{}' is ambiguous, write the time like {}am, {}pm or {}:00",
+                word, word, word, word
+            ))
+        }
+        None => (clock, "00"),
+    };
+    let (hour, minute): (u32, u32) = (
+        hour.parse().map_err(|_| invalid())?,
+        minute.parse().map_err(|_| invalid())?,
+    );
+    let hour = match meridiem {
+        Some(_) if !(1..=12).contains(&hour) => return Err(invalid()),
+        Some(offset) => hour % 12 + offset,
+        None => hour,
+    };
+
+    NaiveTime::from_hms_opt(hour, minute, 0).ok_or_else(invalid)
+}
+
+/// Parses the start of a time span like `--modified-since`: a relative time
+/// such as `2 days ago` or `3h ago`, `today`, `yesterday`, or anything
+/// `parse_datetime` accepts, except that `YYYY-MM-DD` means the start of the
+/// day.
+pub fn parse_since(s: &str) -> Result<DateTime<Local>, String> {
+    let s = s.trim();
+    let start_of_today = || from_local(Local::now().date_naive().and_time(NaiveTime::MIN), s);
+
+    match s {
+        "today" => return start_of_today(),
+        "yesterday" => return start_of_today().map(|today| today - Duration::days(1)),
+        _ => {}
+    }
+
+    if let Some(ago) = s.strip_suffix("ago") {
+        return parse_duration(ago.trim())
+            .map(|duration| Local::now() - duration)
+            .ok_or_else(|| format!("'{}' is not a time ago, expected e.g. 2 days ago", s));
+    }
+
+    if let Ok(date) = NaiveDate::parse_from_str(s, "%Y-%m-%d") {
+        return from_local(date.and_time(NaiveTime::MIN), s);
+    }
+
+    parse_datetime(s)
+}
+
+/// Parses a point in time for comparisons like `due.before:friday`: `today`,
+/// `tomorrow`, `yesterday`, weekdays, `this week`, `last week`, `this month`
+/// and `last month`, which mean the start of that day, week or month, or
+/// anything `parse_since` accepts. A weekday is the next one from today on,
+/// `last friday` the one before today.
+pub fn parse_day(s: &str) -> Result<DateTime<Local>, String> {
+    let s = s.trim();
+    let today = Local::now().date_naive();
+    let weekday = |name: &str| {
+        name.parse::<Weekday>()
+            .ok()
+            .map(|w| w.num_days_from_monday())
+    };
+    let today_weekday = today.weekday().num_days_from_monday();
+    let start_of_week = today - Duration::days(today_weekday.into());
+    let start_of_month = today.with_day(1).unwrap_or(today);
+
+    let lowercase = s.to_lowercase();
+    let day = match lowercase.as_str() {
+        "today" => Some(today),
+        "tomorrow" => today.succ_opt(),
+        "yesterday" => today.pred_opt(),
+        "this week" => Some(start_of_week),
+        "last week" => Some(start_of_week - Duration::weeks(1)),
+        "this month" => Some(start_of_month),
+        "last month" => start_of_month.pred_opt().and_then(|day| day.with_day(1)),
+        name => match name.strip_prefix("last ") {
+            Some(name) => weekday(name).map(|weekday| {
+                let days_back = (today_weekday + 6 - weekday) % 7 + 1;
+                today - Duration::days(days_back.into())
+            }),
+            None => weekday(name).map(|weekday| {
+                let days_ahead = (weekday + 7 - today_weekday) % 7;
+                today + Duration::days(days_ahead.into())
+            }),
+        },
+    };
+
+    match day {
+        Some(day) => from_local(day.and_time(NaiveTime::MIN), s),
+        // Days in words like `next friday` also mean their start here
+        None => parse_since(s).or_else(|e| {
+            if s.ends_with("ago") {
+                return Err(e);
+            }
+            match parse_words(s, Local::now().naive_local())? {
+                When::Day(day) => from_local(day.and_time(NaiveTime::MIN), s),
+                When::Time(datetime) => from_local(datetime, s),
+            }
+        }),
+    }
+}
+
+/// Parses how long ago something happened for options like `--older-than`,
+/// e.g. `30d`, `2 weeks` or `12h`.
+pub fn parse_age(s: &str) -> Result<Duration, String> {
+    parse_duration(s.trim())
+        .ok_or_else(|| format!("'{}' is not an amount of time, expected e.g. 30d", s))
+}
+
+/// The time `age` before now, for the cutoff of options like `--older-than`;
+/// ages reaching back further than dates go are an error.
+pub fn ago(age: Duration) -> Result<DateTime<Local>, String> {
+    Local::now()
+        .checked_sub_signed(age)
+        .ok_or_else(|| format!("{} ago is too long ago for a date", format_duration(age)))
+}
+
+/// Parses an amount of time like `2 days`, `1 week` or `30m`.
+fn parse_duration(s: &str) -> Option<Duration> {
+    let unit_start = s.find(|c: char| !c.is_ascii_digit())?;
+    let amount: i64 = s[..unit_start].parse().ok()?;
+
+    let unit_seconds = match s[unit_start..].trim() {
+        "m" | "min" | "mins" | "minute" | "minutes" => 60,
+        "h" | "hour" | "hours" => 60 * 60,
+        "d" | "day" | "days" => 24 * 60 * 60,
+        "w" | "week" | "weeks" => 7 * 24 * 60 * 60,
+        _ => return None,
+    };
+
+    // Amounts too large for a duration are as unusable as unknown units
+    amount
+        .checked_mul(unit_seconds)
+        .filter(|&seconds| seconds <= Duration::max_value().num_seconds())
+        .map(Duration::seconds)
+}
+
+/// Formats a duration with its two largest units, e.g. `3d 4h` or `25m`.
+pub fn format_duration(duration: Duration) -> String {
+    let minutes = duration.num_minutes().max(0);
+    let (days, hours, minutes) = (minutes / (24 * 60), minutes / 60 % 24, minutes % 60);
+
+    if days > 0 {
+        format!("{}d {}h", days, hours)
+    } else if hours > 0 {
+        format!("{}h {}m", hours, minutes)
+    } else {
+        format!("{}m", minutes)
+    }
+}
+
+/// Formats how far `date` is from `now` in its largest unit, e.g. `2 days
+/// ago`, `in 3 h` or `just now`.
+pub fn format_relative(date: DateTime<Local>, now: DateTime<Local>) -> String {
+    let minutes = (date - now).num_minutes();
+    let distance = minutes.abs();
+    let plural = |amount: i64, unit: &str| {
+        if amount == 1 {
+            format!("1 {}", unit)
+        } else {
+            format!("{} {}s", amount, unit)
+        }
+    };
+
+    let amount = match distance {
+        0 => return "just now".to_string(),
+        1..=59 => format!("{} min", distance),
+        60..=1439 => format!("{} h", distance / 60),
+        1440..=20159 => plural(distance / 1440, "day"),
+        20160..=86399 => plural(distance / 10080, "week"),
+        86400..=525599 => plural(distance / 43200, "month"),
+        _ => plural(distance / 525600, "year"),
+    };
+
+    if minutes < 0 {
+        format!("{} ago", amount)
+    } else {
+        format!("in {}", amount)
+    }
+}
+
+fn from_local(datetime: NaiveDateTime, input: &str) -> Result<DateTime<Local>, String> {
+    Local
+        .from_local_datetime(&datetime)
+        .earliest()
+        .ok_or_else(|| format!("'{}' does not exist in the local time zone", input))
+}
+
+/// Where the time zone database lives on Unix, in the order the time zone
+/// names given in `TZ` are looked up.
+const ZONE_INFO_DIRECTORIES: &[&str] = &[
+    "/usr/share/zoneinfo",
+    "/share/zoneinfo",
+    "/etc/zoneinfo",
+    "/usr/share/lib/zoneinfo",
+];
+
+/// Checks a time zone given on the command line, a name from the time zone
+/// database like `Europe/Berlin` or `UTC`.
+pub fn parse_time_zone(s: &str) -> Result<String, String> {
+    let s = s.trim();
+    let is_name = !s.is_empty()
+        && !s.starts_with('/')
+        && s.split('/').all(|part| !part.is_empty() && part != "..");
+    let tz_dir = std::env::var("TZDIR").ok();
+    let known = is_name
+        && tz_dir
+            .iter()
+            .map(String::as_str)
+            .chain(ZONE_INFO_DIRECTORIES.iter().copied())
+            .any(|dir| std::path::Path::new(dir).join(s).is_file());
+
+    if known {
+        Ok(s.to_string())
+    } else {
+        Err(format!(
+            "'{}' is not a known time zone, expected a name like Europe/Berlin or UTC",
+            s
+        ))
+    }
+}
+
+/// Stored times are written in UTC, so that a database reads the same on
+/// machines in other time zones. Times with any offset are read.
+pub mod utc {
+    use chrono::prelude::*;
+    use serde::{Serialize, Serializer};
+
+    pub fn serialize<S: Serializer>(
+        time: &DateTime<Local>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        time.with_timezone(&Utc).serialize(serializer)
+    }
+
+    pub fn serialize_option<S: Serializer>(
+        time: &Option<DateTime<Local>>,
+        serializer: S,
+    ) -> Result<S::Ok, S::Error> {
+        time.map(|time| time.with_timezone(&Utc))
+            .serialize(serializer)
+    }
+
+    /// The RFC 3339 text of the time in UTC, e.g. `2024-07-01T10:00:00Z`.
+    pub fn format(time: DateTime<Local>) -> String {
+        time.with_timezone(&Utc)
+            .to_rfc3339_opts(SecondsFormat::AutoSi, true)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A Wednesday afternoon
+    fn now() -> NaiveDateTime {
+        NaiveDate::from_ymd_opt(2024, 7, 3)
+            .and_then(|day| day.and_hms_opt(15, 0, 0))
+            .unwrap()
+    }
+
+    fn day(month: u32, day: u32) -> NaiveDate {
+        NaiveDate::from_ymd_opt(2024, month, day).unwrap()
+    }
+
+    fn at(day: NaiveDate, hour: u32, minute: u32) -> When {
+        When::Time(day.and_hms_opt(hour, minute, 0).unwrap())
+    }
+
+    #[test]
+    fn parses_days_in_words() {
+        assert_eq!(parse_words("tomorrow", now()), Ok(When::Day(day(7, 4))));
+        assert_eq!(parse_words("Wednesday", now()), Ok(When::Day(day(7, 3))));
+        assert_eq!(parse_words("next wed", now()), Ok(When::Day(day(7, 10))));
+        assert_eq!(parse_words("next week", now()), Ok(When::Day(day(7, 8))));
+        assert_eq!(parse_words("in 3 days", now()), Ok(When::Day(day(7, 6))));
+        assert_eq!(parse_words("in a month", now()), Ok(When::Day(day(8, 3))));
+    }
+
+    #[test]
+    fn parses_times_in_words() {
+        assert_eq!(
+            parse_words("next friday 5pm", now()),
+            Ok(at(day(7, 5), 17, 0))
+        );
+        assert_eq!(
+            parse_words("today at 9:30 am", now()),
+            Ok(at(day(7, 3), 9, 30))
+        );
+        assert_eq!(parse_words("noon", now()), Ok(at(day(7, 4), 12, 0)));
+        assert_eq!(parse_words("18:15", now()), Ok(at(day(7, 3), 18, 15)));
+        assert_eq!(parse_words("in 2 hours", now()), Ok(at(day(7, 3), 17, 0)));
+    }
+
+    #[test]
+    fn rejects_ambiguous_words() {
+        assert!(parse_words("friday 5", now())
+            .unwrap_err()
+            .contains("ambiguous"));
+        assert!(parse_words("03/04", now())
+            .unwrap_err()
+            .contains("ambiguous"));
+        assert!(parse_words("13pm", now()).is_err());
+        assert!(parse_words("someday", now()).is_err());
+        assert!(parse_words("in 99999999 weeks", now())
+            .unwrap_err()
+            .contains("too far"));
+    }
+
+    #[test]
+    fn rejects_amounts_of_time_out_of_range() {
+        assert_eq!(parse_age("2 weeks"), Ok(Duration::days(14)));
+        assert!(parse_age("99999999999999d").is_err());
+        assert!(parse_age("99999999999999999999m").is_err());
+    }
+}
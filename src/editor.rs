@@ -0,0 +1,28 @@
+use std::env;
+use std::fs;
+use std::io;
+use std::process::Command;
+
+/// Lets the user edit `text` in `$VISUAL` or `$EDITOR` (falling back to
+/// `vi`) and returns the edited text.
+pub fn edit_text(text: &str) -> io::Result<String> {
+    let editor = env::var("VISUAL")
+        .or_else(|_| env::var("EDITOR"))
+        .unwrap_or_else(|_| "vi".to_string());
+    // Allow editors with arguments, e.g. `code --wait`
+    let mut words = editor.split_whitespace();
+    let program = words.next().unwrap_or("vi");
+
+    let path = env::temp_dir().join(format!("rust-todo-{}.txt", std::process::id()));
+    fs::write(&path, text)?;
+
+    let status = Command::new(program).args(words).arg(&path).status();
+    let edited = fs::read_to_string(&path);
+    fs::remove_file(&path)?;
+
+    if !status?.success() {
+        return Err(io::Error::other(format!("{} exited with an error", editor)));
+    }
+
+    edited
+}
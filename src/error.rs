@@ -0,0 +1,83 @@
+use std::io;
+use std::path::PathBuf;
+use thiserror::Error;
+
+#[derive(Debug, Error)]
+pub enum TodoError {
+    /// No todo has the given id
+    #[error("Could not find Todo by id {0}")]
+    NotFound(u32),
+    /// No todo matches the given text
+    #[error("No todo matches '{0}'")]
+    NoMatch(String),
+    /// The database, the config or the input could not be parsed; `line` is
+    /// the 1-based line it happened on, when known
+    #[error("{}{reason}", line.map(|line| format!("line {}: ", line)).unwrap_or_default())]
+    Parse { line: Option<usize>, reason: String },
+    /// Reading or writing a file failed
+    #[error("{0}")]
+    Io(io::Error),
+    /// A remote service refused a request or could not be reached
+    #[error("{0}")]
+    Remote(String),
+    /// An error in a specific file, like the database or the config
+    #[error("{}: {source}", path.display())]
+    File {
+        path: PathBuf,
+        source: Box<TodoError>,
+    },
+}
+
+impl TodoError {
+    pub fn parse(reason: impl Into<String>) -> Self {
+        TodoError::Parse {
+            line: None,
+            reason: reason.into(),
+        }
+    }
+
+    /// Attributes the error to the file at `path`.
+    pub fn in_file(self, path: impl Into<PathBuf>) -> Self {
+        TodoError::File {
+            path: path.into(),
+            source: Box::new(self),
+        }
+    }
+
+    /// Exit code reported to the shell. 1 is left for unexpected failures
+    /// and 2 is what clap uses for invalid arguments.
+    pub fn exit_code(&self) -> u8 {
+        match self {
+            TodoError::NotFound(_) | TodoError::NoMatch(_) => 3,
+            TodoError::Parse { .. } => 4,
+            TodoError::Io(_) => 5,
+            TodoError::Remote(_) => 6,
+            TodoError::File { source, .. } => source.exit_code(),
+        }
+    }
+
+    /// Whether the error is stdout being closed early, e.g. by `todo list |
+    /// head`, which is not worth reporting.
+    pub fn is_broken_pipe(&self) -> bool {
+        match self {
+            TodoError::Io(e) => e.kind() == io::ErrorKind::BrokenPipe,
+            _ => false,
+        }
+    }
+}
+
+impl From<io::Error> for TodoError {
+    fn from(e: io::Error) -> Self {
+        // The storage backends report malformed data as `InvalidData`,
+        // possibly wrapping a `TodoError` that knows the line
+        if e.kind() != io::ErrorKind::InvalidData {
+            return TodoError::Io(e);
+        }
+
+        let reason = e.to_string();
+        match e.into_inner().map(|inner| inner.downcast::<TodoError>()) {
+            Some(Ok(error)) => *error,
+            _ => TodoError::parse(reason),
+        }
+    }
+}
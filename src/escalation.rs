@@ -0,0 +1,65 @@
+//! Escalating todos that stay overdue, by the `[[escalation]]` rules of the
+//! config. The rules are applied whenever the database is opened and on every
+//! check of `todo remind --daemon`.
+
+use crate::config::EscalationRule;
+use crate::database::Database;
+use crate::dates;
+use crate::error::TodoError;
+use crate::journal::Operation;
+use chrono::prelude::*;
+use chrono::Duration;
+
+/// Raises the priority of and adds the tags to every open todo overdue for
+/// at least as long as a rule says. Snoozed todos are left alone until they
+/// wake up. Saves the database if a todo changed.
+pub fn run(db: &mut Database, rules: &[EscalationRule]) -> Result<(), TodoError> {
+    if rules.is_empty() {
+        return Ok(());
+    }
+
+    let rules = rules
+        .iter()
+        .map(|rule| {
+            let overdue = dates::parse_age(&rule.overdue).map_err(|e| {
+                TodoError::parse(format!("`overdue` of an `[[escalation]]` rule: {}", e))
+            })?;
+            Ok((overdue, rule))
+        })
+        .collect::<Result<Vec<(Duration, &EscalationRule)>, TodoError>>()?;
+
+    let now = Local::now();
+    let mut escalated = Vec::new();
+    for todo in db
+        .todos
+        .iter()
+        .filter(|t| t.is_overdue(now) && !t.is_snoozed(now))
+    {
+        let Some(due_at) = todo.due_at else {
+            continue;
+        };
+
+        let mut changed = todo.clone();
+        for (_, rule) in rules.iter().filter(|(overdue, _)| due_at + *overdue <= now) {
+            if let Some(priority) = rule.priority {
+                changed.priority = changed.priority.max(priority);
+            }
+            for tag in &rule.tags {
+                changed.add_tag(tag.trim_start_matches('+'));
+            }
+        }
+        if changed.priority != todo.priority || changed.tags != todo.tags {
+            escalated.push(changed);
+        }
+    }
+    if escalated.is_empty() {
+        return Ok(());
+    }
+
+    for todo in escalated {
+        log::info!("Escalating the overdue todo {}: {}", todo.id, todo.text);
+        db.apply(Operation::Update { todo })?;
+    }
+
+    Ok(db.save()?)
+}
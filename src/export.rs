@@ -0,0 +1,172 @@
+//! Exports of the whole list in formats meant for other tools.
+
+use crate::group::{self, Group, GroupBy};
+use crate::ical;
+use crate::taskwarrior;
+use crate::todo::{Priority, Status, Todo};
+use chrono::prelude::*;
+use clap::ValueEnum;
+use std::io::{self, Write};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum ExportFormat {
+    /// Checklist with one section per group, e.g. for issues and READMEs
+    Markdown,
+    /// iCalendar with the todos that have a due date, as tasks and as events
+    /// on the due date, e.g. to subscribe to from a calendar app
+    Ics,
+    /// JSON for `task import`, to move the todos to Taskwarrior
+    Taskwarrior,
+    /// Emacs org-mode outline with a TODO heading per todo under one per
+    /// group
+    Org,
+}
+
+pub fn write_todos(
+    out: &mut dyn Write,
+    todos: &[Todo],
+    format: ExportFormat,
+    group_by: GroupBy,
+) -> io::Result<()> {
+    match format {
+        ExportFormat::Markdown => {
+            let todos: Vec<&Todo> = todos.iter().collect();
+            write_markdown(out, &group::groups(&todos, group_by))
+        }
+        // Calendars have no sections
+        ExportFormat::Ics => out.write_all(ical::due_calendar(todos).as_bytes()),
+        ExportFormat::Taskwarrior => taskwarrior::write_tasks(out, todos),
+        ExportFormat::Org => {
+            let todos: Vec<&Todo> = todos.iter().collect();
+            write_org(out, &group::groups(&todos, group_by))
+        }
+    }
+}
+
+/// Closed todos are checked; cancelled ones are also struck through.
+fn write_markdown(out: &mut dyn Write, groups: &[Group]) -> io::Result<()> {
+    for (i, (heading, todos)) in groups.iter().enumerate() {
+        if i > 0 {
+            writeln!(out)?;
+        }
+        writeln!(out, "## {}", escape_markdown(heading))?;
+        writeln!(out)?;
+
+        for todo in todos {
+            let check = if todo.status.is_closed() { 'x' } else { ' ' };
+            let mut text = escape_markdown(&todo.text);
+            if todo.status == Status::Cancelled {
+                text = format!("~~{}~~", text);
+            }
+            for tag in &todo.tags {
+                text.push_str(&format!(" `+{}`", tag));
+            }
+            if let Some(due_at) = todo.due_at {
+                text.push_str(&format!(" (due {})", due_at.format("%Y-%m-%d")));
+            }
+
+            writeln!(out, "- [{}] {}", check, text)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// The statuses become keywords declared in the `#+TODO:` line, due dates
+/// deadlines and completions `CLOSED:` timestamps; the notes are the body of
+/// the heading.
+fn write_org(out: &mut dyn Write, groups: &[Group]) -> io::Result<()> {
+    writeln!(out, "#+TODO: TODO STARTED WAITING | DONE CANCELLED")?;
+
+    for (heading, todos) in groups {
+        writeln!(out)?;
+        writeln!(out, "* {}", heading)?;
+
+        for todo in todos {
+            let keyword = match todo.status {
+                Status::Open => "TODO",
+                Status::InProgress => "STARTED",
+                Status::Blocked => "WAITING",
+                Status::Done => "DONE",
+                Status::Cancelled => "CANCELLED",
+            };
+            let mut line = format!("** {}", keyword);
+            let cookie = match todo.priority {
+                Priority::High => Some('A'),
+                Priority::Medium => Some('B'),
+                Priority::Low => Some('C'),
+                Priority::None => None,
+            };
+            if let Some(cookie) = cookie {
+                line.push_str(&format!(" [#{}]", cookie));
+            }
+            line.push(' ');
+            line.push_str(&todo.text);
+            if !todo.tags.is_empty() {
+                let tags: Vec<String> = todo.tags.iter().map(|tag| org_tag(tag)).collect();
+                line.push_str(&format!(" :{}:", tags.join(":")));
+            }
+            writeln!(out, "{}", line)?;
+
+            let mut planning = Vec::new();
+            if let (Some(completed_at), true) = (todo.completed_at, todo.status.is_closed()) {
+                planning.push(format!("CLOSED: [{}]", org_time(completed_at)));
+            }
+            if let Some(due_at) = todo.due_at {
+                planning.push(format!("DEADLINE: <{}>", org_time(due_at)));
+            }
+            if !planning.is_empty() {
+                writeln!(out, "   {}", planning.join(" "))?;
+            }
+            for line in todo.notes.lines() {
+                // A line starting with `*` would be read as a heading
+                let line = if line.starts_with('*') {
+                    format!(",{}", line)
+                } else {
+                    line.to_string()
+                };
+                writeln!(out, "   {}", line)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+/// Org tags may only contain letters, digits, `_`, `@`, `#` and `%`.
+fn org_tag(tag: &str) -> String {
+    tag.chars()
+        .map(|c| {
+            if c.is_alphanumeric() || matches!(c, '_' | '@' | '#' | '%') {
+                c
+            } else {
+                '_'
+            }
+        })
+        .collect()
+}
+
+/// A timestamp without the time for due dates given as a day.
+fn org_time(time: DateTime<Local>) -> String {
+    if Some(time.time()) == NaiveTime::from_hms_opt(23, 59, 59) {
+        time.format("%Y-%m-%d %a").to_string()
+    } else {
+        time.format("%Y-%m-%d %a %H:%M").to_string()
+    }
+}
+
+/// Keeps characters with a meaning in Markdown from formatting the text.
+fn escape_markdown(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        if matches!(
+            c,
+            '\\' | '`' | '*' | '_' | '[' | ']' | '<' | '>' | '#' | '~' | '|'
+        ) {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+
+    escaped
+}
@@ -0,0 +1,227 @@
+use crate::query::Expr;
+use crate::todo::{Priority, Status, Todo};
+use chrono::prelude::*;
+use regex::Regex;
+use std::str::FromStr;
+
+/// Which todos a listing shows. Every set criterion has to match.
+#[derive(Debug, Default, Clone)]
+pub struct Filter {
+    /// Hide done and cancelled todos
+    pub only_open: bool,
+    /// Hide todos snoozed or scheduled until later
+    pub hide_deferred: bool,
+    /// Statuses of which one has to match, if any are given
+    pub statuses: Vec<Status>,
+    /// Tags the todo needs to have, without the leading `+`
+    pub tags: Vec<String>,
+    /// Tags the todo must not have
+    pub not_tags: Vec<String>,
+    /// Only todos past their due date
+    pub overdue: bool,
+    /// Only todos without a priority
+    pub no_priority: bool,
+    /// Filter expression the todo needs to match
+    pub expression: Option<Expr>,
+    /// Only todos changed at or after this time
+    pub modified_since: Option<DateTime<Local>>,
+    /// Only todos created at or after this time
+    pub created_after: Option<DateTime<Local>>,
+    /// Only todos created before this time
+    pub created_before: Option<DateTime<Local>>,
+    /// Only todos completed at or after this time
+    pub completed_after: Option<DateTime<Local>>,
+    /// Only todos completed before this time
+    pub completed_before: Option<DateTime<Local>>,
+    /// Only done or cancelled todos closed before this time; cancelling is
+    /// not tracked, so for those it is their last change
+    pub closed_before: Option<DateTime<Local>>,
+    /// Custom fields the todo needs to have
+    pub fields: Vec<FieldFilter>,
+    /// Lowercase text the text or the notes need to contain
+    pub text: Option<String>,
+    /// Pattern the text needs to match
+    pub regex: Option<Regex>,
+}
+
+impl Filter {
+    pub fn open() -> Self {
+        Filter {
+            only_open: true,
+            ..Filter::default()
+        }
+    }
+
+    /// Todos containing `query` in their text or notes, ignoring case.
+    pub fn search(query: &str) -> Self {
+        Filter {
+            text: Some(query.to_lowercase()),
+            ..Filter::default()
+        }
+    }
+
+    pub fn matches(&self, todo: &Todo) -> bool {
+        if self.only_open && todo.status.is_closed() {
+            return false;
+        }
+
+        if self.hide_deferred && todo.is_deferred(Local::now()) {
+            return false;
+        }
+
+        if !self.statuses.is_empty() && !self.statuses.contains(&todo.status) {
+            return false;
+        }
+
+        if !self.tags.iter().all(|tag| todo.has_tag(tag))
+            || self.not_tags.iter().any(|tag| todo.has_tag(tag))
+        {
+            return false;
+        }
+
+        if self.overdue && !todo.is_overdue(Local::now()) {
+            return false;
+        }
+
+        if self.no_priority && todo.priority != Priority::None {
+            return false;
+        }
+
+        if self
+            .modified_since
+            .is_some_and(|since| todo.updated_at < since)
+        {
+            return false;
+        }
+
+        if !in_range(
+            Some(todo.created_at),
+            self.created_after,
+            self.created_before,
+        ) {
+            return false;
+        }
+
+        let completed_at = todo.completed_at.filter(|_| todo.is_completed());
+        if !in_range(completed_at, self.completed_after, self.completed_before) {
+            return false;
+        }
+        let closed_at = todo
+            .status
+            .is_closed()
+            .then(|| completed_at.unwrap_or(todo.updated_at));
+        if !in_range(closed_at, None, self.closed_before) {
+            return false;
+        }
+
+        if !self.fields.iter().all(|field| field.matches(todo)) {
+            return false;
+        }
+
+        if self
+            .regex
+            .as_ref()
+            .is_some_and(|regex| !regex.is_match(&todo.text))
+        {
+            return false;
+        }
+
+        if let Some(text) = &self.text {
+            let contains = |s: &str| s.to_lowercase().contains(text.as_str());
+            if !contains(&todo.text) && !contains(&todo.notes) {
+                return false;
+            }
+        }
+
+        self.expression
+            .as_ref()
+            .is_none_or(|expression| expression.matches(todo))
+    }
+}
+
+/// Whether `date` is within the bounds that are set; without a date only no
+/// bounds match.
+fn in_range(
+    date: Option<DateTime<Local>>,
+    after: Option<DateTime<Local>>,
+    before: Option<DateTime<Local>>,
+) -> bool {
+    match date {
+        Some(date) => {
+            after.is_none_or(|after| date >= after) && before.is_none_or(|before| date < before)
+        }
+        None => after.is_none() && before.is_none(),
+    }
+}
+
+/// `key` to match todos that have a custom field, `key:value` to match those
+/// where it has that value.
+#[derive(Debug, Clone)]
+pub struct FieldFilter {
+    pub key: String,
+    pub value: Option<String>,
+}
+
+impl FieldFilter {
+    pub fn matches(&self, todo: &Todo) -> bool {
+        match (todo.fields.get(&self.key), &self.value) {
+            (Some(actual), Some(value)) => actual.eq_ignore_ascii_case(value),
+            (Some(_), None) => true,
+            (None, _) => false,
+        }
+    }
+}
+
+impl FromStr for FieldFilter {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (key, value) = if s.contains(':') {
+            parse_field(s)?
+        } else {
+            parse_field(&format!("{}:", s))?
+        };
+
+        Ok(FieldFilter {
+            key,
+            value: Some(value).filter(|v| !v.is_empty()),
+        })
+    }
+}
+
+/// Parses a `key:value` argument into the lowercase key and the value, which
+/// may be empty.
+pub fn parse_field(s: &str) -> Result<(String, String), String> {
+    let valid_key = |key: &str| {
+        !key.is_empty()
+            && key
+                .chars()
+                .all(|c| c.is_alphanumeric() || c == '-' || c == '_')
+    };
+
+    match s.split_once(':') {
+        Some((key, value)) if valid_key(key) && !value.contains(char::is_whitespace) => {
+            Ok((key.to_lowercase(), value.to_string()))
+        }
+        _ => Err(format!("'{}' is not a field, expected key:value", s)),
+    }
+}
+
+/// Parses a `--regex` argument; the error points at what is wrong with the
+/// pattern.
+pub fn parse_regex(s: &str) -> Result<Regex, String> {
+    Regex::new(s).map_err(|e| e.to_string())
+}
+
+/// Parses the tag of `--tag`, with or without the leading `+`.
+pub fn parse_tag_name(s: &str) -> Result<String, String> {
+    parse_tag(&format!("+{}", s.strip_prefix('+').unwrap_or(s)))
+}
+
+/// Parses a `+tag` argument.
+pub fn parse_tag(s: &str) -> Result<String, String> {
+    match s.strip_prefix('+') {
+        Some(tag) if !tag.is_empty() && !tag.contains(char::is_whitespace) => Ok(tag.to_string()),
+        _ => Err(format!("'{}' is not a tag, expected +tag", s)),
+    }
+}
@@ -0,0 +1,250 @@
+//! Sharing the todos between machines through a git repository, for `todo
+//! sync git`.
+
+use crate::config::GitConfig;
+use crate::database::Database;
+use crate::error::TodoError;
+use crate::journal::Operation;
+use crate::output;
+use crate::todo::Todo;
+use serde_json::Value;
+use std::collections::{HashMap, HashSet};
+use std::fs;
+use std::io;
+use std::path::Path;
+use std::process::{Command, Output};
+use uuid::Uuid;
+
+/// The todos in the repository, one JSON object per line so that changes to
+/// different todos touch different lines.
+const FILE_NAME: &str = "todos.jsonl";
+
+/// Merges the todos of the repository's upstream branch with the local ones,
+/// commits the result and pushes it. The merge is done per todo: a todo
+/// changed on one side only takes that change, one changed on both the newer
+/// change, and deleting a todo wins over leaving it unchanged.
+pub fn sync(db: &mut Database, config: &GitConfig) -> Result<(), TodoError> {
+    let repo = Repository(&config.repository);
+    repo.run(&["rev-parse", "--git-dir"])?;
+
+    let has_remote = !repo.run(&["remote"])?.trim().is_empty();
+    if has_remote {
+        repo.run(&["fetch", "--quiet"])?;
+    }
+    let upstream = repo
+        .run(&["rev-parse", "--verify", "--quiet", "@{upstream}"])
+        .ok()
+        .map(|rev| rev.trim().to_string());
+
+    let mut taken_over = 0;
+    if let Some(upstream) = &upstream {
+        let theirs = repo.load(upstream)?.unwrap_or_default();
+        let base = match repo.run(&["merge-base", "HEAD", upstream]) {
+            Ok(base) => repo.load(base.trim())?.unwrap_or_default(),
+            Err(_) => Vec::new(),
+        };
+        let ours: Vec<Todo> = db.todos.iter().chain(&db.trash).cloned().collect();
+
+        taken_over = take_over(db, merge(&base, &ours, &theirs))?;
+        if !repo.succeeds(&["merge-base", "--is-ancestor", upstream, "HEAD"])? {
+            // The merged todos are committed below, so only the history of
+            // the upstream branch is needed
+            repo.run(&["merge", "--quiet", "--no-edit", "-s", "ours", upstream])?;
+        }
+    }
+
+    let mut todos: Vec<&Todo> = db.todos.iter().chain(&db.trash).collect();
+    // The ids can differ between machines, the uuids and creation dates not
+    todos.sort_by_key(|t| (t.created_at, t.uuid));
+    let mut contents = String::new();
+    for todo in todos {
+        contents.push_str(&serde_json::to_string(todo).map_err(io::Error::from)?);
+        contents.push('\n');
+    }
+    fs::write(config.repository.join(FILE_NAME), contents)?;
+
+    repo.run(&["add", FILE_NAME])?;
+    let changed = !repo.succeeds(&["diff", "--cached", "--quiet"])?;
+    if changed {
+        repo.run(&["commit", "--quiet", "-m", "Update todos"])?;
+    }
+    if has_remote {
+        match upstream {
+            Some(_) => repo.run(&["push", "--quiet"])?,
+            None => repo.run(&["push", "--quiet", "--set-upstream", "origin", "HEAD"])?,
+        };
+    }
+
+    if changed {
+        output::status(format!(
+            "Took over {} change(s) and committed the todos",
+            taken_over
+        ));
+    } else {
+        output::status("The todos are up to date");
+    }
+
+    Ok(db.save()?)
+}
+
+struct Repository<'a>(&'a Path);
+
+impl Repository<'_> {
+    fn command(&self, args: &[&str]) -> Result<Output, TodoError> {
+        Command::new("git")
+            .arg("-C")
+            .arg(self.0)
+            .args(args)
+            .output()
+            .map_err(|e| TodoError::Remote(format!("could not run git: {}", e)))
+    }
+
+    /// Runs git and returns its output, failing when it does.
+    fn run(&self, args: &[&str]) -> Result<String, TodoError> {
+        let output = self.command(args)?;
+        if !output.status.success() {
+            return Err(TodoError::Remote(format!(
+                "git {} failed in {}: {}",
+                args.join(" "),
+                self.0.display(),
+                String::from_utf8_lossy(&output.stderr).trim()
+            )));
+        }
+
+        Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+    }
+
+    /// Runs git for a yes or no answer, like `git diff --quiet`.
+    fn succeeds(&self, args: &[&str]) -> Result<bool, TodoError> {
+        Ok(self.command(args)?.status.success())
+    }
+
+    /// The todos committed in `rev`; `None` when it has none.
+    fn load(&self, rev: &str) -> Result<Option<Vec<Todo>>, TodoError> {
+        let Ok(contents) = self.run(&["show", &format!("{}:{}", rev, FILE_NAME)]) else {
+            return Ok(None);
+        };
+
+        let todos = contents
+            .lines()
+            .filter(|line| !line.trim().is_empty())
+            .enumerate()
+            .map(|(index, line)| {
+                serde_json::from_str(line).map_err(|e| TodoError::Parse {
+                    line: Some(index + 1),
+                    reason: format!("{} in {}:{}", e, rev, FILE_NAME),
+                })
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(Some(todos))
+    }
+}
+
+/// Whether two versions of a todo say the same, apart from the id, which is
+/// local to each machine, and when they were saved.
+fn same(a: &Todo, b: &Todo) -> bool {
+    let comparable = |todo: &Todo| {
+        let mut value = serde_json::to_value(todo).unwrap_or(Value::Null);
+        if let Some(object) = value.as_object_mut() {
+            object.remove("id");
+            object.remove("updated_at");
+        }
+        value
+    };
+
+    comparable(a) == comparable(b)
+}
+
+/// Merges the todos from `ours` and `theirs`, both based on `base`.
+fn merge(base: &[Todo], ours: &[Todo], theirs: &[Todo]) -> Vec<Todo> {
+    let by_uuid = |todos: &[Todo]| -> HashMap<Uuid, Todo> {
+        todos.iter().map(|t| (t.uuid, t.clone())).collect()
+    };
+    let (base, mut theirs_by_uuid) = (by_uuid(base), by_uuid(theirs));
+
+    let mut merged = Vec::new();
+    for mine in ours {
+        let base = base.get(&mine.uuid);
+        let todo = match (theirs_by_uuid.remove(&mine.uuid), base) {
+            (Some(theirs), Some(base)) if same(base, mine) => Some(theirs),
+            (Some(theirs), _) if same(&theirs, mine) || theirs.updated_at <= mine.updated_at => {
+                Some(mine.clone())
+            }
+            (Some(theirs), _) => Some(theirs),
+            // Deleted there
+            (None, Some(base)) if same(base, mine) => None,
+            (None, _) => Some(mine.clone()),
+        };
+        merged.extend(todo);
+    }
+    for theirs in theirs {
+        let Some(todo) = theirs_by_uuid.remove(&theirs.uuid) else {
+            continue;
+        };
+        // Kept unless it was deleted here without being changed there
+        if !base.get(&todo.uuid).is_some_and(|base| same(base, &todo)) {
+            merged.push(todo);
+        }
+    }
+
+    merged
+}
+
+/// Changes the database to hold the merged todos. Returns how many todos
+/// changed.
+fn take_over(db: &mut Database, merged: Vec<Todo>) -> Result<usize, TodoError> {
+    let local: HashMap<Uuid, Todo> = db
+        .todos
+        .iter()
+        .chain(&db.trash)
+        .map(|t| (t.uuid, t.clone()))
+        .collect();
+    let kept: HashSet<Uuid> = merged.iter().map(|t| t.uuid).collect();
+    let mut changed = 0;
+
+    for mut todo in merged {
+        let mine = local.get(&todo.uuid);
+        if mine.is_some_and(|mine| same(mine, &todo)) {
+            continue;
+        }
+
+        // Trashed todos are changed like the others and then moved back
+        let deleted_at = todo.deleted_at.take();
+        let id = match mine {
+            Some(mine) => {
+                todo.id = mine.id;
+                if mine.deleted_at.is_some() {
+                    db.apply(Operation::Restore { id: mine.id })?;
+                }
+                db.apply(Operation::Update { todo })?;
+                mine.id
+            }
+            None => {
+                // Todos created on another machine can have an id in use here
+                let in_use = db.todos.iter().chain(&db.trash).any(|t| t.id == todo.id);
+                if in_use {
+                    db.metadata.seq_id += 1;
+                    todo.id = db.metadata.seq_id;
+                }
+                let id = todo.id;
+                db.apply(Operation::Add { todo })?;
+                id
+            }
+        };
+        if let Some(deleted_at) = deleted_at {
+            db.apply(Operation::Delete { id, deleted_at })?;
+        }
+        changed += 1;
+    }
+
+    for mine in local.values().filter(|t| !kept.contains(&t.uuid)) {
+        if mine.deleted_at.is_none() {
+            db.apply(Operation::delete(mine.id))?;
+        }
+        db.apply(Operation::Purge { id: mine.id })?;
+        changed += 1;
+    }
+
+    Ok(changed)
+}
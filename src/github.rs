@@ -0,0 +1,229 @@
+//! Todos for GitHub issues, for `todo import github` and `todo done
+//! --close-issues`.
+
+use crate::config::GithubConfig;
+use crate::database::Database;
+use crate::error::TodoError;
+use crate::journal::Operation;
+use crate::output;
+use crate::todo;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashSet;
+use std::time::Duration;
+
+const API_URL: &str = "https://api.github.com";
+
+/// The custom field of an imported todo holding the URL of its issue.
+const FIELD: &str = "github";
+
+/// How many issues are asked for at once, the most GitHub returns.
+const PAGE_SIZE: usize = 100;
+
+/// An issue as GitHub returns it, with the parts that are imported.
+#[derive(Deserialize)]
+struct Issue {
+    number: u64,
+    title: String,
+    html_url: String,
+    #[serde(default)]
+    body: Option<String>,
+    /// Set when the issue is a pull request, which GitHub lists as issues too
+    #[serde(default)]
+    pull_request: Option<serde_json::Value>,
+}
+
+#[derive(Deserialize)]
+struct User {
+    login: String,
+}
+
+struct Client {
+    agent: ureq::Agent,
+    authorization: Option<String>,
+}
+
+impl Client {
+    fn new(config: Option<&GithubConfig>) -> Self {
+        let agent = ureq::Agent::config_builder()
+            .timeout_global(Some(Duration::from_secs(30)))
+            .build()
+            .into();
+
+        Client {
+            agent,
+            authorization: config
+                .and_then(|config| config.token.as_ref())
+                .map(|token| format!("Bearer {}", token)),
+        }
+    }
+
+    fn get<T: serde::de::DeserializeOwned>(
+        &self,
+        path: &str,
+        query: &[(&str, &str)],
+    ) -> Result<T, TodoError> {
+        let mut request = self
+            .agent
+            .get(format!("{}/{}", API_URL, path))
+            .header("Accept", "application/vnd.github+json");
+        if let Some(authorization) = &self.authorization {
+            request = request.header("Authorization", authorization);
+        }
+
+        request
+            .query_pairs(query.iter().copied())
+            .call()
+            .and_then(|mut response| response.body_mut().read_json())
+            .map_err(remote_error)
+    }
+
+    /// The login of the user the token belongs to.
+    fn me(&self) -> Result<String, TodoError> {
+        if self.authorization.is_none() {
+            return Err(TodoError::parse(
+                "set a GitHub token as `token` under `[github]` in the config to use `--assignee me`",
+            ));
+        }
+
+        Ok(self.get::<User>("user", &[])?.login)
+    }
+
+    /// The open issues of the repository, without pull requests.
+    fn open_issues(&self, repo: &str, assignee: Option<&str>) -> Result<Vec<Issue>, TodoError> {
+        let per_page = PAGE_SIZE.to_string();
+        let mut issues = Vec::new();
+        for page in 1.. {
+            let page = page.to_string();
+            let mut query = vec![
+                ("state", "open"),
+                ("per_page", per_page.as_str()),
+                ("page", page.as_str()),
+            ];
+            if let Some(assignee) = assignee {
+                query.push(("assignee", assignee));
+            }
+
+            let batch: Vec<Issue> = self.get(&format!("repos/{}/issues", repo), &query)?;
+            let last = batch.len() < PAGE_SIZE;
+            issues.extend(batch.into_iter().filter(|i| i.pull_request.is_none()));
+            if last {
+                break;
+            }
+        }
+
+        Ok(issues)
+    }
+
+    fn close(&self, repo: &str, number: &str) -> Result<(), TodoError> {
+        let Some(authorization) = &self.authorization else {
+            return Err(TodoError::parse(
+                "set a GitHub token as `token` under `[github]` in the config to close issues",
+            ));
+        };
+
+        self.agent
+            .patch(format!("{}/repos/{}/issues/{}", API_URL, repo, number))
+            .header("Accept", "application/vnd.github+json")
+            .header("Authorization", authorization)
+            .send_json(json!({ "state": "closed", "state_reason": "completed" }))
+            .map(drop)
+            .map_err(remote_error)
+    }
+}
+
+fn remote_error(e: ureq::Error) -> TodoError {
+    TodoError::Remote(format!("GitHub: {}", e))
+}
+
+/// Checks a repository given as `owner/name`.
+pub fn parse_repo(repo: &str) -> Result<String, String> {
+    match repo.split_once('/') {
+        Some((owner, name)) if !owner.is_empty() && !name.is_empty() && !name.contains('/') => {
+            Ok(repo.to_string())
+        }
+        _ => Err(format!(
+            "expected the repository as owner/name, not '{}'",
+            repo
+        )),
+    }
+}
+
+/// Creates a todo for every open issue of `repo` that has none yet, keeping
+/// the issue's URL in the `github` field. `me` as the assignee stands for
+/// the owner of the token.
+pub fn import(
+    db: &mut Database,
+    config: Option<&GithubConfig>,
+    repo: &str,
+    assignee: Option<&str>,
+) -> Result<(), TodoError> {
+    let client = Client::new(config);
+    let assignee = match assignee {
+        Some("me") => Some(client.me()?),
+        assignee => assignee.map(str::to_string),
+    };
+
+    // Deleted todos count too, so that issues dismissed here stay away
+    let imported: HashSet<String> = db
+        .todos
+        .iter()
+        .chain(&db.trash)
+        .filter_map(|t| t.fields.get(FIELD).cloned())
+        .collect();
+
+    let mut count = 0;
+    for issue in client.open_issues(repo, assignee.as_deref())? {
+        if imported.contains(&issue.html_url) {
+            continue;
+        }
+
+        let title: String = issue.title.chars().take(db.max_text_length).collect();
+        let text = todo::sanitize_text(&title, db.max_text_length)
+            .unwrap_or_else(|_| format!("{}#{}", repo, issue.number));
+        let mut todo = db.new_todo(&text)?;
+        todo.notes = issue.body.unwrap_or_default().replace("\r\n", "\n");
+        todo.fields.insert(FIELD.to_string(), issue.html_url);
+        db.apply(Operation::Add { todo })?;
+        count += 1;
+    }
+    output::status(format!("Imported {} issue(s) from {}", count, repo));
+
+    Ok(db.save()?)
+}
+
+/// Closes the issues linked to the todos with the ids, skipping those
+/// without an issue.
+pub fn close_issues(db: &Database, config: &GithubConfig, ids: &[u32]) -> Result<(), TodoError> {
+    let client = Client::new(Some(config));
+    for todo in db.todos.iter().filter(|t| ids.contains(&t.id)) {
+        let Some(url) = todo.fields.get(FIELD) else {
+            continue;
+        };
+        let Some((repo, number)) = issue_of(url) else {
+            return Err(TodoError::parse(format!(
+                "todo {} links to '{}', which is not a GitHub issue",
+                todo.id, url
+            )));
+        };
+
+        client.close(&repo, number)?;
+        output::status(format!("Closed {}#{}", repo, number));
+    }
+
+    Ok(())
+}
+
+/// The repository and number of an issue URL like
+/// `https://github.com/owner/name/issues/12`.
+fn issue_of(url: &str) -> Option<(String, &str)> {
+    let path = url.strip_prefix("https://github.com/")?;
+    let mut parts = path.split('/');
+    let (owner, name, kind, number) = (parts.next()?, parts.next()?, parts.next()?, parts.next()?);
+    let valid = kind == "issues"
+        && !number.is_empty()
+        && number.chars().all(|c| c.is_ascii_digit())
+        && parts.next().is_none();
+
+    valid.then(|| (format!("{}/{}", owner, name), number))
+}
@@ -0,0 +1,108 @@
+//! Splitting lists into sections, for `--group-by`.
+
+use crate::todo::{Status, Todo};
+use chrono::prelude::*;
+use chrono::IsoWeek;
+use clap::ValueEnum;
+use std::cmp::Reverse;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum GroupBy {
+    Status,
+    /// Todos with several tags are listed under each of them
+    Tag,
+    /// The custom field `project`
+    Project,
+    /// The week the todos were created in, the most recent first
+    CreatedWeek,
+}
+
+/// A section of a list: its heading and the todos in it.
+pub type Group<'a> = (String, Vec<&'a Todo>);
+
+/// Splits `todos` into the non-empty groups in a stable order: statuses from
+/// open to cancelled, tags and projects alphabetically with the todos
+/// without one last. Within a group the order of `todos` is kept.
+pub fn groups<'a>(todos: &[&'a Todo], group_by: GroupBy) -> Vec<Group<'a>> {
+    match group_by {
+        GroupBy::Status => Status::value_variants()
+            .iter()
+            .map(|&status| {
+                let todos = todos.iter().copied().filter(|t| t.status == status);
+                (status_heading(status).to_string(), todos.collect())
+            })
+            .filter(|(_, todos): &Group| !todos.is_empty())
+            .collect(),
+        GroupBy::Tag => by_keys(
+            todos,
+            |todo| todo.tags.iter().map(|tag| format!("+{}", tag)).collect(),
+            "Untagged",
+        ),
+        GroupBy::Project => by_keys(
+            todos,
+            |todo| todo.fields.get("project").cloned().into_iter().collect(),
+            "No project",
+        ),
+        GroupBy::CreatedWeek => {
+            let mut weeks: Vec<IsoWeek> = todos.iter().map(|t| t.created_at.iso_week()).collect();
+            weeks.sort_unstable_by_key(|&week| Reverse(week));
+            weeks.dedup();
+
+            weeks
+                .into_iter()
+                .map(|week| {
+                    let todos = todos
+                        .iter()
+                        .copied()
+                        .filter(|t| t.created_at.iso_week() == week);
+                    let heading = format!("Week {}, {}", week.week(), week.year());
+                    (heading, todos.collect())
+                })
+                .collect()
+        }
+    }
+}
+
+/// Groups by the sorted keys of the todos, with the todos without any key in
+/// a last group called `none`.
+fn by_keys<'a>(
+    todos: &[&'a Todo],
+    keys: impl Fn(&Todo) -> Vec<String>,
+    none: &str,
+) -> Vec<Group<'a>> {
+    let mut all_keys: Vec<String> = todos.iter().flat_map(|t| keys(t)).collect();
+    all_keys.sort_unstable();
+    all_keys.dedup();
+
+    let mut groups: Vec<Group> = all_keys
+        .into_iter()
+        .map(|key| {
+            let todos = todos
+                .iter()
+                .copied()
+                .filter(|t| keys(t).contains(&key))
+                .collect();
+            (key, todos)
+        })
+        .collect();
+    let without: Vec<&Todo> = todos
+        .iter()
+        .copied()
+        .filter(|t| keys(t).is_empty())
+        .collect();
+    if !without.is_empty() {
+        groups.push((none.to_string(), without));
+    }
+
+    groups
+}
+
+fn status_heading(status: Status) -> &'static str {
+    match status {
+        Status::Open => "Open",
+        Status::InProgress => "In progress",
+        Status::Blocked => "Blocked",
+        Status::Done => "Done",
+        Status::Cancelled => "Cancelled",
+    }
+}
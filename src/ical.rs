@@ -0,0 +1,409 @@
+//! The parts of iCalendar (RFC 5545) needed to exchange todos as VTODO
+//! components.
+
+use crate::todo::{Priority, Status, Todo};
+use chrono::prelude::*;
+
+/// A VTODO read from a calendar, together with its UID, which other apps do
+/// not necessarily make a UUID.
+pub struct Vtodo {
+    pub uid: String,
+    pub todo: Todo,
+}
+
+/// A calendar with the todo as its only component, under the given UID.
+pub fn calendar(todo: &Todo, uid: &str) -> String {
+    document(vtodo(todo, uid))
+}
+
+/// A calendar with a VTODO for every todo with a due date, for task apps,
+/// and a VEVENT on its due date, for the calendar apps that do not show
+/// tasks.
+pub fn due_calendar(todos: &[Todo]) -> String {
+    let mut components = vec!["X-WR-CALNAME:Todos".to_string()];
+    for todo in todos.iter().filter(|t| t.due_at.is_some()) {
+        let uid = todo.uuid.to_string();
+        components.extend(vtodo(todo, &uid));
+        components.extend(vevent(todo, &format!("{}-due", uid)));
+    }
+
+    document(components)
+}
+
+/// Wraps the lines of components into a calendar and folds them.
+fn document(components: Vec<String>) -> String {
+    let mut lines = vec![
+        "BEGIN:VCALENDAR".to_string(),
+        "VERSION:2.0".to_string(),
+        "PRODID:-//rust-todo//EN".to_string(),
+    ];
+    lines.extend(components);
+    lines.push("END:VCALENDAR".to_string());
+
+    lines.iter().map(|line| fold(line) + "\r\n").collect()
+}
+
+/// The lines of the VTODO describing the todo, without folding.
+pub fn vtodo(todo: &Todo, uid: &str) -> Vec<String> {
+    let mut lines = vec![
+        "BEGIN:VTODO".to_string(),
+        format!("UID:{}", uid),
+        format!("DTSTAMP:{}", format_time(Local::now())),
+        format!("CREATED:{}", format_time(todo.created_at)),
+        format!("LAST-MODIFIED:{}", format_time(todo.updated_at)),
+        format!("SUMMARY:{}", escape(&todo.text)),
+    ];
+    if !todo.notes.is_empty() {
+        lines.push(format!("DESCRIPTION:{}", escape(&todo.notes)));
+    }
+    if !todo.tags.is_empty() {
+        let tags: Vec<String> = todo.tags.iter().map(|tag| escape(tag)).collect();
+        lines.push(format!("CATEGORIES:{}", tags.join(",")));
+    }
+    let priority = match todo.priority {
+        Priority::High => Some(1),
+        Priority::Medium => Some(5),
+        Priority::Low => Some(9),
+        Priority::None => None,
+    };
+    if let Some(priority) = priority {
+        lines.push(format!("PRIORITY:{}", priority));
+    }
+    if let Some(due_at) = todo.due_at {
+        // Due dates given without a time are at the end of the day
+        if Some(due_at.time()) == NaiveTime::from_hms_opt(23, 59, 59) {
+            lines.push(format!("DUE;VALUE=DATE:{}", due_at.format("%Y%m%d")));
+        } else {
+            lines.push(format!("DUE:{}", format_time(due_at)));
+        }
+    }
+    if let Some(scheduled_at) = todo.scheduled_at {
+        // Todos scheduled for a day start at its beginning
+        if scheduled_at.time() == NaiveTime::MIN {
+            let day = scheduled_at.format("%Y%m%d");
+            lines.push(format!("DTSTART;VALUE=DATE:{}", day));
+        } else {
+            lines.push(format!("DTSTART:{}", format_time(scheduled_at)));
+        }
+    }
+    let status = match todo.status {
+        Status::Open | Status::Blocked => "NEEDS-ACTION",
+        Status::InProgress => "IN-PROCESS",
+        Status::Done => "COMPLETED",
+        Status::Cancelled => "CANCELLED",
+    };
+    lines.push(format!("STATUS:{}", status));
+    if let Some(completed_at) = todo.completed_at {
+        lines.push(format!("COMPLETED:{}", format_time(completed_at)));
+    }
+    lines.push("END:VTODO".to_string());
+
+    lines
+}
+
+/// The lines of a VEVENT at the due date of the todo, all day for dates
+/// without a time; none for todos without a due date.
+fn vevent(todo: &Todo, uid: &str) -> Vec<String> {
+    let Some(due_at) = todo.due_at else {
+        return Vec::new();
+    };
+
+    let mut lines = vec![
+        "BEGIN:VEVENT".to_string(),
+        format!("UID:{}", uid),
+        format!("DTSTAMP:{}", format_time(Local::now())),
+        format!("LAST-MODIFIED:{}", format_time(todo.updated_at)),
+        format!("SUMMARY:{}", escape(&todo.text)),
+    ];
+    if Some(due_at.time()) == NaiveTime::from_hms_opt(23, 59, 59) {
+        let day = due_at.date_naive();
+        lines.push(format!("DTSTART;VALUE=DATE:{}", day.format("%Y%m%d")));
+        if let Some(next_day) = day.succ_opt() {
+            lines.push(format!("DTEND;VALUE=DATE:{}", next_day.format("%Y%m%d")));
+        }
+    } else {
+        lines.push(format!("DTSTART:{}", format_time(due_at)));
+    }
+    if !todo.notes.is_empty() {
+        lines.push(format!("DESCRIPTION:{}", escape(&todo.notes)));
+    }
+    if todo.status == Status::Cancelled {
+        lines.push("STATUS:CANCELLED".to_string());
+    }
+    lines.push("TRANSP:TRANSPARENT".to_string());
+    lines.push("END:VEVENT".to_string());
+
+    lines
+}
+
+/// The VTODOs of a calendar. Their todos have no id yet; properties that
+/// cannot be read are skipped.
+pub fn parse(calendar: &str) -> Vec<Vtodo> {
+    let mut vtodos = Vec::new();
+    let mut current: Option<Vtodo> = None;
+
+    for line in unfold(calendar) {
+        let Some((name, params, value)) = split_line(&line) else {
+            continue;
+        };
+        match (name.as_str(), value) {
+            ("BEGIN", "VTODO") => {
+                current = Some(Vtodo {
+                    uid: String::new(),
+                    todo: Todo::new(0, ""),
+                })
+            }
+            ("END", "VTODO") => {
+                vtodos.extend(current.take().filter(|vtodo| !vtodo.uid.is_empty()));
+            }
+            (name, value) => {
+                if let Some(vtodo) = &mut current {
+                    set_property(vtodo, name, params, value);
+                }
+            }
+        }
+    }
+
+    vtodos
+}
+
+fn set_property(vtodo: &mut Vtodo, name: &str, params: &str, value: &str) {
+    let todo = &mut vtodo.todo;
+    let is_date = params.to_uppercase().contains("VALUE=DATE") && !value.contains('T');
+
+    match name {
+        "UID" => vtodo.uid = value.to_string(),
+        "SUMMARY" => todo.text = unescape(value),
+        "DESCRIPTION" => todo.notes = unescape(value),
+        "CATEGORIES" => {
+            for tag in split_list(value) {
+                todo.add_tag(&tag);
+            }
+        }
+        "PRIORITY" => {
+            todo.priority = match value.trim().parse::<u8>() {
+                Ok(1..=4) => Priority::High,
+                Ok(5) => Priority::Medium,
+                Ok(6..=9) => Priority::Low,
+                _ => Priority::None,
+            }
+        }
+        "STATUS" => {
+            todo.status = match value.trim().to_uppercase().as_str() {
+                "IN-PROCESS" => Status::InProgress,
+                "COMPLETED" => Status::Done,
+                "CANCELLED" => Status::Cancelled,
+                _ => Status::Open,
+            }
+        }
+        "DUE" => todo.due_at = parse_time(value, is_date),
+        "DTSTART" => {
+            // Unlike due dates, days are scheduled from their beginning
+            let start = parse_time(value, is_date);
+            todo.scheduled_at = match is_date || value.trim().len() == 8 {
+                true => start.and_then(|day| {
+                    let midnight = day.date_naive().and_time(NaiveTime::MIN);
+                    Local.from_local_datetime(&midnight).earliest()
+                }),
+                false => start,
+            };
+        }
+        "COMPLETED" => todo.completed_at = parse_time(value, is_date),
+        "CREATED" => {
+            if let Some(created_at) = parse_time(value, is_date) {
+                todo.created_at = created_at;
+            }
+        }
+        "LAST-MODIFIED" => {
+            if let Some(updated_at) = parse_time(value, is_date) {
+                todo.updated_at = updated_at;
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Joins the continuation lines, which start with a space or tab, to the
+/// line before them.
+fn unfold(calendar: &str) -> Vec<String> {
+    let mut lines: Vec<String> = Vec::new();
+    for line in calendar.lines() {
+        match (line.strip_prefix([' ', '\t']), lines.last_mut()) {
+            (Some(rest), Some(last)) => last.push_str(rest),
+            _ => lines.push(line.to_string()),
+        }
+    }
+
+    lines
+}
+
+/// Breaks lines longer than 75 bytes into continuation lines.
+fn fold(line: &str) -> String {
+    let mut folded = String::new();
+    let mut length = 0;
+    for c in line.chars() {
+        if length + c.len_utf8() > 75 {
+            folded.push_str("\r\n ");
+            length = 1;
+        }
+        folded.push(c);
+        length += c.len_utf8();
+    }
+
+    folded
+}
+
+/// Splits a content line into the uppercase name, the parameters and the
+/// value. Parameter values may contain colons in quotes.
+fn split_line(line: &str) -> Option<(String, &str, &str)> {
+    let mut quoted = false;
+    let colon = line.char_indices().find_map(|(i, c)| match c {
+        '"' => {
+            quoted = !quoted;
+            None
+        }
+        ':' if !quoted => Some(i),
+        _ => None,
+    })?;
+    let (head, value) = (&line[..colon], &line[colon + 1..]);
+    let (name, params) = head.split_once(';').unwrap_or((head, ""));
+
+    Some((name.to_uppercase(), params, value))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('\\', "\\\\")
+        .replace(';', "\\;")
+        .replace(',', "\\,")
+        .replace("\r\n", "\\n")
+        .replace('\n', "\\n")
+}
+
+fn unescape(text: &str) -> String {
+    let mut unescaped = String::new();
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match (c, chars.clone().next()) {
+            ('\\', Some('n' | 'N')) => {
+                unescaped.push('\n');
+                chars.next();
+            }
+            ('\\', Some(next)) => {
+                unescaped.push(next);
+                chars.next();
+            }
+            (c, _) => unescaped.push(c),
+        }
+    }
+
+    unescaped
+}
+
+/// Splits a list like `CATEGORIES` at the commas that are not escaped.
+fn split_list(value: &str) -> Vec<String> {
+    let mut items = Vec::new();
+    let mut item = String::new();
+    let mut chars = value.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => {
+                item.push(c);
+                item.extend(chars.next());
+            }
+            ',' => items.push(std::mem::take(&mut item)),
+            c => item.push(c),
+        }
+    }
+    items.push(item);
+
+    items
+        .iter()
+        .map(|item| unescape(item.trim()))
+        .filter(|item| !item.is_empty())
+        .collect()
+}
+
+fn format_time(time: DateTime<Local>) -> String {
+    time.with_timezone(&Utc)
+        .format("%Y%m%dT%H%M%SZ")
+        .to_string()
+}
+
+/// Parses a time in UTC, a local time or, for dates, the end of the day.
+/// Times in other time zones are taken as local times.
+fn parse_time(value: &str, is_date: bool) -> Option<DateTime<Local>> {
+    let value = value.trim();
+    if is_date || value.len() == 8 {
+        let date = NaiveDate::parse_from_str(value, "%Y%m%d").ok()?;
+        let end_of_day = date.and_hms_opt(23, 59, 59)?;
+        return Local.from_local_datetime(&end_of_day).earliest();
+    }
+
+    match value.strip_suffix('Z') {
+        Some(utc) => NaiveDateTime::parse_from_str(utc, "%Y%m%dT%H%M%S")
+            .ok()
+            .map(|time| Utc.from_utc_datetime(&time).with_timezone(&Local)),
+        None => NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%S")
+            .ok()
+            .and_then(|time| Local.from_local_datetime(&time).earliest()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dates;
+
+    #[test]
+    fn vtodo_round_trips() {
+        let mut todo = Todo::new(1, "call mom; then dad, maybe");
+        todo.notes = "first line\nsecond \\ line".to_string();
+        todo.tags = vec!["family".to_string(), "a,b".to_string()];
+        todo.priority = Priority::Medium;
+        todo.scheduled_at = dates::parse_day("2024-07-01").ok();
+        todo.set_status(Status::Done, Local::now());
+
+        let vtodos = parse(&calendar(&todo, "some-uid"));
+        assert_eq!(vtodos.len(), 1);
+        let parsed = &vtodos[0].todo;
+        assert_eq!(vtodos[0].uid, "some-uid");
+        assert_eq!(parsed.text, todo.text);
+        assert_eq!(parsed.notes, todo.notes);
+        assert_eq!(parsed.tags, todo.tags);
+        assert_eq!(parsed.priority, Priority::Medium);
+        assert_eq!(parsed.scheduled_at, todo.scheduled_at);
+        assert_eq!(parsed.status, Status::Done);
+        assert!(parsed.completed_at.is_some());
+    }
+
+    #[test]
+    fn due_calendar_has_the_due_todos() {
+        let mut due = Todo::new(1, "pay rent");
+        due.due_at = dates::parse_datetime("2024-07-01").ok();
+        let todos = [due, Todo::new(2, "some day")];
+
+        let calendar = due_calendar(&todos);
+        assert_eq!(parse(&calendar).len(), 1);
+        assert!(calendar.contains("BEGIN:VEVENT\r\n"));
+        assert!(calendar.contains("DTSTART;VALUE=DATE:20240701\r\n"));
+        assert!(calendar.contains("DTEND;VALUE=DATE:20240702\r\n"));
+        assert!(!calendar.contains("some day"));
+    }
+
+    #[test]
+    fn folds_long_lines() {
+        let text = "ü".repeat(100);
+        let folded = fold(&format!("SUMMARY:{}", text));
+        assert!(folded.split("\r\n").all(|line| line.len() <= 75));
+        assert_eq!(unfold(&folded), [format!("SUMMARY:{}", text)]);
+    }
+
+    #[test]
+    fn reads_dates_and_times() {
+        let due = parse_time("20240701", true).unwrap();
+        assert_eq!(due.format("%Y-%m-%d %H:%M").to_string(), "2024-07-01 23:59");
+        let utc = parse_time("20240701T120000Z", false).unwrap();
+        assert_eq!(utc.with_timezone(&Utc).hour(), 12);
+        assert!(parse_time("garbage", false).is_none());
+    }
+}
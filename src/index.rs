@@ -0,0 +1,77 @@
+//! A word index over the texts and notes, so searching large databases does
+//! not have to look at every todo.
+
+use crate::todo::Todo;
+use std::collections::{BTreeSet, HashMap};
+
+/// Maps every lowercase word of the texts and notes to the ids of the todos
+/// using it. It only narrows a search down; the candidates still have to be
+/// checked for the whole query.
+#[derive(Debug, Default)]
+pub struct SearchIndex {
+    words: HashMap<String, BTreeSet<u32>>,
+}
+
+impl SearchIndex {
+    pub fn build(todos: &[Todo]) -> Self {
+        let mut index = SearchIndex::default();
+        for todo in todos {
+            index.add(todo);
+        }
+
+        index
+    }
+
+    pub fn add(&mut self, todo: &Todo) {
+        for word in words_of(todo) {
+            self.words.entry(word).or_default().insert(todo.id);
+        }
+    }
+
+    pub fn remove(&mut self, todo: &Todo) {
+        for word in words_of(todo) {
+            if let Some(ids) = self.words.get_mut(&word) {
+                ids.remove(&todo.id);
+                if ids.is_empty() {
+                    self.words.remove(&word);
+                }
+            }
+        }
+    }
+
+    /// Ids of the todos that can contain `query`: for each word of the query
+    /// some word of the todo contains it. `None` when the query has no words
+    /// to look up, so every todo is a candidate.
+    pub fn candidates(&self, query: &str) -> Option<BTreeSet<u32>> {
+        let mut candidates: Option<BTreeSet<u32>> = None;
+
+        for query_word in split_words(&query.to_lowercase()) {
+            // The vocabulary is much smaller than the texts, and substrings
+            // have to be found as well, e.g. `port` in `report`
+            let ids: BTreeSet<u32> = self
+                .words
+                .iter()
+                .filter(|(word, _)| word.contains(query_word))
+                .flat_map(|(_, ids)| ids.iter().copied())
+                .collect();
+
+            candidates = Some(match candidates {
+                Some(candidates) => candidates.intersection(&ids).copied().collect(),
+                None => ids,
+            });
+        }
+
+        candidates
+    }
+}
+
+fn words_of(todo: &Todo) -> BTreeSet<String> {
+    let text = format!("{} {}", todo.text, todo.notes).to_lowercase();
+
+    split_words(&text).map(String::from).collect()
+}
+
+fn split_words(text: &str) -> impl Iterator<Item = &str> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|word| !word.is_empty())
+}
@@ -0,0 +1,353 @@
+use crate::commands::{self, TagChange};
+use crate::database::Database;
+use crate::error::TodoError;
+use crate::filter::Filter;
+use crate::journal::Operation;
+use crate::output::{Format, Layout};
+use crate::prompt;
+use crate::render::Renderer;
+use crate::selection::{self, IdSelector};
+use crate::todo::{self, Todo};
+use colored::Colorize;
+use dialoguer::{FuzzySelect, MultiSelect, Select};
+use std::io::{self, IsTerminal};
+
+/// A change made in the menu, with the operations reverting it.
+struct Change {
+    operations: Vec<Operation>,
+    undo: Vec<Operation>,
+}
+
+const LEGEND: &str = " a add  l list  o open  c complete  d delete  s search  e edit  \
+                      m several  u undo  q quit ";
+
+/// The menu used when no subcommand is given, with a key for every action.
+pub fn run(db: &mut Database, renderer: &Renderer, layout: &Layout) -> Result<(), TodoError> {
+    let mut changes: Vec<Change> = Vec::new();
+
+    loop {
+        println!("{}", LEGEND.reversed());
+
+        // The end of input closes the menu like `q` does
+        let key = prompt::read_key("> ")?.unwrap_or_else(|| "q".to_string());
+
+        let operations: Vec<Operation> = match key.as_str() {
+            "l" => {
+                show_all_todos(db, renderer, layout)?;
+                Vec::new()
+            }
+            "o" => {
+                show_all_open_todos(db, renderer, layout)?;
+                Vec::new()
+            }
+            "a" => new_todo(db)?
+                .map(|todo| Operation::Add { todo })
+                .into_iter()
+                .collect(),
+            "c" => set_todo_completed(db)?
+                .map(Operation::complete)
+                .into_iter()
+                .collect(),
+            "d" => delete_todo(db)?
+                .map(Operation::delete)
+                .into_iter()
+                .collect(),
+            "s" => {
+                search_todos(db, renderer, layout)?;
+                Vec::new()
+            }
+            "e" => edit_todo(db)?
+                .map(|todo| Operation::Update { todo })
+                .into_iter()
+                .collect(),
+            "m" => change_several_todos(db)?,
+            "q" => return close(db, changes),
+            "u" => {
+                match changes.pop() {
+                    Some(change) => {
+                        for operation in change.undo {
+                            db.apply(operation)?;
+                        }
+                        println!("Undid the last change");
+                    }
+                    None => println!("There is nothing to undo"),
+                }
+                Vec::new()
+            }
+            "" => Vec::new(),
+            other => {
+                println!("'{}' is not an option", other);
+                Vec::new()
+            }
+        };
+
+        if !operations.is_empty() {
+            let mut inverses = Vec::new();
+            for operation in &operations {
+                inverses.push(operation.inverse(&db.todos, &db.trash));
+                db.apply(operation.clone())?;
+            }
+            changes.push(Change {
+                operations,
+                // The last operation is reverted first
+                undo: inverses.into_iter().rev().flatten().collect(),
+            });
+        }
+    }
+}
+
+/// Shows what the session changed and saves it, or reverts it all when the
+/// user would rather discard it.
+fn close(db: &mut Database, changes: Vec<Change>) -> Result<(), TodoError> {
+    if !changes.is_empty() {
+        let operations: Vec<&Operation> = changes.iter().flat_map(|c| &c.operations).collect();
+        println!("This session: {}", summary(&operations));
+
+        if !prompt::confirm_with_default("Save the changes?", true)? {
+            for change in changes.into_iter().rev() {
+                for operation in change.undo {
+                    db.apply(operation)?;
+                }
+            }
+            println!("Discarded the changes");
+        }
+    }
+
+    Ok(db.save()?)
+}
+
+/// Counts the operations by kind, like `+2 added, 1 completed, 1 deleted`.
+fn summary(operations: &[&Operation]) -> String {
+    const KINDS: [&str; 5] = ["added", "completed", "edited", "deleted", "restored"];
+
+    let mut counts = [0; KINDS.len()];
+    for operation in operations {
+        let kind = match operation {
+            Operation::Add { .. } => 0,
+            Operation::Complete { .. } => 1,
+            Operation::Update { .. } => 2,
+            Operation::Delete { .. } | Operation::Purge { .. } | Operation::Archive { .. } => 3,
+            Operation::Restore { .. } => 4,
+        };
+        counts[kind] += 1;
+    }
+
+    let parts: Vec<String> = KINDS
+        .iter()
+        .zip(counts)
+        .filter(|&(_, count)| count > 0)
+        .map(|(&kind, count)| match kind {
+            "added" => format!("+{} added", count),
+            _ => format!("{} {}", count, kind),
+        })
+        .collect();
+
+    parts.join(", ")
+}
+
+fn show_all_todos(db: &Database, renderer: &Renderer, layout: &Layout) -> io::Result<()> {
+    let filter = Filter {
+        hide_deferred: true,
+        ..Filter::default()
+    };
+    renderer.print_todos(db.active_todos(), &filter, Format::Plain, layout)
+}
+
+fn show_all_open_todos(db: &Database, renderer: &Renderer, layout: &Layout) -> io::Result<()> {
+    renderer.print_todos(db.active_todos(), &Filter::open(), Format::Plain, layout)
+}
+
+fn search_todos(db: &Database, renderer: &Renderer, layout: &Layout) -> Result<(), TodoError> {
+    let query = prompt::ask("Search for (empty to cancel):", |query| {
+        Ok::<_, String>(query.to_string())
+    })?;
+
+    query.map_or(Ok(()), |query| {
+        commands::search(db, renderer, layout, &query)
+    })
+}
+
+fn new_todo(db: &mut Database) -> Result<Option<Todo>, TodoError> {
+    let max_length = db.max_text_length;
+    let text = prompt::ask("Text (empty to cancel):", |text| {
+        todo::sanitize_text(text, max_length)
+    })?;
+
+    text.map(|text| db.new_todo(&text)).transpose()
+}
+
+fn edit_todo(db: &Database) -> io::Result<Option<Todo>> {
+    let Some(mut todo) = pick_todo(db, &Filter::default())?
+        .and_then(|id| db.find(id))
+        .cloned()
+    else {
+        return Ok(None);
+    };
+    println!("Current text: {}", todo.text);
+    let max_length = db.max_text_length;
+    let text = prompt::ask("New text (empty to cancel):", |text| {
+        todo::sanitize_text(text, max_length)
+    })?;
+
+    Ok(text.map(|text| {
+        todo.set_text(&text);
+        todo
+    }))
+}
+
+fn set_todo_completed(db: &Database) -> io::Result<Option<u32>> {
+    pick_todo(db, &Filter::open())
+}
+
+fn delete_todo(db: &Database) -> io::Result<Option<u32>> {
+    let Some(todo) = pick_todo(db, &Filter::default())?.and_then(|id| db.find(id)) else {
+        return Ok(None);
+    };
+    if !prompt::confirm(&format!("Delete \"{}\"?", todo.text))? {
+        return Ok(None);
+    }
+
+    Ok(Some(todo.id))
+}
+
+/// Lets the user choose one of the todos matching `filter` from a list that
+/// narrows down while typing. Without a terminal, e.g. when reading from a
+/// pipe, the id is asked for instead.
+fn pick_todo(db: &Database, filter: &Filter) -> io::Result<Option<u32>> {
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        return read_todo_id(db);
+    }
+
+    let todos: Vec<&Todo> = db.active_todos().filter(|t| filter.matches(t)).collect();
+    if todos.is_empty() {
+        println!("There are no todos to choose from");
+        return Ok(None);
+    }
+    let items: Vec<String> = todos.iter().map(|todo| todo_item(todo)).collect();
+
+    let choice = FuzzySelect::new()
+        .with_prompt("Type to filter, Esc to cancel")
+        .items(&items)
+        .interact_opt()
+        .map_err(|dialoguer::Error::IO(e)| e)?;
+
+    Ok(choice.map(|index| todos[index].id))
+}
+
+/// Lets the user check several todos and then choose what to do with all of
+/// them: complete, delete or tag them.
+fn change_several_todos(db: &Database) -> io::Result<Vec<Operation>> {
+    let ids = pick_todos(db)?;
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+
+    let actions = ["Complete", "Delete", "Tag"];
+    let question = format!("What to do with the {} todo(s)?", ids.len());
+    let operations = match choose(&question, &actions)? {
+        Some(0) => ids.into_iter().map(Operation::complete).collect(),
+        Some(1) => {
+            if !prompt::confirm(&format!("Delete {} todo(s)?", ids.len()))? {
+                return Ok(Vec::new());
+            }
+            ids.into_iter().map(Operation::delete).collect()
+        }
+        Some(_) => {
+            let changes = prompt::ask("Tags, e.g. +work -later (empty to cancel):", |input| {
+                input
+                    .split_whitespace()
+                    .map(str::parse)
+                    .collect::<Result<Vec<TagChange>, _>>()
+            })?;
+            let Some(changes) = changes else {
+                return Ok(Vec::new());
+            };
+
+            ids.iter()
+                .filter_map(|&id| db.find(id))
+                .map(|todo| {
+                    let mut todo = todo.clone();
+                    for change in &changes {
+                        change.apply(&mut todo);
+                    }
+                    Operation::Update { todo }
+                })
+                .collect()
+        }
+        None => Vec::new(),
+    };
+
+    Ok(operations)
+}
+
+/// Ids of the todos the user checks with the space bar, or without a
+/// terminal types as ids and ranges.
+fn pick_todos(db: &Database) -> io::Result<Vec<u32>> {
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        let ids = prompt::ask("Ids, e.g. 1 3 7-9 (empty to cancel):", |input| {
+            let selectors = input
+                .split_whitespace()
+                .map(str::parse)
+                .collect::<Result<Vec<IdSelector>, _>>()?;
+            selection::resolve_ids(db, &selectors).map_err(|e| e.to_string())
+        })?;
+        return Ok(ids.unwrap_or_default());
+    }
+
+    let todos: Vec<&Todo> = db.active_todos().collect();
+    let items: Vec<String> = todos.iter().map(|todo| todo_item(todo)).collect();
+    let checked = MultiSelect::new()
+        .with_prompt("Space to check, Enter to continue, Esc to cancel")
+        .items(&items)
+        .interact_opt()
+        .map_err(|dialoguer::Error::IO(e)| e)?;
+
+    Ok(checked
+        .unwrap_or_default()
+        .into_iter()
+        .map(|index| todos[index].id)
+        .collect())
+}
+
+/// Index of the chosen item, from a list to move through on a terminal or
+/// else by number.
+fn choose(question: &str, items: &[&str]) -> io::Result<Option<usize>> {
+    if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+        println!("{}", question);
+        for (i, item) in items.iter().enumerate() {
+            println!("[{}] {}", i + 1, item);
+        }
+        return prompt::ask(">>", |input| match input.parse::<usize>() {
+            Ok(n) if (1..=items.len()).contains(&n) => Ok(n - 1),
+            _ => Err(format!(
+                "'{}' is not an option, choose 1-{}",
+                input,
+                items.len()
+            )),
+        });
+    }
+
+    Select::new()
+        .with_prompt(question)
+        .items(items)
+        .default(0)
+        .interact_opt()
+        .map_err(|dialoguer::Error::IO(e)| e)
+}
+
+/// A todo as an item of a list to pick from.
+fn todo_item(todo: &Todo) -> String {
+    let tags: String = todo.tags.iter().map(|tag| format!(" +{}", tag)).collect();
+
+    format!("{}: {}{}", todo.id, todo.text, tags)
+}
+
+fn read_todo_id(db: &Database) -> io::Result<Option<u32>> {
+    prompt::ask("Id (empty to cancel):", |input| {
+        let id = input
+            .parse::<u32>()
+            .map_err(|_| format!("'{}' is not a valid id", input))?;
+
+        commands::find_todo_id(db, id).map_err(|e| e.to_string())
+    })
+}
@@ -0,0 +1,392 @@
+use crate::dates::utc;
+use crate::todo::{Metadata, Status, Todo};
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use std::ffi::OsString;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Number of journal entries after which the journal is folded into the
+/// database and truncated.
+pub const COMPACT_THRESHOLD: usize = 100;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase")]
+pub enum Operation {
+    Add {
+        todo: Todo,
+    },
+    Complete {
+        id: u32,
+        // Journals written before completion times were tracked lack it
+        #[serde(default = "Local::now", serialize_with = "utc::serialize")]
+        completed_at: DateTime<Local>,
+    },
+    /// Moves the todo to the trash
+    Delete {
+        id: u32,
+        // Journals written before there was a trash lack it
+        #[serde(default = "Local::now", serialize_with = "utc::serialize")]
+        deleted_at: DateTime<Local>,
+    },
+    /// Moves the todo out of the trash again
+    Restore {
+        id: u32,
+    },
+    /// Deletes a todo in the trash for good
+    Purge {
+        id: u32,
+    },
+    /// Removes a todo that was written to the archive
+    Archive {
+        id: u32,
+    },
+    /// Replaces the todo with the same id, for all other changes
+    Update {
+        todo: Todo,
+    },
+}
+
+impl Operation {
+    /// Completes the todo now.
+    pub fn complete(id: u32) -> Self {
+        Operation::Complete {
+            id,
+            completed_at: Local::now(),
+        }
+    }
+
+    /// Completes an open todo now or sets a closed one as open again.
+    pub fn toggle(todo: &Todo) -> Self {
+        if todo.status.is_closed() {
+            let mut todo = todo.clone();
+            todo.set_status(Status::Open, Local::now());
+            Operation::Update { todo }
+        } else {
+            Operation::complete(todo.id)
+        }
+    }
+
+    /// Moves the todo to the trash now.
+    pub fn delete(id: u32) -> Self {
+        Operation::Delete {
+            id,
+            deleted_at: Local::now(),
+        }
+    }
+
+    /// The operations reverting this one, to be applied in order. Has to be
+    /// called before the operation is applied, with the todos and trash it
+    /// is applied to.
+    pub fn inverse(&self, todos: &[Todo], trash: &[Todo]) -> Vec<Operation> {
+        let find = |todos: &[Todo], id: u32| todos.iter().find(|t| t.id == id).cloned();
+
+        match self {
+            Operation::Add { todo } => {
+                vec![Operation::delete(todo.id), Operation::Purge { id: todo.id }]
+            }
+            Operation::Complete { id, .. } => find(todos, *id)
+                .map(|todo| Operation::Update { todo })
+                .into_iter()
+                .collect(),
+            Operation::Delete { id, .. } => vec![Operation::Restore { id: *id }],
+            Operation::Restore { id } => find(trash, *id)
+                .and_then(|todo| todo.deleted_at)
+                .map(|deleted_at| Operation::Delete {
+                    id: *id,
+                    deleted_at,
+                })
+                .into_iter()
+                .collect(),
+            Operation::Purge { id } => match find(trash, *id) {
+                Some(mut todo) => {
+                    let deleted_at = todo.deleted_at.take().unwrap_or_else(Local::now);
+                    vec![
+                        Operation::Add { todo },
+                        Operation::Delete {
+                            id: *id,
+                            deleted_at,
+                        },
+                    ]
+                }
+                None => Vec::new(),
+            },
+            Operation::Archive { id } => find(todos, *id)
+                .map(|todo| Operation::Add { todo })
+                .into_iter()
+                .collect(),
+            Operation::Update { todo } => find(todos, todo.id)
+                .map(|todo| Operation::Update { todo })
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    pub fn apply(self, metadata: &mut Metadata, todos: &mut Vec<Todo>, trash: &mut Vec<Todo>) {
+        match self {
+            Operation::Add { todo } => {
+                metadata.seq_id = metadata.seq_id.max(todo.id);
+                todos.push(todo);
+            }
+            Operation::Complete { id, completed_at } => {
+                if let Some(t) = todos.iter_mut().find(|t| t.id == id) {
+                    t.set_status(Status::Done, completed_at);
+                    t.updated_at = completed_at;
+                }
+            }
+            Operation::Delete { id, deleted_at } => {
+                if let Some(index) = todos.iter().position(|t| t.id == id) {
+                    let mut todo = todos.remove(index);
+                    todo.deleted_at = Some(deleted_at);
+                    trash.push(todo);
+                }
+            }
+            Operation::Restore { id } => {
+                if let Some(index) = trash.iter().position(|t| t.id == id) {
+                    let mut todo = trash.remove(index);
+                    todo.deleted_at = None;
+                    todos.push(todo);
+                }
+            }
+            Operation::Purge { id } => {
+                trash.retain(|t| t.id != id);
+            }
+            Operation::Archive { id } => {
+                todos.retain(|t| t.id != id);
+            }
+            Operation::Update { todo } => {
+                if let Some(t) = todos.iter_mut().find(|t| t.id == todo.id) {
+                    *t = todo;
+                }
+            }
+        }
+    }
+}
+
+/// Append-only log of the operations done since the database was last saved.
+/// Every operation is written and synced to disk right away, so a crash never
+/// loses more than the operation in flight.
+pub struct Journal {
+    path: PathBuf,
+    len: usize,
+}
+
+impl Journal {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        Journal {
+            path: path.into(),
+            len: 0,
+        }
+    }
+
+    /// The journal belonging to a database lives next to it as `<db>.log`.
+    pub fn for_database(db_path: &Path) -> Self {
+        let mut path = OsString::from(db_path.as_os_str());
+        path.push(".log");
+
+        Journal::new(path)
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn append(&mut self, operation: &Operation) -> io::Result<()> {
+        let mut f = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&self.path)?;
+
+        let mut line = serde_json::to_string(operation)?;
+        line.push('\n');
+        f.write_all(line.as_bytes())?;
+        f.sync_data()?;
+
+        self.len += 1;
+        Ok(())
+    }
+
+    /// Applies all logged operations on top of the loaded database.
+    pub fn replay(
+        &mut self,
+        metadata: &mut Metadata,
+        todos: &mut Vec<Todo>,
+        trash: &mut Vec<Todo>,
+    ) -> io::Result<()> {
+        if !self.path.exists() {
+            return Ok(());
+        }
+
+        // Bytes, as a torn line can end in the middle of a character
+        let contents = fs::read(&self.path)?;
+        let lines: Vec<&[u8]> = contents.split_inclusive(|&b| b == b'\n').collect();
+        let mut intact = 0;
+
+        for (index, line) in lines.iter().enumerate() {
+            match serde_json::from_slice::<Operation>(line) {
+                Ok(operation) => {
+                    operation.apply(metadata, todos, trash);
+                    self.len += 1;
+                    intact += line.len();
+                }
+                // A torn last line is what a crash during append leaves behind
+                Err(_) if index == lines.len() - 1 => break,
+                Err(e) => return Err(e.into()),
+            }
+        }
+
+        // The next append would otherwise continue the last line
+        if intact < contents.len() {
+            log::warn!("Dropping the torn last line of {}", self.path.display());
+            let f = OpenOptions::new().write(true).open(&self.path)?;
+            f.set_len(intact as u64)?;
+            f.sync_data()?;
+        } else if !contents.is_empty() && !contents.ends_with(b"\n") {
+            let mut f = OpenOptions::new().append(true).open(&self.path)?;
+            f.write_all(b"\n")?;
+            f.sync_data()?;
+        }
+
+        Ok(())
+    }
+
+    /// Empties the journal once its operations are part of the database.
+    pub fn clear(&mut self) -> io::Result<()> {
+        if self.path.exists() {
+            fs::remove_file(&self.path)?;
+        }
+
+        self.len = 0;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::process;
+
+    fn journal(name: &str) -> Journal {
+        let path = env::temp_dir().join(format!("rust-todo-{}-{}.log", name, process::id()));
+        let _ = fs::remove_file(&path);
+
+        Journal::new(path)
+    }
+
+    #[test]
+    fn replays_operations_up_to_a_torn_last_line() {
+        let mut journal = journal("torn");
+        journal
+            .append(&Operation::Add {
+                todo: Todo::new(1, "buy milk"),
+            })
+            .unwrap();
+        journal
+            .append(&Operation::Add {
+                todo: Todo::new(2, "call mom"),
+            })
+            .unwrap();
+        journal.append(&Operation::complete(1)).unwrap();
+        journal.append(&Operation::delete(2)).unwrap();
+        // What a crash in the middle of appending leaves behind
+        let torn = serde_json::to_string(&Operation::Add {
+            todo: Todo::new(3, "lost"),
+        })
+        .unwrap();
+        let mut f = OpenOptions::new()
+            .append(true)
+            .open(journal.path())
+            .unwrap();
+        f.write_all(&torn.as_bytes()[..torn.len() / 2]).unwrap();
+
+        let mut metadata = Metadata {
+            seq_id: 0,
+            version: 0,
+        };
+        let (mut todos, mut trash) = (Vec::new(), Vec::new());
+        let mut replayed = Journal::new(journal.path());
+        replayed
+            .replay(&mut metadata, &mut todos, &mut trash)
+            .unwrap();
+
+        assert_eq!(replayed.len(), 4);
+        assert_eq!(metadata.seq_id, 2);
+        assert_eq!(todos.len(), 1);
+        assert_eq!(todos[0].status, Status::Done);
+        assert!(todos[0].completed_at.is_some());
+        assert_eq!(trash.len(), 1);
+        assert_eq!(trash[0].id, 2);
+        assert!(trash[0].deleted_at.is_some());
+
+        journal.clear().unwrap();
+        assert!(!journal.path().exists());
+    }
+
+    #[test]
+    fn appends_after_a_torn_line_start_a_line_of_their_own() {
+        let journal = journal("torn-only");
+        fs::write(journal.path(), b"{\"op\":\"add\",\"todo\":{").unwrap();
+
+        let mut metadata = Metadata {
+            seq_id: 0,
+            version: 0,
+        };
+        let (mut todos, mut trash) = (Vec::new(), Vec::new());
+        let mut recovered = Journal::new(journal.path());
+        recovered
+            .replay(&mut metadata, &mut todos, &mut trash)
+            .unwrap();
+        assert!(recovered.is_empty());
+        recovered
+            .append(&Operation::Add {
+                todo: Todo::new(1, "buy milk"),
+            })
+            .unwrap();
+
+        let mut replayed = Journal::new(journal.path());
+        replayed
+            .replay(&mut metadata, &mut todos, &mut trash)
+            .unwrap();
+        assert_eq!(replayed.len(), 1);
+        assert_eq!(todos[0].text, "buy milk");
+
+        recovered.clear().unwrap();
+    }
+
+    #[test]
+    fn a_broken_line_before_the_last_is_an_error() {
+        let mut journal = journal("broken");
+        journal
+            .append(&Operation::Add {
+                todo: Todo::new(1, "buy milk"),
+            })
+            .unwrap();
+        let mut f = OpenOptions::new()
+            .append(true)
+            .open(journal.path())
+            .unwrap();
+        f.write_all(b"{\"op\":\n").unwrap();
+        journal.append(&Operation::complete(1)).unwrap();
+
+        let mut metadata = Metadata {
+            seq_id: 0,
+            version: 0,
+        };
+        let mut replayed = Journal::new(journal.path());
+        assert!(replayed
+            .replay(&mut metadata, &mut Vec::new(), &mut Vec::new())
+            .is_err());
+
+        journal.clear().unwrap();
+    }
+}
@@ -0,0 +1,110 @@
+//! Named lists like `work` or `groceries` that keep separate sets of todos
+//! in one database, for `todo lists`, `todo use` and `todo add --list`.
+
+use crate::database::Database;
+use crate::error::TodoError;
+use crate::output;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Which list is in use, kept next to the database as `<db>.lists.json`
+/// since not every backend has room for it.
+#[derive(Default, Serialize, Deserialize)]
+struct State {
+    active: Option<String>,
+}
+
+fn state_path(db_path: &Path) -> PathBuf {
+    let mut path = db_path.as_os_str().to_owned();
+    path.push(".lists.json");
+    PathBuf::from(path)
+}
+
+fn load(db_path: &Path) -> Result<State, TodoError> {
+    let path = state_path(db_path);
+    match fs::read(&path) {
+        Ok(contents) => serde_json::from_slice(&contents)
+            .map_err(|e| TodoError::parse(e.to_string()).in_file(&path)),
+        Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(State::default()),
+        Err(e) => Err(TodoError::from(e).in_file(&path)),
+    }
+}
+
+/// Checks a list name given on the command line; names are lowercase.
+pub fn parse_name(name: &str) -> Result<String, String> {
+    let name = name.trim().to_lowercase();
+    let valid = !name.is_empty()
+        && name
+            .chars()
+            .all(|c| c.is_alphanumeric() || c == '-' || c == '_');
+
+    if valid {
+        Ok(name)
+    } else {
+        Err(format!(
+            "'{}' is not a list name, use letters, digits, - and _",
+            name
+        ))
+    }
+}
+
+/// The list in use for the database, if any.
+pub fn active(db_path: &Path) -> Result<Option<String>, TodoError> {
+    Ok(load(db_path)?.active)
+}
+
+/// Makes `list` the one new todos are put on and listings show, or shows all
+/// lists again without.
+pub fn use_list(db_path: &Path, list: Option<&str>) -> Result<(), TodoError> {
+    let path = state_path(db_path);
+    let state = State {
+        active: list.map(str::to_string),
+    };
+    let write = || fs::write(&path, serde_json::to_vec_pretty(&state)?);
+    write().map_err(|e| TodoError::from(e).in_file(&path))?;
+
+    match list {
+        Some(list) => output::status(format!("Using the list {}", list)),
+        None => output::status("Using all lists"),
+    }
+
+    Ok(())
+}
+
+/// Prints the lists with the number of open todos on each, the active one
+/// marked with `*`.
+pub fn print(db: &Database) -> Result<(), TodoError> {
+    let mut open: BTreeMap<Option<&str>, usize> = BTreeMap::new();
+    for todo in &db.todos {
+        let count = open.entry(todo.list.as_deref()).or_default();
+        if !todo.status.is_closed() {
+            *count += 1;
+        }
+    }
+    if let Some(active) = db.active_list.as_deref() {
+        open.entry(Some(active)).or_default();
+    }
+
+    if open.is_empty() {
+        output::status("There are no todos on any list yet");
+        return Ok(());
+    }
+
+    for (&list, count) in open.iter().filter(|(list, _)| list.is_some()) {
+        let marker = if list == db.active_list.as_deref() {
+            "*"
+        } else {
+            " "
+        };
+        println!("{} {} ({} open)", marker, list.unwrap_or_default(), count);
+    }
+    // Todos without a list last
+    if let Some(count) = open.get(&None) {
+        println!("  (no list) ({} open)", count);
+    }
+
+    Ok(())
+}
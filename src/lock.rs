@@ -0,0 +1,49 @@
+use std::ffi::OsString;
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io;
+use std::path::Path;
+
+/// Advisory lock on a database, held for the whole session so a second
+/// instance cannot clobber the changes of the first one. The lock is
+/// released when the value is dropped.
+pub struct DatabaseLock {
+    _file: File,
+}
+
+impl DatabaseLock {
+    /// Locks the database by locking `<db>.lock` next to it. If another
+    /// instance holds the lock, this either waits for it or fails right away.
+    pub fn acquire(db_path: &Path, wait: bool) -> io::Result<Self> {
+        let mut path = OsString::from(db_path.as_os_str());
+        path.push(".lock");
+
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&path)?;
+
+        match file.try_lock() {
+            Ok(()) => log::debug!("Locked {}", path.to_string_lossy()),
+            Err(TryLockError::WouldBlock) if wait => {
+                log::warn!(
+                    "Waiting for another instance to release {}...",
+                    db_path.display()
+                );
+                file.lock()?;
+            }
+            Err(TryLockError::WouldBlock) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::WouldBlock,
+                    format!(
+                        "{} is in use by another instance (pass --wait to wait for it)",
+                        db_path.display()
+                    ),
+                ));
+            }
+            Err(TryLockError::Error(e)) => return Err(e),
+        }
+
+        Ok(DatabaseLock { _file: file })
+    }
+}
@@ -1,260 +1,1036 @@
 use chrono::prelude::*;
-use std::fmt;
-use std::fs::File;
-use std::io::{self, BufRead, BufReader, Write};
-use std::str::FromStr;
-
-#[derive(Debug)]
-struct Todo {
-    id: u32,
-    is_completed: bool,
-    text: String,
-    created_at: DateTime<Local>,
-}
+use clap::{Args, CommandFactory, Parser, Subcommand, ValueEnum};
+use clap_complete::Shell;
+use commands::{FieldChange, TagChange};
+use config::Config;
+use database::Database;
+use error::TodoError;
+use export::ExportFormat;
+use filter::{FieldFilter, Filter};
+use group::GroupBy;
+use output::{Column, DateStyle, Format, Layout, SortKey};
+use regex::Regex;
+use render::Renderer;
+use selection::IdSelector;
+use std::env;
+use std::io;
+use std::path::PathBuf;
+use std::process::{self, ExitCode};
+use storage::Backend;
+use todo::{Priority, Status};
+use webhook::Webhook;
 
-#[derive(Debug)]
-struct Metadata {
-    seq_id: u32,
-}
+mod archive;
+mod board;
+mod caldav;
+mod calendar;
+mod commands;
+mod config;
+mod csv;
+mod database;
+mod dates;
+mod editor;
+mod error;
+mod escalation;
+mod export;
+mod filter;
+mod git_sync;
+mod github;
+mod group;
+mod ical;
+mod index;
+mod interactive;
+mod journal;
+mod lists;
+mod lock;
+mod output;
+mod prompt;
+mod query;
+mod remind;
+mod render;
+mod report;
+mod selection;
+mod storage;
+mod taskwarrior;
+mod todo;
+mod todoist;
+mod tree;
+mod tui;
+mod urgency;
+mod webhook;
 
-#[derive(Debug, PartialEq, Eq)]
-struct ParseTodoError;
+#[derive(Parser)]
+#[command(name = "todo", version, about)]
+struct Cli {
+    /// Storage backend to load and save the todos with
+    #[arg(long, value_enum, default_value_t = Backend::Sqlite, global = true)]
+    storage: Backend,
 
-#[derive(Debug, PartialEq, Eq)]
-struct ParseMetadataError;
+    /// Path of the database [default: the nearest local database, or
+    /// $XDG_DATA_HOME/rust-todo/<backend file>]
+    #[arg(long, value_name = "PATH", global = true)]
+    db: Option<PathBuf>,
 
-impl FromStr for Todo {
-    type Err = ParseTodoError;
+    /// Use the global database even inside a directory with a local one
+    #[arg(long, conflicts_with = "db", global = true)]
+    global: bool,
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        let elements: Vec<&str> = s.split(',').collect();
+    /// Wait for another instance to close the database instead of failing
+    #[arg(long, global = true)]
+    wait: bool,
 
-        if elements.len() != 4 {
-            return Err(ParseTodoError);
-        }
+    /// Only print essential output
+    #[arg(short, long, global = true, conflicts_with = "verbose")]
+    quiet: bool,
 
-        let id = elements[0].parse::<u32>().unwrap();
-        let created_at = elements[1].parse::<DateTime<Local>>().unwrap();
-        let text = elements[2].to_string();
-        let is_completed = elements[3].parse::<bool>().unwrap();
+    /// Log what the storage is doing; repeat for more detail
+    #[arg(short, long, action = clap::ArgAction::Count, global = true)]
+    verbose: u8,
 
-        Ok(Todo {
-            id: id,
-            created_at: created_at,
-            text: text,
-            is_completed: is_completed,
-        })
-    }
+    /// Keep changes in memory until the menu is closed instead of persisting
+    /// every change right away
+    #[arg(long, global = true)]
+    no_autosave: bool,
+
+    /// Skip malformed lines of a text database instead of failing, moving
+    /// them to <db>.rejected
+    #[arg(long, global = true)]
+    lenient: bool,
+
+    /// Print without colors; setting NO_COLOR does the same
+    #[arg(long, global = true)]
+    no_color: bool,
+
+    /// Print long listings directly instead of through $PAGER, or less
+    #[arg(long, global = true)]
+    no_pager: bool,
+
+    /// Show and read times in this time zone, e.g. Europe/Berlin, instead of
+    /// the local one
+    #[arg(long, value_name = "ZONE", global = true, value_parser = dates::parse_time_zone)]
+    tz: Option<String>,
+
+    /// Runs the interactive menu when no command is given
+    #[command(subcommand)]
+    command: Option<Command>,
 }
 
-impl FromStr for Metadata {
-    type Err = ParseMetadataError;
+#[derive(Subcommand)]
+enum Command {
+    /// Create a new todo
+    Add {
+        /// Text of the todo; multiple words are joined with spaces
+        #[arg(
+            required_unless_present_any = ["stdin", "from_clipboard"],
+            conflicts_with_all = ["stdin", "from_clipboard"]
+        )]
+        text: Vec<String>,
 
-    fn from_str(s: &str) -> Result<Self, Self::Err> {
-        if !s.starts_with("seq_id:") {
-            return Err(ParseMetadataError);
-        }
+        /// Create one todo per line read from stdin
+        #[arg(long, conflicts_with = "from_clipboard")]
+        stdin: bool,
 
-        let elements: Vec<&str> = s.split(':').collect();
+        /// Create one todo per line of the text in the clipboard
+        #[arg(long)]
+        from_clipboard: bool,
 
-        if elements.len() != 2 {
-            return Err(ParseMetadataError);
-        }
+        /// Due date as YYYY-MM-DD, YYYY-MM-DD HH:MM or in words like
+        /// `tomorrow`, `next friday 5pm` or `in 3 days`
+        #[arg(long, value_parser = dates::parse_due)]
+        due: Option<DateTime<Local>>,
 
-        let seq_id = elements[1].parse::<u32>().unwrap();
+        /// When work on the todo can start, e.g. `monday`; it is hidden from
+        /// `list` until then
+        #[arg(long, value_name = "WHEN", value_parser = dates::parse_day)]
+        scheduled: Option<DateTime<Local>>,
 
-        Ok(Metadata { seq_id: seq_id })
-    }
-}
+        /// Priority of the todo
+        #[arg(short, long, value_enum, default_value_t = Priority::None)]
+        priority: Priority,
 
-impl fmt::Display for Todo {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(
-            f,
-            "{},{:?},{},{}",
-            self.id, self.created_at, self.text, self.is_completed
-        )
-    }
-}
+        /// Make the todo a subtask of this one
+        #[arg(long, value_name = "ID")]
+        parent: Option<u32>,
 
-impl fmt::Display for Metadata {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "seq_id:{}", self.seq_id)
-    }
-}
+        /// Put the todo on this list instead of the one in use
+        #[arg(long, value_name = "NAME", value_parser = lists::parse_name)]
+        list: Option<String>,
+    },
+    /// Create a new todo with the text, tags and priority of another
+    Clone { id: u32 },
+    /// Show the todos
+    List(Box<ListArgs>),
+    /// Browse and change the todos in a full-screen interface
+    Ui,
+    /// Move todos between the stages open, in progress and done on a board
+    Board,
+    /// Show a month with the number of todos due on each day
+    Calendar {
+        /// The month as YYYY-MM, by default the current one
+        #[arg(value_parser = calendar::parse_month)]
+        month: Option<NaiveDate>,
 
-fn main() -> Result<(), io::Error> {
-    let mut stdout = io::stdout();
-    let stdin = io::stdin();
-
-    let mut todos: Vec<Todo> = Vec::new();
-    let mut metadata = load_metadata();
-    load_todos(&mut todos);
-
-    loop {
-        println!("What do you want to do?");
-        println!("[1] Show all todos");
-        println!("[2] Show all open todos");
-        println!("[3] Create a new todo");
-        println!("[4] Set a todo as complete");
-        println!("[5] Delete a todo");
-        println!("[6] Close");
-
-        print!(">> ");
-        stdout.flush()?;
-
-        let mut input = String::new();
-        stdin.read_line(&mut input)?;
-
-        match input.trim() {
-            "1" => show_all_todos(&todos),
-            "2" => show_all_open_todos(&todos),
-            "3" => {
-                let new_todo = new_todo(&mut metadata);
-                todos.push(new_todo);
-            }
-            "4" => set_todo_completed(&mut todos),
-            "5" => delete_todo(&mut todos),
-            _ => {
-                save_todos(&metadata, &todos);
-                break;
-            }
-        }
-    }
+        /// Also list the todos due on this day of the month
+        #[arg(long, value_parser = clap::value_parser!(u32).range(1..=31))]
+        day: Option<u32>,
+    },
+    /// Show the overdue todos, those due, scheduled or snoozed until today
+    /// and the pinned ones
+    Today,
+    /// Show the todos due or scheduled on each of the next seven days
+    Week {
+        /// The first day, e.g. monday; by default today
+        #[arg(value_name = "FROM", value_parser = dates::parse_day)]
+        from: Option<DateTime<Local>>,
+    },
+    /// Show the todos containing a text in their text or notes, ignoring case
+    Search {
+        /// Multiple words are joined with spaces
+        #[arg(required = true)]
+        query: Vec<String>,
+    },
+    /// Print the number of todos matching a filter, e.g. for a status bar
+    Count {
+        /// The same filter as for `list`, e.g. `status:open +work`; without
+        /// one all todos are counted
+        #[arg(value_name = "FILTER")]
+        expression: Vec<String>,
+    },
+    /// Exit with 0 when a todo is due soon and 1 otherwise, printing a line
+    /// about them unless `--quiet` is given, e.g. for cron or a shell prompt
+    Check {
+        /// How soon counts as soon, e.g. `30m`; overdue todos always do
+        #[arg(long, value_name = "AGE", value_parser = dates::parse_age, default_value = "24h")]
+        due_within: chrono::Duration,
+    },
+    /// Set todos as complete
+    Done {
+        /// Ids, ranges of ids or texts, e.g. `1 3 7-9 milk`
+        #[arg(required_unless_present = "filter", conflicts_with = "filter")]
+        ids: Vec<IdSelector>,
 
-    Ok(())
-}
+        /// Complete all open todos matching this filter instead, e.g.
+        /// `tag:shopping`
+        #[arg(long, value_name = "FILTER")]
+        filter: Option<String>,
+
+        /// Only print which todos would be completed
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Complete the todos matching `--filter` without asking for
+        /// confirmation
+        #[arg(short, long)]
+        force: bool,
+
+        /// Also close the GitHub issues the todos were imported from
+        #[arg(long)]
+        close_issues: bool,
+    },
+    /// Set done or cancelled todos as open again
+    Reopen {
+        /// Ids, ranges of ids or texts, e.g. `1 3 7-9 milk`
+        #[arg(required = true)]
+        ids: Vec<IdSelector>,
+    },
+    /// Set open todos as complete and closed ones as open
+    Toggle {
+        /// Ids, ranges of ids or texts, e.g. `1 3 7-9 milk`
+        #[arg(required = true)]
+        ids: Vec<IdSelector>,
+    },
+    /// Delete todos
+    Rm {
+        /// Ids, ranges of ids or texts, e.g. `1 3 7-9 milk`
+        #[arg(
+            required_unless_present_any = ["filter", "completed"],
+            conflicts_with_all = ["filter", "completed"]
+        )]
+        ids: Vec<IdSelector>,
 
-fn load_metadata() -> Metadata {
-    let f = File::open("todos_db.txt").unwrap();
-    let mut reader = BufReader::new(f);
-    let mut line = String::new();
-    reader.read_line(&mut line).unwrap();
+        /// Delete all todos matching this filter instead
+        #[arg(long, value_name = "FILTER")]
+        filter: Option<String>,
 
-    line.trim().parse::<Metadata>().unwrap()
+        /// Delete all done and cancelled todos instead, or those of them
+        /// matching `--filter`
+        #[arg(long)]
+        completed: bool,
+
+        /// With `--completed`, only delete todos done or cancelled longer ago
+        /// than this, e.g. `30d` or `2 weeks`
+        #[arg(long, value_name = "AGE", requires = "completed", value_parser = dates::parse_age)]
+        older_than: Option<chrono::Duration>,
+
+        /// Only print which todos would be deleted
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Delete without asking for confirmation
+        #[arg(short, long)]
+        force: bool,
+    },
+    /// Move old done and cancelled todos to an archive next to the database
+    Archive {
+        /// Only those closed longer ago than this
+        #[arg(long, value_name = "AGE", default_value = "30d", value_parser = dates::parse_age)]
+        older_than: chrono::Duration,
+    },
+    /// Show or empty the deleted todos
+    Trash {
+        #[command(subcommand)]
+        command: TrashCommand,
+    },
+    /// Create todos from another service
+    Import {
+        #[command(subcommand)]
+        source: ImportSource,
+    },
+    /// Exchange the changes with another service
+    Sync {
+        #[command(subcommand)]
+        service: SyncService,
+    },
+    /// Move deleted todos out of the trash again
+    Restore {
+        #[arg(required = true)]
+        ids: Vec<u32>,
+    },
+    /// Set or clear the due date of a todo
+    Due {
+        id: u32,
+
+        /// Due date as YYYY-MM-DD, YYYY-MM-DD HH:MM or in words like
+        /// `tomorrow`, `next friday 5pm` or `in 3 days`
+        #[arg(value_parser = dates::parse_due, required_unless_present = "clear")]
+        date: Option<DateTime<Local>>,
+
+        /// Remove the due date
+        #[arg(long, conflicts_with = "date")]
+        clear: bool,
+    },
+    /// Change the priority of a todo
+    Priority {
+        id: u32,
+        #[arg(value_enum)]
+        priority: Priority,
+    },
+    /// Change the status of a todo, e.g. to `in-progress` or `blocked`; without
+    /// arguments, print how many todos have each status, e.g. for a prompt
+    Status {
+        #[arg(requires = "status")]
+        id: Option<u32>,
+        #[arg(value_enum)]
+        status: Option<Status>,
+    },
+    /// Hide a todo from the listings until then, e.g. `tomorrow`, `monday`
+    /// or "in 2 hours"; `list --all` still shows it
+    Snooze {
+        id: u32,
+
+        #[arg(value_name = "WHEN", value_parser = dates::parse_day, required_unless_present = "clear")]
+        until: Option<DateTime<Local>>,
+
+        /// Show the todo again right away
+        #[arg(long, conflicts_with = "until")]
+        clear: bool,
+    },
+    /// Set when work on a todo can start, e.g. `monday` or `2024-07-01`; it
+    /// is hidden from `list` until then, but not late like after a due date
+    Schedule {
+        id: u32,
+
+        #[arg(value_name = "WHEN", value_parser = dates::parse_day, required_unless_present = "clear")]
+        start: Option<DateTime<Local>>,
+
+        /// Remove the start date
+        #[arg(long, conflicts_with = "start")]
+        clear: bool,
+    },
+    /// Show the lists with the number of open todos on each
+    Lists,
+    /// Put new todos on a list and only show the todos on it, or all todos
+    /// again with `--all`
+    Use {
+        #[arg(value_parser = lists::parse_name, required_unless_present = "all")]
+        list: Option<String>,
+
+        /// Use all lists
+        #[arg(long, conflicts_with = "list")]
+        all: bool,
+    },
+    /// Move a todo to another list, or off all lists without LIST
+    Move {
+        id: u32,
+        #[arg(value_parser = lists::parse_name)]
+        list: Option<String>,
+    },
+    /// Always list a todo first
+    Pin { id: u32 },
+    /// Undo `pin`
+    Unpin { id: u32 },
+    /// Make a todo a subtask of another, or a top-level todo without PARENT
+    Parent { id: u32, parent: Option<u32> },
+    /// Merge a duplicate into a todo, keeping the notes, tags and fields of
+    /// both, and delete the duplicate
+    Merge { id: u32, duplicate: u32 },
+    /// Set or remove custom fields of a todo
+    Field {
+        id: u32,
+
+        /// `key:value` to set a field, `key:` to remove it
+        #[arg(required = true)]
+        changes: Vec<FieldChange>,
+    },
+    /// Add or remove tags of a todo
+    Tag {
+        id: u32,
+
+        /// `+tag` to add a tag, `-tag` to remove it
+        #[arg(required = true, allow_hyphen_values = true)]
+        changes: Vec<TagChange>,
+    },
+    /// Change the text of a todo
+    Edit {
+        id: u32,
+
+        /// New text; without it the current text is opened in $EDITOR
+        text: Vec<String>,
+    },
+    /// Edit the notes of a todo in $EDITOR
+    Note { id: u32 },
+    /// Show all details of a todo, including its notes
+    Show { id: u32 },
+    /// Print all todos in a format for other tools
+    Export {
+        #[arg(long, value_enum, default_value_t = ExportFormat::Markdown)]
+        format: ExportFormat,
+
+        /// Put the todos into sections by their status or tags
+        #[arg(long, value_enum, default_value_t = GroupBy::Status)]
+        group_by: GroupBy,
+    },
+    /// Send desktop notifications for the todos coming due, each once; the
+    /// `remind` field of a todo, e.g. `remind:1h`, notifies that long before
+    Remind {
+        /// Keep checking every 30 seconds until interrupted, e.g. as a
+        /// service started with the desktop session
+        #[arg(long)]
+        daemon: bool,
+    },
+    /// Write a report of all todos to share or print
+    Report {
+        /// Write a standalone HTML page to this file
+        #[arg(long, value_name = "FILE")]
+        html: PathBuf,
+    },
+    /// Create a database in the current directory, or at `--db`
+    Init,
+    /// Give todos sharing an id new ids
+    Repair,
+    /// Give all todos sequential ids from 1 on, e.g. after many deletions
+    Renumber {
+        /// Renumber without asking for confirmation
+        #[arg(short, long)]
+        force: bool,
+    },
+    /// Print a shell completion script
+    Completions { shell: Shell },
 }
 
-fn load_todos(todos: &mut Vec<Todo>) {
-    // Read todos from db file
-    let f = File::open("todos_db.txt").unwrap();
-    let reader = BufReader::new(f);
-    let mut count = 0;
-    for line in reader.lines() {
-        if count == 0 {
-            count += 1;
-            continue;
+impl Command {
+    /// Whether the command may change todos. Only those escalate the overdue
+    /// todos first, so looking at the todos never writes to the database.
+    fn changes_todos(&self) -> bool {
+        match self {
+            Command::List(_)
+            | Command::Calendar { .. }
+            | Command::Today
+            | Command::Week { .. }
+            | Command::Search { .. }
+            | Command::Count { .. }
+            | Command::Check { .. }
+            | Command::Lists
+            | Command::Use { .. }
+            | Command::Show { .. }
+            | Command::Export { .. }
+            | Command::Report { .. }
+            | Command::Trash {
+                command: TrashCommand::List,
+            } => false,
+            // Only prints how many todos have each status
+            Command::Status { id: None, .. } => false,
+            // Escalates on every check itself
+            Command::Remind { .. } => false,
+            Command::Done { dry_run, .. } | Command::Rm { dry_run, .. } => !dry_run,
+            _ => true,
         }
-        let t = line.unwrap().parse::<Todo>().unwrap();
-        todos.push(t);
     }
 }
 
-fn save_todos(metadata: &Metadata, todos: &Vec<Todo>) {
-    // Store todos in a file
-    let mut f = File::create("todos_db.txt").unwrap();
-
-    let todos_buf = todos
-        .iter()
-        .map(|t| t.to_string())
-        .collect::<Vec<String>>()
-        .join("\n");
+#[derive(Subcommand)]
+enum TrashCommand {
+    /// Show the deleted todos, the most recent first
+    List,
+    /// Delete the todos in the trash for good
+    Empty {
+        /// Only those deleted longer ago than this, e.g. `30d`
+        #[arg(long, value_name = "AGE", value_parser = dates::parse_age)]
+        older_than: Option<chrono::Duration>,
 
-    f.write(metadata.to_string().as_bytes()).unwrap();
-    f.write(b"\n").unwrap();
-    f.write(todos_buf.as_bytes()).unwrap();
+        /// Delete without asking for confirmation
+        #[arg(short, long)]
+        force: bool,
+    },
 }
 
-fn show_all_todos(todos: &Vec<Todo>) {
-    print_todos(&todos, false);
+#[derive(Subcommand)]
+enum ImportSource {
+    /// Create a todo for every open issue of a repository that has none
+    /// yet, with the issue's URL in the `github` field
+    Github {
+        /// The repository as owner/name
+        #[arg(long, value_parser = github::parse_repo)]
+        repo: String,
+
+        /// Only the issues assigned to this user; `me` for the owner of the
+        /// token under `[github]` in the config
+        #[arg(long, value_name = "USER")]
+        assignee: Option<String>,
+    },
+    /// Create a todo for every task of a `task export` file that has none
+    /// yet, keeping its uuid
+    Taskwarrior { file: PathBuf },
 }
 
-fn show_all_open_todos(todos: &Vec<Todo>) {
-    print_todos(&todos, true);
+#[derive(Subcommand)]
+enum SyncService {
+    /// Push the changes to Todoist and pull those made there; the newer
+    /// change wins. Needs `token` under `[todoist]` in the config
+    Todoist,
+    /// Store the todos as VTODOs in a CalDAV task list, like Nextcloud Tasks,
+    /// and take over the changes made there. Needs `url` under `[caldav]` in
+    /// the config
+    Caldav,
+    /// Merge the todos with those committed to a git repository, then commit
+    /// and push them. Needs `repository` under `[git]` in the config
+    Git,
 }
 
-fn new_todo(metadata: &mut Metadata) -> Todo {
-    let mut input_todo = String::new();
-    io::stdin().read_line(&mut input_todo).unwrap();
+/// Options of `todo list`, boxed as they make up most of `Command`
+#[derive(Args)]
+struct ListArgs {
+    /// Only show todos that are not done or cancelled
+    #[arg(long)]
+    open: bool,
 
-    metadata.seq_id += 1;
+    /// Also show snoozed todos and those scheduled to start later
+    #[arg(long)]
+    all: bool,
 
-    Todo {
-        id: metadata.seq_id,
-        is_completed: false,
-        text: input_todo.trim().into(),
-        created_at: Local::now(),
-    }
-}
+    /// Only show todos with one of these statuses
+    #[arg(long, value_enum)]
+    status: Vec<Status>,
 
-fn set_todo_completed(todos: &mut Vec<Todo>) {
-    let mut input_todo_id = String::new();
-    io::stdin().read_line(&mut input_todo_id).unwrap();
+    /// Only show todos with this tag; repeat for several
+    #[arg(long = "tag", value_name = "TAG", value_parser = filter::parse_tag_name)]
+    tags: Vec<String>,
 
-    let id = input_todo_id.trim().parse::<u32>().unwrap();
+    /// Hide todos with this tag; repeat for several
+    #[arg(long = "not-tag", value_name = "TAG", value_parser = filter::parse_tag_name)]
+    not_tags: Vec<String>,
 
-    let todo = todos.iter_mut().find(|t| t.id == id);
+    /// Only show todos past their due date
+    #[arg(long)]
+    overdue: bool,
 
-    match todo {
-        Some(t) => t.is_completed = true,
-        None => println!("Could not find Todo by that id"),
-    }
-}
+    /// Only show todos without a priority
+    #[arg(long)]
+    no_priority: bool,
+
+    /// Only show todos changed since then, e.g. "2 days ago", "yesterday"
+    /// or 2024-05-01
+    #[arg(long, value_name = "WHEN", value_parser = dates::parse_since)]
+    modified_since: Option<DateTime<Local>>,
+
+    /// Only show todos created at or after then, e.g. "last week",
+    /// "last monday", "3 days ago" or 2024-05-01
+    #[arg(long, value_name = "WHEN", value_parser = dates::parse_day)]
+    created_after: Option<DateTime<Local>>,
+
+    /// Only show todos created before then
+    #[arg(long, value_name = "WHEN", value_parser = dates::parse_day)]
+    created_before: Option<DateTime<Local>>,
+
+    /// Only show todos completed at or after then
+    #[arg(long, value_name = "WHEN", value_parser = dates::parse_day)]
+    completed_after: Option<DateTime<Local>>,
+
+    /// Only show todos completed before then
+    #[arg(long, value_name = "WHEN", value_parser = dates::parse_day)]
+    completed_before: Option<DateTime<Local>>,
 
-fn delete_todo(todos: &mut Vec<Todo>) {
-    let mut input_todo_id = String::new();
-    io::stdin().read_line(&mut input_todo_id).unwrap();
+    /// Only show todos whose text matches this regular expression, e.g.
+    /// 'release v\d+'
+    #[arg(long, value_name = "PATTERN", value_parser = filter::parse_regex)]
+    regex: Option<Regex>,
 
-    let id = input_todo_id.trim().parse::<u32>().unwrap();
+    /// Only show todos with this custom field, or with this value for it
+    #[arg(long = "field", value_name = "KEY[:VALUE]")]
+    fields: Vec<FieldFilter>,
 
-    let todo_index = todos.iter().position(|t| t.id == id);
+    /// Add columns with these custom fields, e.g. `ticket,estimate`
+    #[arg(long, value_name = "KEYS", value_delimiter = ',')]
+    show_fields: Vec<String>,
 
-    match todo_index {
-        Some(index) => {
-            todos.remove(index);
+    /// Columns of the table in this order, e.g. `id,text,due,priority`
+    #[arg(long, value_enum, value_delimiter = ',')]
+    columns: Option<Vec<Column>>,
+
+    /// Show dates relative to now, e.g. "2 days ago", or as days
+    #[arg(long, value_enum)]
+    dates: Option<DateStyle>,
+
+    /// Split the list into sections with the number of todos in each
+    #[arg(long, value_enum)]
+    group_by: Option<GroupBy>,
+
+    /// Sort by these keys, e.g. `priority,due`; by default overdue and
+    /// important todos come first
+    #[arg(long, value_enum, value_delimiter = ',')]
+    sort: Vec<SortKey>,
+
+    /// Reverse the order
+    #[arg(long)]
+    reverse: bool,
+
+    /// Show subtasks below their parents, with the number of closed
+    /// subtasks per parent
+    #[arg(long, conflicts_with = "group_by")]
+    tree: bool,
+
+    /// Only show todos matching this filter, e.g. `+work`, `+work
+    /// priority:high` or `status:open and (+work or due.before:friday)`
+    #[arg(value_name = "FILTER")]
+    expression: Vec<String>,
+
+    /// Show the archived todos instead, see `todo archive`
+    #[arg(long)]
+    archived: bool,
+
+    /// Keep the list on screen and refresh it whenever the database changes
+    #[arg(long, conflicts_with = "archived")]
+    watch: bool,
+
+    /// Output format
+    #[arg(long, value_enum, default_value_t = Format::Plain)]
+    format: Format,
+}
+
+fn main() -> ExitCode {
+    match run() {
+        Ok(()) => ExitCode::SUCCESS,
+        Err(e) if e.is_broken_pipe() => ExitCode::SUCCESS,
+        Err(e) => {
+            eprintln!("Error: {}", e);
+            ExitCode::from(e.exit_code())
         }
-        None => println!("Could not find Todo by that id"),
     }
 }
 
-fn print_todos(todos: &Vec<Todo>, only_open_todos: bool) {
-    let column_sizes = get_size_for_columns(&todos);
+fn run() -> Result<(), TodoError> {
+    let config = Config::load()?;
+    let args = config.expand_aliases(env::args().collect(), &Cli::command());
+    // Due dates are read while the arguments are parsed, so their time zone
+    // has to be in place before
+    if let Some(tz) = time_zone_arg(&args) {
+        env::set_var("TZ", tz);
+    }
+    let cli = Cli::parse_from(args);
 
-    println!("");
-    for todo in todos {
-        if only_open_todos && todo.is_completed == true {
-            continue;
-        }
+    output::set_quiet(cli.quiet);
+    let renderer = Renderer::detect(cli.no_color, cli.no_pager);
+    init_logger(&cli);
+    if let Some(tz) = &cli.tz {
+        log::debug!("Using the time zone {}", tz);
+    }
 
-        let created_at = todo.created_at.format("%d.%m.%Y");
-        print!("{:>width$}", todo.id, width = column_sizes[0]);
-        print!(" {:>width$}", created_at, width = column_sizes[1]);
-        print!(" {:<width$}", todo.text, width = column_sizes[2]);
-        print!(" {:>width$}", todo.is_completed, width = column_sizes[3]);
-        println!();
+    if let Some(Command::Completions { shell }) = cli.command {
+        let mut cmd = Cli::command();
+        let name = cmd.get_name().to_string();
+        clap_complete::generate(shell, &mut cmd, name, &mut io::stdout());
+        return Ok(());
+    }
+
+    if let Some(Command::Init) = cli.command {
+        let path = cli
+            .db
+            .unwrap_or_else(|| PathBuf::from(cli.storage.file_name()));
+        return commands::init(cli.storage, &path);
+    }
+
+    let db_path = match cli.db {
+        Some(path) => path,
+        None => storage::resolve_path(cli.storage, cli.global)?,
+    };
+    let mut db = Database::open(
+        cli.storage,
+        &db_path,
+        cli.wait,
+        !cli.no_autosave,
+        cli.lenient,
+    )
+    .map_err(|e| TodoError::from(e).in_file(&db_path))?;
+    db.max_text_length = config.max_text_length;
+    db.webhook = config.webhook.as_ref().map(Webhook::new);
+    db.active_list = lists::active(&db_path)?;
+    if cli.command.as_ref().is_none_or(Command::changes_todos) {
+        escalation::run(&mut db, &config.escalation)?;
+    }
+    if cli.no_autosave {
+        db.save_on_interrupt()?;
     }
-    println!("");
-}
 
-fn get_size_for_columns(todos: &Vec<Todo>) -> Vec<usize> {
-    let mut column_sizes: Vec<usize> = Vec::new();
-    let mut id_column_size = 0;
-    let mut text_column_size = 0;
+    // For the listings without options for the layout
+    let default_layout = Layout {
+        columns: config.columns.clone(),
+        dates: config.dates,
+        urgency: config.urgency.clone(),
+        ..Layout::default()
+    };
 
-    for todo in todos {
-        let id_str = todo.id.to_string();
-        if id_str.len() > id_column_size {
-            id_column_size = id_str.len();
+    match cli.command {
+        None => interactive::run(&mut db, &renderer, &default_layout),
+        Some(Command::Ui) => tui::run(&mut db),
+        Some(Command::Board) => board::run(&mut db),
+        Some(Command::Calendar { month, day }) => {
+            commands::calendar(&db, &renderer, &default_layout, month, day)
+        }
+        Some(Command::Today) => commands::today(&db, &renderer, &default_layout),
+        Some(Command::Week { from }) => commands::week(&db, &renderer, &default_layout, from),
+        Some(Command::Search { query }) => {
+            commands::search(&db, &renderer, &default_layout, &query.join(" "))
+        }
+        Some(Command::Add { stdin: true, .. }) => commands::add_from_stdin(&mut db),
+        Some(Command::Add {
+            from_clipboard: true,
+            ..
+        }) => commands::add_from_clipboard(&mut db),
+        Some(Command::Add {
+            text,
+            due,
+            scheduled,
+            priority,
+            parent,
+            list,
+            ..
+        }) => {
+            if list.is_some() {
+                db.active_list = list;
+            }
+            commands::add(&mut db, &text.join(" "), due, scheduled, priority, parent)
+        }
+        Some(Command::Clone { id }) => commands::clone(&mut db, id),
+        Some(Command::List(args)) => {
+            let ListArgs {
+                open,
+                all,
+                status,
+                tags,
+                not_tags,
+                overdue,
+                no_priority,
+                modified_since,
+                created_after,
+                created_before,
+                completed_after,
+                completed_before,
+                regex,
+                fields,
+                show_fields,
+                columns,
+                dates,
+                group_by,
+                sort,
+                reverse,
+                tree,
+                expression,
+                archived,
+                watch,
+                format,
+            } = *args;
+            let expression = parse_expression(&config, &expression, "list");
+            let filter = Filter {
+                only_open: open,
+                hide_deferred: !all,
+                statuses: status,
+                tags,
+                not_tags,
+                overdue,
+                no_priority,
+                expression,
+                modified_since,
+                created_after,
+                created_before,
+                completed_after,
+                completed_before,
+                fields,
+                regex,
+                ..Filter::default()
+            };
+            let layout = Layout {
+                columns: columns.or(config.columns),
+                fields: show_fields.iter().map(|f| f.to_lowercase()).collect(),
+                dates: dates.unwrap_or(config.dates),
+                group_by,
+                tree,
+                sort,
+                reverse,
+                urgency: config.urgency.clone(),
+                ..Layout::default()
+            };
+            if watch {
+                // Listings that change all the time cannot be paged
+                let renderer = Renderer::detect(cli.no_color, true);
+                drop(db);
+                commands::watch(
+                    cli.storage,
+                    &db_path,
+                    cli.lenient,
+                    &renderer,
+                    &filter,
+                    format,
+                    &layout,
+                )
+            } else if archived {
+                commands::list_archived(&db_path, &renderer, &filter, format, &layout)
+            } else {
+                commands::list(&db, &renderer, &filter, format, &layout)
+            }
         }
+        Some(Command::Count { expression }) => {
+            let filter = Filter {
+                expression: parse_expression(&config, &expression, "count"),
+                ..Filter::default()
+            };
+            commands::count(&db, &filter)
+        }
+        Some(Command::Check { due_within }) => {
+            let due = commands::check(&db, due_within)?;
+            if !due {
+                // Like grep, finding nothing is no error but shows in the
+                // exit code
+                drop(db);
+                process::exit(1);
+            }
+            Ok(())
+        }
+        Some(Command::Done {
+            ids,
+            filter,
+            dry_run,
+            force,
+            close_issues,
+        }) => {
+            let filter = filter.map(|filter| Filter {
+                only_open: true,
+                expression: parse_expression(&config, &[filter], "done"),
+                ..Filter::default()
+            });
+            let github = match (close_issues, &config.github) {
+                (false, _) => None,
+                (true, Some(github)) => Some(github),
+                (true, None) => Err(TodoError::parse(
+                    "set a GitHub token as `token` under `[github]` in the config to close issues",
+                ))?,
+            };
+            commands::done(&mut db, &ids, filter.as_ref(), dry_run, force, github)
+        }
+        Some(Command::Reopen { ids }) => commands::reopen(&mut db, &ids),
+        Some(Command::Toggle { ids }) => commands::toggle(&mut db, &ids),
+        Some(Command::Rm {
+            ids,
+            filter,
+            completed,
+            older_than,
+            dry_run,
+            force,
+        }) => {
+            let closed_before = older_than
+                .map(dates::ago)
+                .transpose()
+                .map_err(TodoError::parse)?;
+            let filter = (filter.is_some() || completed).then(|| Filter {
+                statuses: if completed {
+                    let statuses = Status::value_variants().iter().copied();
+                    statuses.filter(|status| status.is_closed()).collect()
+                } else {
+                    vec![]
+                },
+                closed_before,
+                expression: filter.and_then(|f| parse_expression(&config, &[f], "rm")),
+                ..Filter::default()
+            });
+            commands::rm(&mut db, &ids, filter.as_ref(), dry_run, force)
+        }
+        Some(Command::Archive { older_than }) => commands::archive(&mut db, &db_path, older_than),
+        Some(Command::Trash {
+            command: TrashCommand::List,
+        }) => commands::trash(&db),
+        Some(Command::Trash {
+            command: TrashCommand::Empty { older_than, force },
+        }) => commands::empty_trash(&mut db, older_than, force),
+        Some(Command::Import {
+            source: ImportSource::Github { repo, assignee },
+        }) => github::import(&mut db, config.github.as_ref(), &repo, assignee.as_deref()),
+        Some(Command::Import {
+            source: ImportSource::Taskwarrior { file },
+        }) => taskwarrior::import(&mut db, &file),
+        Some(Command::Sync {
+            service: SyncService::Todoist,
+        }) => match &config.todoist {
+            Some(todoist) => todoist::sync(&mut db, &db_path, &todoist.token),
+            None => Err(TodoError::parse(
+                "set the Todoist API token as `token` under `[todoist]` in the config",
+            )),
+        },
+        Some(Command::Sync {
+            service: SyncService::Caldav,
+        }) => match &config.caldav {
+            Some(caldav) => caldav::sync(&mut db, &db_path, caldav),
+            None => Err(TodoError::parse(
+                "set the URL of the task list as `url` under `[caldav]` in the config",
+            )),
+        },
+        Some(Command::Sync {
+            service: SyncService::Git,
+        }) => match &config.git {
+            Some(git) => git_sync::sync(&mut db, git),
+            None => Err(TodoError::parse(
+                "set the path of the repository as `repository` under `[git]` in the config",
+            )),
+        },
+        Some(Command::Restore { ids }) => commands::restore(&mut db, &ids),
+        Some(Command::Due { id, date, .. }) => commands::due(&mut db, id, date),
+        Some(Command::Priority { id, priority }) => commands::priority(&mut db, id, priority),
+        Some(Command::Status {
+            id: Some(id),
+            status: Some(status),
+        }) => commands::status(&mut db, id, status),
+        Some(Command::Status { .. }) => commands::summary(&db),
+        Some(Command::Snooze { id, until, .. }) => commands::snooze(&mut db, id, until),
+        Some(Command::Schedule { id, start, .. }) => commands::schedule(&mut db, id, start),
+        Some(Command::Lists) => lists::print(&db),
+        Some(Command::Use { list, .. }) => lists::use_list(&db_path, list.as_deref()),
+        Some(Command::Move { id, list }) => commands::move_to_list(&mut db, id, list),
+        Some(Command::Pin { id }) => commands::pin(&mut db, id, true),
+        Some(Command::Unpin { id }) => commands::pin(&mut db, id, false),
+        Some(Command::Parent { id, parent }) => commands::set_parent(&mut db, id, parent),
+        Some(Command::Merge { id, duplicate }) => commands::merge(&mut db, id, duplicate),
+        Some(Command::Field { id, changes }) => commands::field(&mut db, id, &changes),
+        Some(Command::Tag { id, changes }) => commands::tag(&mut db, id, &changes),
+        Some(Command::Edit { id, text }) => {
+            let text = (!text.is_empty()).then(|| text.join(" "));
+            commands::edit(&mut db, id, text.as_deref())
+        }
+        Some(Command::Note { id }) => commands::note(&mut db, id),
+        Some(Command::Show { id }) => commands::show(&db, id),
+        Some(Command::Export { format, group_by }) => commands::export(&db, format, group_by),
+        Some(Command::Report { html }) => commands::report(&db, &html),
+        Some(Command::Remind { daemon }) => {
+            let lead_time = match &config.reminders.lead_time {
+                Some(lead_time) => dates::parse_age(lead_time).map_err(|e| {
+                    TodoError::parse(format!("`lead_time` under `[reminders]`: {}", e))
+                })?,
+                None => chrono::Duration::zero(),
+            };
+            drop(db);
+            let rules = &config.escalation;
+            remind::run(cli.storage, &db_path, cli.lenient, lead_time, rules, daemon)
+        }
+        Some(Command::Repair) => commands::repair(&mut db),
+        Some(Command::Renumber { force }) => commands::renumber(&mut db, force),
+        Some(Command::Init | Command::Completions { .. }) => {
+            unreachable!("handled before opening the database")
+        }
+    }
+}
 
-        if todo.text.len() > text_column_size {
-            text_column_size = todo.text.len();
+/// Parses the FILTER words of `subcommand`, with saved filters expanded. An
+/// invalid filter exits with a usage error like any other invalid argument.
+fn parse_expression(config: &Config, words: &[String], subcommand: &str) -> Option<query::Expr> {
+    (!words.is_empty())
+        .then(|| {
+            let expression = config.expand_filters(&words.join(" "))?;
+            query::parse(&expression)
+        })
+        .transpose()
+        .unwrap_or_else(|e| {
+            let mut cmd = Cli::command();
+            // Sets the name shown in the usage of the subcommand
+            cmd.build();
+            let subcommand = cmd
+                .find_subcommand_mut(subcommand)
+                .expect("the filter belongs to a command");
+            subcommand
+                .error(clap::error::ErrorKind::ValueValidation, e)
+                .exit()
+        })
+}
+
+/// The value of `--tz` in the arguments, if given.
+fn time_zone_arg(args: &[String]) -> Option<&str> {
+    let mut args = args.iter().skip(1).take_while(|arg| *arg != "--");
+    while let Some(arg) = args.next() {
+        if arg == "--tz" {
+            return args.next().map(String::as_str);
+        }
+        if let Some(tz) = arg.strip_prefix("--tz=") {
+            return Some(tz);
         }
     }
 
-    column_sizes.push(id_column_size);
-    column_sizes.push(10);
-    column_sizes.push(text_column_size);
-    column_sizes.push(4);
+    None
+}
+
+fn init_logger(cli: &Cli) {
+    let level = match (cli.quiet, cli.verbose) {
+        (true, _) => log::LevelFilter::Error,
+        (false, 0) => log::LevelFilter::Warn,
+        (false, 1) => log::LevelFilter::Info,
+        (false, 2) => log::LevelFilter::Debug,
+        (false, _) => log::LevelFilter::Trace,
+    };
+
+    env_logger::Builder::new()
+        .filter_level(level)
+        .format_timestamp(None)
+        .parse_default_env()
+        .init();
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn changes_todos(args: &str) -> bool {
+        let cli = Cli::try_parse_from(args.split_whitespace()).unwrap();
+        cli.command.as_ref().is_none_or(Command::changes_todos)
+    }
 
-    column_sizes
+    #[test]
+    fn only_commands_changing_todos_escalate() {
+        for args in ["todo add milk", "todo done 1", "todo status 1 done", "todo"] {
+            assert!(changes_todos(args), "{}", args);
+        }
+        for args in [
+            "todo list",
+            "todo status",
+            "todo done 1 --dry-run",
+            "todo rm --completed --dry-run",
+            "todo trash list",
+        ] {
+            assert!(!changes_todos(args), "{}", args);
+        }
+    }
 }
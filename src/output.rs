@@ -0,0 +1,765 @@
+use crate::csv;
+use crate::dates;
+use crate::group::{Group, GroupBy};
+use crate::todo::{Priority, Status, Todo};
+use crate::tree::{self, Node};
+use crate::urgency::{self, Weights};
+use chrono::prelude::*;
+use clap::ValueEnum;
+use colored::{ColoredString, Colorize};
+use serde::Deserialize;
+use std::fmt;
+use std::io::{self, Write};
+use std::sync::atomic::{AtomicBool, Ordering};
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+/// Suppresses the messages printed by `status`, for `--quiet`.
+pub fn set_quiet(quiet: bool) {
+    QUIET.store(quiet, Ordering::Relaxed);
+}
+
+pub fn is_quiet() -> bool {
+    QUIET.load(Ordering::Relaxed)
+}
+
+/// Prints a non-essential message, like the id of a created todo.
+pub fn status(message: impl fmt::Display) {
+    if !is_quiet() {
+        println!("{}", message);
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Format {
+    /// Aligned table for humans
+    Plain,
+    /// JSON array of todos
+    Json,
+    /// Comma separated values with a header row
+    Csv,
+}
+
+/// A built-in column of the plain table.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Column {
+    Id,
+    Uuid,
+    Created,
+    Updated,
+    Due,
+    Priority,
+    /// The text together with the tags
+    Text,
+    Status,
+    /// How urgent the todo is, see `urgency`
+    Urgency,
+}
+
+impl Column {
+    /// Shown when neither `--columns` nor the config choose the columns.
+    /// Due and priority are left out when no todo has one.
+    const DEFAULT: &'static [Column] = &[
+        Column::Id,
+        Column::Created,
+        Column::Due,
+        Column::Priority,
+        Column::Text,
+        Column::Status,
+    ];
+
+    fn header(self) -> &'static str {
+        match self {
+            Column::Id => "ID",
+            Column::Uuid => "UUID",
+            Column::Created => "CREATED",
+            Column::Updated => "UPDATED",
+            Column::Due => "DUE",
+            Column::Priority => "PRIORITY",
+            Column::Text => "TEXT",
+            Column::Status => "STATUS",
+            Column::Urgency => "URGENCY",
+        }
+    }
+}
+
+/// How the plain table shows dates.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, ValueEnum, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum DateStyle {
+    /// Distance from now, e.g. `2 days ago` or `in 3 h`
+    #[default]
+    Relative,
+    /// The day, e.g. `14.05.2024`
+    Absolute,
+}
+
+impl DateStyle {
+    fn format(self, date: DateTime<Local>, now: DateTime<Local>) -> String {
+        match self {
+            DateStyle::Relative => dates::format_relative(date, now),
+            DateStyle::Absolute => date.format("%d.%m.%Y").to_string(),
+        }
+    }
+}
+
+/// What listings can be sorted by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum SortKey {
+    Id,
+    /// Oldest first
+    Created,
+    /// Least recently changed first
+    Updated,
+    /// Soonest first, todos without a due date last
+    Due,
+    /// Most important first
+    Priority,
+    /// Alphabetically, ignoring case
+    Text,
+    /// Most urgent first
+    Urgency,
+}
+
+impl SortKey {
+    pub fn compare(
+        self,
+        a: &Todo,
+        b: &Todo,
+        weights: &Weights,
+        now: DateTime<Local>,
+    ) -> std::cmp::Ordering {
+        match self {
+            SortKey::Id => a.id.cmp(&b.id),
+            SortKey::Created => a.created_at.cmp(&b.created_at),
+            SortKey::Updated => a.updated_at.cmp(&b.updated_at),
+            SortKey::Due => match (a.due_at, b.due_at) {
+                (Some(a), Some(b)) => a.cmp(&b),
+                (a, b) => b.is_some().cmp(&a.is_some()),
+            },
+            SortKey::Priority => b.priority.cmp(&a.priority),
+            SortKey::Text => a.text.to_lowercase().cmp(&b.text.to_lowercase()),
+            SortKey::Urgency => compare_urgency(a, b, weights, now),
+        }
+    }
+}
+
+/// Orders the more urgent todo first.
+pub fn compare_urgency(
+    a: &Todo,
+    b: &Todo,
+    weights: &Weights,
+    now: DateTime<Local>,
+) -> std::cmp::Ordering {
+    urgency::score(b, weights, now).total_cmp(&urgency::score(a, weights, now))
+}
+
+/// Which columns the plain table shows, in what order, and how.
+#[derive(Debug, Clone, Default)]
+pub struct Layout {
+    /// `None` for the default columns
+    pub columns: Option<Vec<Column>>,
+    /// Custom fields shown after the text, or at the end without it
+    pub fields: Vec<String>,
+    pub dates: DateStyle,
+    /// Sections to split the list into
+    pub group_by: Option<GroupBy>,
+    /// Show subtasks indented below their parents
+    pub tree: bool,
+    /// Lowercase text to highlight in the text column
+    pub highlight: Option<String>,
+    /// Keys to sort by, the first one deciding most; without keys the todos
+    /// are sorted by importance
+    pub sort: Vec<SortKey>,
+    /// Sort in the opposite direction
+    pub reverse: bool,
+    /// The weights of the urgency column and sort key
+    pub urgency: Weights,
+}
+
+pub trait Formatter {
+    fn write_todos(&self, out: &mut dyn Write, todos: &[&Todo]) -> io::Result<()>;
+
+    /// Writes the todos in sections. Formats without sections write every
+    /// todo once, in the order of the groups.
+    fn write_groups(&self, out: &mut dyn Write, groups: &[Group]) -> io::Result<()> {
+        let mut todos: Vec<&Todo> = Vec::new();
+        for todo in groups.iter().flat_map(|(_, todos)| todos) {
+            if !todos.iter().any(|t| std::ptr::eq(*t, *todo)) {
+                todos.push(todo);
+            }
+        }
+
+        self.write_todos(out, &todos)
+    }
+}
+
+/// `layout` picks the columns of the plain table; the other formats always
+/// contain all fields. `color` styles the table and its text column is
+/// truncated to make it no wider than `max_width`.
+pub fn formatter(
+    format: Format,
+    layout: &Layout,
+    color: bool,
+    max_width: Option<usize>,
+) -> Box<dyn Formatter> {
+    match format {
+        Format::Plain => Box::new(PlainFormatter {
+            layout: layout.clone(),
+            color,
+            max_width,
+        }),
+        Format::Json => Box::new(JsonFormatter),
+        Format::Csv => Box::new(CsvFormatter),
+    }
+}
+
+/// Aligned table with a header row. Closed todos are dimmed and struck
+/// through and overdue ones are red when `color` is set.
+pub struct PlainFormatter {
+    layout: Layout,
+    color: bool,
+    max_width: Option<usize>,
+}
+
+/// The text column is never truncated to less than this.
+const MIN_TEXT_WIDTH: usize = 10;
+
+/// A cell of the plain table together with its color.
+struct Cell {
+    text: String,
+    style: fn(&str) -> ColoredString,
+    /// Whether `Layout::highlight` is highlighted in the cell
+    highlight: bool,
+}
+
+impl Cell {
+    fn new(text: impl Into<String>, style: fn(&str) -> ColoredString) -> Self {
+        Cell {
+            text: text.into(),
+            style,
+            highlight: false,
+        }
+    }
+
+    fn plain(text: impl Into<String>) -> Self {
+        Cell::new(text, |s| s.normal())
+    }
+}
+
+impl Formatter for PlainFormatter {
+    fn write_todos(&self, out: &mut dyn Write, todos: &[&Todo]) -> io::Result<()> {
+        if !self.layout.tree {
+            return self.write_sections(out, &[(None, todos)], None);
+        }
+
+        let nodes = tree::flatten(todos);
+        let todos: Vec<&Todo> = nodes.iter().map(|node| node.todo).collect();
+        self.write_sections(out, &[(None, &todos)], Some(&nodes))
+    }
+
+    fn write_groups(&self, out: &mut dyn Write, groups: &[Group]) -> io::Result<()> {
+        let headings: Vec<String> = groups
+            .iter()
+            .map(|(heading, todos)| format!("{} ({})", heading, todos.len()))
+            .collect();
+        let sections: Vec<(Option<&str>, &[&Todo])> = headings
+            .iter()
+            .zip(groups)
+            .map(|(heading, (_, todos))| (Some(heading.as_str()), todos.as_slice()))
+            .collect();
+
+        self.write_sections(out, &sections, None)
+    }
+}
+
+impl PlainFormatter {
+    /// Writes a table for each section, below its heading if it has one. The
+    /// columns have the same widths in all of them. `nodes` draw the tree
+    /// into the text column.
+    fn write_sections(
+        &self,
+        out: &mut dyn Write,
+        sections: &[(Option<&str>, &[&Todo])],
+        nodes: Option<&[Node]>,
+    ) -> io::Result<()> {
+        let todos: Vec<&Todo> = sections
+            .iter()
+            .flat_map(|(_, todos)| todos.iter().copied())
+            .collect();
+        let todos = todos.as_slice();
+
+        writeln!(out)?;
+        if todos.is_empty() {
+            return writeln!(out);
+        }
+
+        let now = Local::now();
+        let columns = self.columns(todos);
+        let text_index = columns.iter().position(|c| *c == Some(Column::Text));
+        let id_index = columns.iter().position(|c| *c == Some(Column::Id));
+
+        let mut field_names = self.layout.fields.iter();
+        let header: Vec<String> = columns
+            .iter()
+            .map(|column| match column {
+                Some(column) => column.header().to_string(),
+                None => field_names
+                    .next()
+                    .map(|f| f.to_uppercase())
+                    .unwrap_or_default(),
+            })
+            .collect();
+
+        let mut rows: Vec<Vec<Cell>> = todos
+            .iter()
+            .map(|todo| {
+                let mut field_names = self.layout.fields.iter();
+                columns
+                    .iter()
+                    .map(|column| match column {
+                        Some(column) => cell(*column, todo, now, &self.layout),
+                        None => Cell::plain(
+                            field_names
+                                .next()
+                                .map(|f| field_column(todo, f))
+                                .unwrap_or_default(),
+                        ),
+                    })
+                    .collect()
+            })
+            .collect();
+
+        if let (Some(_), Some(text_index)) = (&self.layout.highlight, text_index) {
+            for row in &mut rows {
+                row[text_index].highlight = true;
+            }
+        }
+        if let (Some(nodes), Some(text_index)) = (nodes, text_index) {
+            for (row, node) in rows.iter_mut().zip(nodes) {
+                let text = &mut row[text_index].text;
+                text.insert_str(0, &node.prefix);
+                if let Some((closed, total)) = node.progress {
+                    text.push_str(&format!(" [{}/{}]", closed, total));
+                }
+            }
+        }
+
+        let mut widths: Vec<usize> = header.iter().map(|h| h.width()).collect();
+        for row in &rows {
+            for (width, cell) in widths.iter_mut().zip(row) {
+                *width = (*width).max(cell.text.width());
+            }
+        }
+
+        // Shorten the texts rather than letting the rows wrap
+        let total_width = widths.iter().sum::<usize>() + widths.len().saturating_sub(1);
+        if let (Some(max_width), Some(text_index)) =
+            (self.max_width.filter(|&max| total_width > max), text_index)
+        {
+            let excess = total_width - max_width;
+            let text_width = widths[text_index]
+                .saturating_sub(excess)
+                .max(MIN_TEXT_WIDTH);
+            widths[text_index] = widths[text_index].min(text_width);
+            for row in &mut rows {
+                row[text_index].text = truncate(&row[text_index].text, widths[text_index]);
+            }
+        }
+
+        let header: Vec<Cell> = header
+            .into_iter()
+            .map(|h| Cell::new(h, |s| s.bold()))
+            .collect();
+        let mut rows = todos.iter().zip(&rows);
+        for (heading, section) in sections {
+            if let Some(heading) = heading {
+                writeln!(out, "{}", heading.bold())?;
+            }
+            // Sections can be empty, e.g. the days of `todo week`
+            if !section.is_empty() {
+                self.write_row(out, &header, &widths, id_index, false)?;
+            }
+            for (todo, row) in rows.by_ref().take(section.len()) {
+                self.write_row(out, row, &widths, id_index, todo.status.is_closed())?;
+            }
+            writeln!(out)?;
+        }
+
+        Ok(())
+    }
+
+    /// The columns to show, with `None` standing for the next custom field.
+    fn columns(&self, todos: &[&Todo]) -> Vec<Option<Column>> {
+        let columns: Vec<Column> = match &self.layout.columns {
+            Some(columns) => columns.clone(),
+            None => Column::DEFAULT
+                .iter()
+                .copied()
+                .filter(|column| match column {
+                    Column::Due => todos.iter().any(|t| t.due_at.is_some()),
+                    Column::Priority => todos.iter().any(|t| t.priority != Priority::None),
+                    _ => true,
+                })
+                .collect(),
+        };
+
+        let fields = self.layout.fields.iter().map(|_| None);
+        let mut columns: Vec<Option<Column>> = columns.into_iter().map(Some).collect();
+        match columns.iter().position(|c| *c == Some(Column::Text)) {
+            Some(text_index) => {
+                columns.splice(text_index + 1..text_index + 1, fields);
+            }
+            None => columns.extend(fields),
+        }
+
+        columns
+    }
+
+    /// The id column is aligned right and the last one is not padded to
+    /// avoid trailing whitespace.
+    fn write_row(
+        &self,
+        out: &mut dyn Write,
+        cells: &[Cell],
+        widths: &[usize],
+        id_index: Option<usize>,
+        closed: bool,
+    ) -> io::Result<()> {
+        let Some(last) = cells.len().checked_sub(1) else {
+            return writeln!(out);
+        };
+        let padded: Vec<String> = cells
+            .iter()
+            .zip(widths)
+            .enumerate()
+            .map(|(i, (cell, &width))| {
+                // Padding by display width, as `format!` counts characters
+                let padding = " ".repeat(width.saturating_sub(cell.text.width()));
+                match i {
+                    i if Some(i) == id_index => padding + &cell.text,
+                    i if i == last => cell.text.clone(),
+                    _ => cell.text.clone() + &padding,
+                }
+            })
+            .collect();
+
+        if !self.color {
+            return writeln!(out, "{}", padded.join(" ").trim_end());
+        }
+        if closed {
+            return writeln!(out, "{}", padded.join(" ").dimmed().strikethrough());
+        }
+
+        let styled: Vec<String> = cells
+            .iter()
+            .zip(&padded)
+            .map(|(cell, text)| match &self.layout.highlight {
+                Some(query) if cell.highlight => highlight(text, query, cell.style),
+                _ => (cell.style)(text).to_string(),
+            })
+            .collect();
+        writeln!(out, "{}", styled.join(" "))
+    }
+}
+
+pub struct JsonFormatter;
+
+impl Formatter for JsonFormatter {
+    fn write_todos(&self, out: &mut dyn Write, todos: &[&Todo]) -> io::Result<()> {
+        serde_json::to_writer_pretty(&mut *out, todos)?;
+        writeln!(out)
+    }
+
+    /// An array of `{"group": .., "todos": [..]}` objects.
+    fn write_groups(&self, out: &mut dyn Write, groups: &[Group]) -> io::Result<()> {
+        let groups: Vec<serde_json::Value> = groups
+            .iter()
+            .map(|(heading, todos)| serde_json::json!({ "group": heading, "todos": todos }))
+            .collect();
+        serde_json::to_writer_pretty(&mut *out, &groups)?;
+        writeln!(out)
+    }
+}
+
+pub struct CsvFormatter;
+
+impl Formatter for CsvFormatter {
+    fn write_todos(&self, out: &mut dyn Write, todos: &[&Todo]) -> io::Result<()> {
+        writeln!(
+            out,
+            "id,uuid,created_at,updated_at,text,status,completed_at,due_at,scheduled_at,snoozed_until,deleted_at,priority,tags,notes,fields,pinned,parent,list"
+        )?;
+        for todo in todos {
+            writeln!(
+                out,
+                "{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{},{}",
+                todo.id,
+                todo.uuid,
+                todo.created_at.to_rfc3339(),
+                todo.updated_at.to_rfc3339(),
+                csv::quote(&todo.text),
+                todo.status.as_str(),
+                todo.completed_at
+                    .map(|d| d.to_rfc3339())
+                    .unwrap_or_default(),
+                todo.due_at.map(|d| d.to_rfc3339()).unwrap_or_default(),
+                todo.scheduled_at
+                    .map(|d| d.to_rfc3339())
+                    .unwrap_or_default(),
+                todo.snoozed_until
+                    .map(|d| d.to_rfc3339())
+                    .unwrap_or_default(),
+                todo.deleted_at.map(|d| d.to_rfc3339()).unwrap_or_default(),
+                todo.priority.as_str(),
+                csv::quote(&todo.tags.join(" ")),
+                csv::quote(&todo.notes),
+                csv::quote(&fields_column(todo)),
+                todo.pinned,
+                todo.parent.map(|id| id.to_string()).unwrap_or_default(),
+                todo.list.as_deref().unwrap_or_default()
+            )?;
+        }
+
+        Ok(())
+    }
+}
+
+/// The number of todos with each status and of the overdue ones, like `12
+/// open, 5 done, 3 overdue`. Statuses no todo has are left out.
+pub fn summary(todos: &[&Todo], now: DateTime<Local>) -> String {
+    let mut counts: Vec<String> = Status::value_variants()
+        .iter()
+        .map(|&status| (status, todos.iter().filter(|t| t.status == status).count()))
+        .filter(|&(_, count)| count > 0)
+        .map(|(status, count)| format!("{} {}", count, status.as_str()))
+        .collect();
+    let overdue = todos.iter().filter(|t| t.is_overdue(now)).count();
+    if overdue > 0 {
+        counts.push(format!("{} overdue", overdue));
+    }
+
+    if counts.is_empty() {
+        "no todos".to_string()
+    } else {
+        counts.join(", ")
+    }
+}
+
+/// Prints everything known about a todo, for `todo show`.
+pub fn print_todo_details(todo: &Todo) -> io::Result<()> {
+    let mut out = io::stdout().lock();
+    let format_date = |date: DateTime<Local>| date.format("%d.%m.%Y %H:%M").to_string();
+
+    writeln!(out, "{} {}", todo.id, todo.text.bold())?;
+    if todo.pinned {
+        writeln!(out, "Pinned")?;
+    }
+    if let Some(parent) = todo.parent {
+        writeln!(out, "Parent:    {}", parent)?;
+    }
+    if !todo.tags.is_empty() {
+        let tags: Vec<String> = todo.tags.iter().map(|t| format!("+{}", t)).collect();
+        writeln!(out, "Tags:      {}", tags.join(" "))?;
+    }
+    if todo.priority != Priority::None {
+        writeln!(out, "Priority:  {}", priority_column(todo.priority))?;
+    }
+    writeln!(out, "UUID:      {}", todo.uuid)?;
+    writeln!(out, "Created:   {}", format_date(todo.created_at))?;
+    writeln!(out, "Updated:   {}", format_date(todo.updated_at))?;
+    if let Some(due_at) = todo.due_at {
+        writeln!(out, "Due:       {}", format_date(due_at))?;
+    }
+    if let Some(scheduled_at) = todo.scheduled_at {
+        writeln!(out, "Scheduled: {}", format_date(scheduled_at))?;
+    }
+    writeln!(out, "Status:    {}", todo.status.as_str())?;
+    if let Some(snoozed_until) = todo.snoozed_until.filter(|_| todo.is_snoozed(Local::now())) {
+        writeln!(out, "Snoozed:   until {}", format_date(snoozed_until))?;
+    }
+    if let (Some(completed_at), true) = (todo.completed_at, todo.is_completed()) {
+        writeln!(
+            out,
+            "Completed: {} (after {})",
+            format_date(completed_at),
+            dates::format_duration(completed_at - todo.created_at)
+        )?;
+    }
+    for (key, value) in &todo.fields {
+        writeln!(out, "{:<11}{}", format!("{}:", key), value)?;
+    }
+    if !todo.notes.is_empty() {
+        writeln!(out)?;
+        writeln!(out, "{}", todo.notes.trim_end())?;
+    }
+
+    Ok(())
+}
+
+/// Styles `text` with `style`, except for the occurrences of the lowercase
+/// `query`, which stand out.
+fn highlight(text: &str, query: &str, style: fn(&str) -> ColoredString) -> String {
+    let lowercase = text.to_lowercase();
+    // Lowercasing can change the length of some characters, which would
+    // make the positions of the matches useless
+    if query.is_empty() || lowercase.len() != text.len() {
+        return style(text).to_string();
+    }
+
+    let mut highlighted = String::new();
+    let mut end = 0;
+    for (start, _) in lowercase.match_indices(query) {
+        highlighted.push_str(&style(&text[end..start]).to_string());
+        end = start + query.len();
+        highlighted.push_str(&text[start..end].black().on_yellow().to_string());
+    }
+    highlighted.push_str(&style(&text[end..]).to_string());
+
+    highlighted
+}
+
+/// Shortens `text` to at most `width` columns, ending it with `…` if
+/// anything was cut off.
+fn truncate(text: &str, width: usize) -> String {
+    if text.width() <= width {
+        return text.to_string();
+    }
+
+    let mut truncated = String::new();
+    let mut truncated_width = 0;
+    for c in text.chars() {
+        let char_width = c.width().unwrap_or(0);
+        if truncated_width + char_width + 1 > width {
+            break;
+        }
+        truncated.push(c);
+        truncated_width += char_width;
+    }
+    truncated.push('…');
+
+    truncated
+}
+
+/// The cell of `column` in the row of `todo`.
+fn cell(column: Column, todo: &Todo, now: DateTime<Local>, layout: &Layout) -> Cell {
+    let dates = layout.dates;
+    let overdue: fn(&str) -> ColoredString = if todo.is_overdue(now) {
+        |s| s.red()
+    } else {
+        |s| s.normal()
+    };
+
+    match column {
+        Column::Id => Cell::plain(todo.id.to_string()),
+        Column::Uuid => Cell::plain(todo.uuid.to_string()),
+        Column::Created => Cell::plain(dates.format(todo.created_at, now)),
+        Column::Updated => Cell::plain(dates.format(todo.updated_at, now)),
+        Column::Due => Cell::new(due_column(todo, now, dates), overdue),
+        Column::Priority => priority_cell(todo.priority),
+        Column::Text => Cell::new(text_column(todo), overdue),
+        Column::Status => Cell::plain(status_column(todo, now)),
+        Column::Urgency => {
+            Cell::plain(format!("{:.1}", urgency::score(todo, &layout.urgency, now)))
+        }
+    }
+}
+
+/// The due date, flagged when it has passed.
+fn due_column(todo: &Todo, now: DateTime<Local>, dates: DateStyle) -> String {
+    match todo.due_at {
+        Some(due_at) if todo.is_overdue(now) => format!("{} OVERDUE", dates.format(due_at, now)),
+        Some(due_at) => dates.format(due_at, now),
+        None => String::new(),
+    }
+}
+
+/// The status and, for completed todos, how long they were open if known,
+/// for snoozed and scheduled ones that they are.
+fn status_column(todo: &Todo, now: DateTime<Local>) -> String {
+    match todo.completed_at {
+        Some(completed_at) if todo.is_completed() => format!(
+            "done ({})",
+            dates::format_duration(completed_at - todo.created_at)
+        ),
+        _ if todo.is_snoozed(now) => format!("{} (snoozed)", todo.status.as_str()),
+        _ if todo.is_scheduled_later(now) => format!("{} (scheduled)", todo.status.as_str()),
+        _ => todo.status.as_str().to_string(),
+    }
+}
+
+/// The text followed by the tags, marked with `*` when pinned.
+fn text_column(todo: &Todo) -> String {
+    let mut text = if todo.pinned {
+        format!("* {}", todo.text)
+    } else {
+        todo.text.clone()
+    };
+    for tag in &todo.tags {
+        text.push_str(" +");
+        text.push_str(tag);
+    }
+
+    text
+}
+
+/// A custom field as `key:value`, or nothing when the todo lacks it.
+fn field_column(todo: &Todo, key: &str) -> String {
+    todo.fields
+        .get(key)
+        .map(|value| format!("{}:{}", key, value))
+        .unwrap_or_default()
+}
+
+/// All custom fields as space separated `key:value` pairs.
+fn fields_column(todo: &Todo) -> String {
+    let fields: Vec<String> = todo
+        .fields
+        .keys()
+        .map(|key| field_column(todo, key))
+        .collect();
+
+    fields.join(" ")
+}
+
+fn priority_cell(priority: Priority) -> Cell {
+    match priority {
+        Priority::High => Cell::new("high", |s| s.red().bold()),
+        Priority::Medium => Cell::new("medium", |s| s.yellow()),
+        Priority::Low => Cell::new("low", |s| s.blue()),
+        Priority::None => Cell::plain(""),
+    }
+}
+
+fn priority_column(priority: Priority) -> ColoredString {
+    let cell = priority_cell(priority);
+
+    (cell.style)(&cell.text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn csv_has_a_column_for_every_field() {
+        let todo = Todo::new(1, "water the plants");
+        let mut out = Vec::new();
+        CsvFormatter.write_todos(&mut out, &[&todo]).unwrap();
+        let out = String::from_utf8(out).unwrap();
+        let mut columns: Vec<&str> = out.lines().next().unwrap().split(',').collect();
+        columns.sort_unstable();
+
+        let json = serde_json::to_value(&todo).unwrap();
+        let mut fields: Vec<&str> = json
+            .as_object()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect();
+        fields.sort_unstable();
+        assert_eq!(columns, fields);
+    }
+}
@@ -0,0 +1,111 @@
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use ratatui::crossterm::terminal;
+use rustyline::error::ReadlineError;
+use rustyline::DefaultEditor;
+use std::io::{self, IsTerminal, Write};
+use std::sync::Mutex;
+
+/// Shared by all prompts so the history covers the whole session.
+static EDITOR: Mutex<Option<DefaultEditor>> = Mutex::new(None);
+
+/// Reads a line after showing `prompt`, without the line break. On a terminal
+/// the line can be edited and earlier answers are recalled with the arrow
+/// keys. `None` at the end of input or on Ctrl-C.
+pub fn read_line(prompt: &str) -> io::Result<Option<String>> {
+    if !io::stdin().is_terminal() {
+        print!("{}", prompt);
+        io::stdout().flush()?;
+
+        let mut line = String::new();
+        if io::stdin().read_line(&mut line)? == 0 {
+            return Ok(None);
+        }
+        return Ok(Some(line.trim_end_matches(['\n', '\r']).to_string()));
+    }
+
+    let mut editor = EDITOR.lock().unwrap_or_else(|e| e.into_inner());
+    if editor.is_none() {
+        *editor = Some(DefaultEditor::new().map_err(io::Error::other)?);
+    }
+    let editor = editor.as_mut().expect("the editor was just created");
+
+    match editor.readline(prompt) {
+        Ok(line) => {
+            if !line.trim().is_empty() {
+                editor.add_history_entry(&line).map_err(io::Error::other)?;
+            }
+            Ok(Some(line))
+        }
+        Err(ReadlineError::Eof | ReadlineError::Interrupted) => Ok(None),
+        Err(ReadlineError::Io(e)) => Err(e),
+        Err(e) => Err(io::Error::other(e)),
+    }
+}
+
+/// Reads a single key press after showing `prompt`, without waiting for
+/// Enter. Without a terminal a whole line is read instead. `None` at the end
+/// of input or on Ctrl-C and Ctrl-D.
+pub fn read_key(prompt: &str) -> io::Result<Option<String>> {
+    if !io::stdin().is_terminal() {
+        return Ok(read_line(prompt)?.map(|line| line.trim().to_string()));
+    }
+
+    print!("{}", prompt);
+    io::stdout().flush()?;
+    terminal::enable_raw_mode()?;
+    let key = loop {
+        match event::read() {
+            Ok(Event::Key(key)) if key.kind == KeyEventKind::Press => match key.code {
+                KeyCode::Char('c' | 'd') if key.modifiers.contains(KeyModifiers::CONTROL) => {
+                    break Ok(None)
+                }
+                KeyCode::Char(c) => break Ok(Some(c.to_string())),
+                KeyCode::Esc => break Ok(None),
+                _ => {}
+            },
+            Ok(_) => {}
+            Err(e) => break Err(e),
+        }
+    };
+    terminal::disable_raw_mode()?;
+
+    let key = key?;
+    println!("{}", key.as_deref().unwrap_or_default());
+    Ok(key)
+}
+
+/// Asks for a value until `parse` accepts the answer, printing why it did
+/// not. `q`, an empty answer or the end of input cancel with `None`.
+pub fn ask<T>(question: &str, parse: impl Fn(&str) -> Result<T, String>) -> io::Result<Option<T>> {
+    loop {
+        let Some(answer) = read_line(&format!("{} ", question))? else {
+            return Ok(None);
+        };
+
+        match answer.trim() {
+            "" | "q" => return Ok(None),
+            answer => match parse(answer) {
+                Ok(value) => return Ok(Some(value)),
+                Err(e) => println!("{}", e),
+            },
+        }
+    }
+}
+
+/// Asks a yes/no question that defaults to no. Anything but `y` or `yes`,
+/// including the end of input, counts as no.
+pub fn confirm(question: &str) -> io::Result<bool> {
+    confirm_with_default(question, false)
+}
+
+/// Asks a yes/no question; an empty answer or the end of input count as
+/// `default`, anything else that is not yes as no.
+pub fn confirm_with_default(question: &str, default: bool) -> io::Result<bool> {
+    let choices = if default { "[Y/n]" } else { "[y/N]" };
+    let answer = read_line(&format!("{} {} ", question, choices))?.unwrap_or_default();
+
+    Ok(match answer.trim().to_lowercase().as_str() {
+        "" => default,
+        answer => matches!(answer, "y" | "yes"),
+    })
+}
@@ -0,0 +1,351 @@
+//! Filter expressions like `status:open and (+work or priority:high) and
+//! due.before:friday`.
+//!
+//! Terms are combined with `and`, `or` and `not` and grouped with
+//! parentheses; terms next to each other have to match both. A term is one
+//! of:
+//!
+//! - `+tag` or `tag:name`
+//! - `status:open`, or any other status
+//! - `priority:high`, `priority.above:low` or `priority.below:high`
+//! - `due`, `scheduled`, `created`, `modified` or `completed` with `:day`,
+//!   `.before:when` or `.after:when`, where the day or time is anything
+//!   `dates::parse_day` accepts; `due:none` matches todos without a due date
+//! - `text:word`, or just `word`, to find text in the text or the notes
+//! - `key:value` or `key:` for any other custom field
+
+use crate::dates;
+use crate::filter::{self, FieldFilter};
+use crate::todo::{Priority, Status, Todo};
+use chrono::prelude::*;
+use clap::ValueEnum;
+use std::cmp::Ordering;
+
+#[derive(Debug, Clone)]
+pub enum Expr {
+    And(Box<Expr>, Box<Expr>),
+    Or(Box<Expr>, Box<Expr>),
+    Not(Box<Expr>),
+    Term(Term),
+}
+
+#[derive(Debug, Clone)]
+pub enum Term {
+    Status(Status),
+    Tag(String),
+    /// The priority compared to the given one has to be this ordering
+    Priority(Ordering, Priority),
+    Date(DateField, DateCondition),
+    /// Lowercase text to find in the text or the notes
+    Text(String),
+    Field(FieldFilter),
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum DateField {
+    Due,
+    Scheduled,
+    Created,
+    Modified,
+    Completed,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub enum DateCondition {
+    /// The date is not set
+    None,
+    /// On the same day
+    On(DateTime<Local>),
+    Before(DateTime<Local>),
+    After(DateTime<Local>),
+}
+
+impl Expr {
+    pub fn matches(&self, todo: &Todo) -> bool {
+        match self {
+            Expr::And(left, right) => left.matches(todo) && right.matches(todo),
+            Expr::Or(left, right) => left.matches(todo) || right.matches(todo),
+            Expr::Not(expr) => !expr.matches(todo),
+            Expr::Term(term) => term.matches(todo),
+        }
+    }
+}
+
+impl Term {
+    fn matches(&self, todo: &Todo) -> bool {
+        match self {
+            Term::Status(status) => todo.status == *status,
+            Term::Tag(tag) => todo.has_tag(tag),
+            Term::Priority(ordering, priority) => todo.priority.cmp(priority) == *ordering,
+            Term::Date(field, condition) => {
+                let date = match field {
+                    DateField::Due => todo.due_at,
+                    DateField::Scheduled => todo.scheduled_at,
+                    DateField::Created => Some(todo.created_at),
+                    DateField::Modified => Some(todo.updated_at),
+                    DateField::Completed => todo.completed_at.filter(|_| todo.is_completed()),
+                };
+                match (condition, date) {
+                    (DateCondition::None, date) => date.is_none(),
+                    (_, None) => false,
+                    (DateCondition::On(day), Some(date)) => date.date_naive() == day.date_naive(),
+                    (DateCondition::Before(time), Some(date)) => date < *time,
+                    (DateCondition::After(time), Some(date)) => date > *time,
+                }
+            }
+            Term::Text(text) => {
+                let contains = |s: &str| s.to_lowercase().contains(text.as_str());
+                contains(&todo.text) || contains(&todo.notes)
+            }
+            Term::Field(field) => field.matches(todo),
+        }
+    }
+}
+
+/// Parses an expression; the error says which part is wrong.
+pub fn parse(s: &str) -> Result<Expr, String> {
+    let tokens = tokenize(s)?;
+    if tokens.is_empty() {
+        return Err("the filter is empty".to_string());
+    }
+
+    let mut parser = Parser { tokens, pos: 0 };
+    let expr = parser.or()?;
+    match parser.peek() {
+        None => Ok(expr),
+        Some(token) => Err(format!("unexpected '{}' in the filter", token)),
+    }
+}
+
+/// Splits the expression into words and parentheses. Double quotes keep
+/// spaces in a value, like `text:"two words"`.
+fn tokenize(s: &str) -> Result<Vec<String>, String> {
+    let mut tokens = Vec::new();
+    let mut token = String::new();
+    let mut chars = s.chars();
+    let push = |token: &mut String, tokens: &mut Vec<String>| {
+        if !token.is_empty() {
+            tokens.push(std::mem::take(token));
+        }
+    };
+
+    while let Some(c) = chars.next() {
+        match c {
+            '(' | ')' => {
+                push(&mut token, &mut tokens);
+                tokens.push(c.to_string());
+            }
+            '"' => loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some(c) => token.push(c),
+                    None => return Err("a quote in the filter is not closed".to_string()),
+                }
+            },
+            c if c.is_whitespace() => push(&mut token, &mut tokens),
+            c => token.push(c),
+        }
+    }
+    push(&mut token, &mut tokens);
+
+    Ok(tokens)
+}
+
+struct Parser {
+    tokens: Vec<String>,
+    pos: usize,
+}
+
+impl Parser {
+    fn peek(&self) -> Option<&str> {
+        self.tokens.get(self.pos).map(String::as_str)
+    }
+
+    /// Whether the next token is the keyword `word`, consuming it if so.
+    fn eat(&mut self, word: &str) -> bool {
+        let found = self.peek().is_some_and(|t| t.eq_ignore_ascii_case(word));
+        if found {
+            self.pos += 1;
+        }
+
+        found
+    }
+
+    fn or(&mut self) -> Result<Expr, String> {
+        let mut expr = self.and()?;
+        while self.eat("or") {
+            expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+        }
+
+        Ok(expr)
+    }
+
+    fn and(&mut self) -> Result<Expr, String> {
+        let mut expr = self.not()?;
+        loop {
+            if !self.eat("and") && !self.starts_operand() {
+                return Ok(expr);
+            }
+            expr = Expr::And(Box::new(expr), Box::new(self.not()?));
+        }
+    }
+
+    /// Whether the next token starts a term or group, for the implicit `and`.
+    fn starts_operand(&self) -> bool {
+        match self.peek() {
+            None | Some(")") => false,
+            Some(t) => !t.eq_ignore_ascii_case("or"),
+        }
+    }
+
+    fn not(&mut self) -> Result<Expr, String> {
+        if self.eat("not") {
+            return Ok(Expr::Not(Box::new(self.not()?)));
+        }
+
+        self.primary()
+    }
+
+    fn primary(&mut self) -> Result<Expr, String> {
+        let Some(token) = self.peek().map(str::to_string) else {
+            return Err("the filter ends too early".to_string());
+        };
+        self.pos += 1;
+
+        match token.as_str() {
+            "(" => {
+                let expr = self.or()?;
+                if !self.eat(")") {
+                    return Err("a '(' in the filter is not closed".to_string());
+                }
+                Ok(expr)
+            }
+            ")" => Err("unexpected ')' in the filter".to_string()),
+            t if t.eq_ignore_ascii_case("and") || t.eq_ignore_ascii_case("or") => {
+                Err(format!("'{}' needs a term before it", token))
+            }
+            _ => parse_term(&token).map(Expr::Term),
+        }
+    }
+}
+
+fn parse_term(token: &str) -> Result<Term, String> {
+    if token.starts_with('+') {
+        return filter::parse_tag(token).map(Term::Tag);
+    }
+    let Some((key, value)) = token.split_once(':') else {
+        return Ok(Term::Text(token.to_lowercase()));
+    };
+    let (name, modifier) = match key.split_once('.') {
+        Some((name, modifier)) => (name.to_lowercase(), Some(modifier.to_lowercase())),
+        None => (key.to_lowercase(), None),
+    };
+
+    let date_field = match name.as_str() {
+        "due" => Some(DateField::Due),
+        "scheduled" => Some(DateField::Scheduled),
+        "created" => Some(DateField::Created),
+        "modified" => Some(DateField::Modified),
+        "completed" => Some(DateField::Completed),
+        _ => None,
+    };
+    if let Some(field) = date_field {
+        return parse_date_condition(value, modifier.as_deref())
+            .map(|condition| Term::Date(field, condition));
+    }
+
+    match (name.as_str(), modifier.as_deref()) {
+        ("status", None) => Status::from_str(value, true)
+            .map(Term::Status)
+            .map_err(|_| format!("'{}' is not a status", value)),
+        ("tag", None) => filter::parse_tag(&format!("+{}", value)).map(Term::Tag),
+        ("priority", modifier) => {
+            let ordering = match modifier {
+                None => Ordering::Equal,
+                Some("above") => Ordering::Greater,
+                Some("below") => Ordering::Less,
+                Some(modifier) => return Err(unknown_modifier(&name, modifier)),
+            };
+            Priority::from_str(value, true)
+                .map(|priority| Term::Priority(ordering, priority))
+                .map_err(|_| format!("'{}' is not a priority", value))
+        }
+        ("text", None) => Ok(Term::Text(value.to_lowercase())),
+        (_, None) => token.parse().map(Term::Field),
+        (_, Some(modifier)) => Err(unknown_modifier(&name, modifier)),
+    }
+    .map_err(|e| format!("'{}': {}", token, e))
+}
+
+fn parse_date_condition(value: &str, modifier: Option<&str>) -> Result<DateCondition, String> {
+    match (modifier, value) {
+        (None, "none") => Ok(DateCondition::None),
+        (None, value) => dates::parse_day(value).map(DateCondition::On),
+        (Some("before"), value) => dates::parse_day(value).map(DateCondition::Before),
+        (Some("after"), value) => dates::parse_day(value).map(DateCondition::After),
+        (Some(modifier), _) => Err(format!(
+            "unknown modifier '{}', expected before or after",
+            modifier
+        )),
+    }
+}
+
+fn unknown_modifier(name: &str, modifier: &str) -> String {
+    format!("'{}' has no modifier '{}'", name, modifier)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn todo(text: &str, status: Status, priority: Priority) -> Todo {
+        let mut todo = Todo::with_tags_from_text(1, text);
+        todo.status = status;
+        todo.priority = priority;
+        todo
+    }
+
+    fn matches(filter: &str, todo: &Todo) -> bool {
+        parse(filter).unwrap().matches(todo)
+    }
+
+    #[test]
+    fn and_binds_tighter_than_or() {
+        let work = todo("report +work", Status::Open, Priority::None);
+        let urgent = todo("call", Status::Done, Priority::High);
+
+        let filter = "status:open and +work or priority:high";
+        assert!(matches(filter, &work));
+        assert!(matches(filter, &urgent));
+        assert!(matches("status:open AND +work Or priority:high", &urgent));
+        assert!(!matches(
+            "status:open and (+work or priority:high)",
+            &urgent
+        ));
+    }
+
+    #[test]
+    fn terms_next_to_each_other_all_have_to_match() {
+        let todo = todo("write report +work", Status::Open, Priority::Low);
+
+        assert!(matches("+work REPORT", &todo));
+        assert!(!matches("+work call", &todo));
+        assert!(matches("not priority.above:low +work", &todo));
+        assert!(matches("due:none", &todo));
+    }
+
+    #[test]
+    fn rejects_malformed_filters() {
+        for filter in [
+            "",
+            "(+work",
+            "+work )",
+            "or +work",
+            "AND +work",
+            "status:bogus",
+            "due.near:today",
+        ] {
+            assert!(parse(filter).is_err(), "{:?} should not parse", filter);
+        }
+    }
+}
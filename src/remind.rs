@@ -0,0 +1,138 @@
+//! Desktop notifications for todos coming due, for `todo remind`.
+
+use crate::config::EscalationRule;
+use crate::database::Database;
+use crate::dates;
+use crate::error::TodoError;
+use crate::escalation;
+use crate::output;
+use crate::storage::Backend;
+use crate::todo::Todo;
+use chrono::prelude::*;
+use chrono::Duration;
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time;
+use uuid::Uuid;
+
+/// The custom field with how long before its due date a todo is reminded of,
+/// e.g. `remind:1h`.
+const FIELD: &str = "remind";
+
+/// How often the daemon looks for todos to remind of.
+const CHECK_INTERVAL: time::Duration = time::Duration::from_secs(30);
+
+/// Todos more overdue than this many hours are not reminded of anymore,
+/// e.g. when the daemon was not running at the time.
+const MISSED_AFTER_HOURS: i64 = 24;
+
+/// The due dates reminded of, by the uuid of the todo, kept next to the
+/// database as `<db>.reminders.json` so every due date is only reminded of
+/// once, also across runs.
+struct Reminded {
+    path: PathBuf,
+    due_dates: HashMap<Uuid, DateTime<Local>>,
+}
+
+impl Reminded {
+    fn load(db_path: &Path) -> Result<Self, TodoError> {
+        let mut path = db_path.as_os_str().to_owned();
+        path.push(".reminders.json");
+        let path = PathBuf::from(path);
+
+        let due_dates = match fs::read(&path) {
+            Ok(contents) => serde_json::from_slice(&contents)
+                .map_err(|e| TodoError::parse(e.to_string()).in_file(&path))?,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => HashMap::new(),
+            Err(e) => return Err(TodoError::from(e).in_file(&path)),
+        };
+
+        Ok(Reminded { path, due_dates })
+    }
+
+    fn save(&self) -> Result<(), TodoError> {
+        let write = || fs::write(&self.path, serde_json::to_vec_pretty(&self.due_dates)?);
+        write().map_err(|e| TodoError::from(e).in_file(&self.path))
+    }
+}
+
+/// Sends a notification for every open todo whose due date is at most its
+/// lead time away: the `remind` field of the todo, e.g. `30m`, or else
+/// `lead_time`. Snoozed todos wait until they wake up. With `daemon`, keeps
+/// checking until interrupted, escalating the todos overdue for long by the
+/// `rules` each time; the database is only opened for each check.
+pub fn run(
+    backend: Backend,
+    path: &Path,
+    lenient: bool,
+    lead_time: Duration,
+    rules: &[EscalationRule],
+    daemon: bool,
+) -> Result<(), TodoError> {
+    let mut reminded = Reminded::load(path)?;
+
+    loop {
+        let mut db = Database::open(backend, path, true, true, lenient)
+            .map_err(|e| TodoError::from(e).in_file(path))?;
+        escalation::run(&mut db, rules)?;
+        let now = Local::now();
+        let missed = now - Duration::hours(MISSED_AFTER_HOURS);
+        let due: Vec<&Todo> = db
+            .todos
+            .iter()
+            .filter(|t| !t.status.is_closed() && !t.is_snoozed(now))
+            .filter(|t| t.due_at.is_some_and(|due_at| due_at > missed))
+            .collect();
+
+        let mut changed = false;
+        for todo in &due {
+            let Some(due_at) = todo.due_at else {
+                continue;
+            };
+            let lead_time = match todo.fields.get(FIELD) {
+                Some(value) => dates::parse_age(value).map_err(|e| {
+                    TodoError::parse(format!("the `{}` field of todo {}: {}", FIELD, todo.id, e))
+                })?,
+                None => lead_time,
+            };
+            if due_at - lead_time > now || reminded.due_dates.get(&todo.uuid) == Some(&due_at) {
+                continue;
+            }
+
+            notify(todo, due_at, now)?;
+            reminded.due_dates.insert(todo.uuid, due_at);
+            changed = true;
+        }
+
+        // Todos no longer due need no entry, and ones due again get a new one
+        let before = reminded.due_dates.len();
+        reminded
+            .due_dates
+            .retain(|uuid, _| due.iter().any(|t| t.uuid == *uuid));
+        if changed || reminded.due_dates.len() != before {
+            reminded.save()?;
+        }
+        drop(db);
+
+        if !daemon {
+            return Ok(());
+        }
+        thread::sleep(CHECK_INTERVAL);
+    }
+}
+
+fn notify(todo: &Todo, due_at: DateTime<Local>, now: DateTime<Local>) -> Result<(), TodoError> {
+    let when = format!("Due {}", dates::format_relative(due_at, now));
+    notify_rust::Notification::new()
+        .appname("todo")
+        .summary(&todo.text)
+        .body(&format!("{} (todo {})", when, todo.id))
+        .show()
+        .map_err(|e| io::Error::other(format!("could not show a notification: {}", e)))?;
+    output::status(format!("Reminded of todo {}: {}", todo.id, todo.text));
+
+    Ok(())
+}
@@ -0,0 +1,150 @@
+use crate::filter::Filter;
+use crate::group::{self, Group};
+use crate::output::{self, Format, Layout};
+use crate::todo::Todo;
+use chrono::prelude::*;
+use std::cmp::Ordering;
+use std::env;
+use std::io::{self, IsTerminal, Write};
+use std::process::{Command, Stdio};
+use terminal_size::{Height, Width};
+
+/// Prints listings to stdout the way the terminal behind it can show them.
+pub struct Renderer {
+    color: bool,
+    /// Width of the terminal, if stdout is one
+    width: Option<usize>,
+    /// Height of the terminal when listings longer than it are paged
+    page_height: Option<usize>,
+}
+
+impl Renderer {
+    /// Colors are used when stdout is a terminal, unless `no_color` is set
+    /// or the `NO_COLOR` environment variable is set to anything. Listings
+    /// that do not fit the terminal go through a pager unless `no_pager` is
+    /// set.
+    pub fn detect(no_color: bool, no_pager: bool) -> Self {
+        let no_color_env = env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty());
+        let color = !no_color && !no_color_env && io::stdout().is_terminal();
+
+        // Also covers the output that does not go through the renderer
+        colored::control::set_override(color);
+
+        let size = terminal_size::terminal_size();
+        let width = size.map(|(Width(width), _)| width as usize);
+        let page_height = size
+            .filter(|_| !no_pager)
+            .map(|(_, Height(height))| height as usize);
+
+        Renderer {
+            color,
+            width,
+            page_height,
+        }
+    }
+
+    pub fn print_todos<'a>(
+        &self,
+        todos: impl IntoIterator<Item = &'a Todo>,
+        filter: &Filter,
+        format: Format,
+        layout: &Layout,
+    ) -> io::Result<()> {
+        let mut todos: Vec<&Todo> = todos.into_iter().filter(|t| filter.matches(t)).collect();
+
+        // Pinned todos always come first. Without sort keys the more urgent
+        // ones come next; otherwise the order is kept
+        let now = Local::now();
+        todos.sort_by(|a, b| {
+            let order = if layout.sort.is_empty() {
+                output::compare_urgency(a, b, &layout.urgency, now)
+            } else {
+                layout.sort.iter().fold(Ordering::Equal, |order, key| {
+                    order.then(key.compare(a, b, &layout.urgency, now))
+                })
+            };
+            let order = if layout.reverse {
+                order.reverse()
+            } else {
+                order
+            };
+
+            b.pinned.cmp(&a.pinned).then(order)
+        });
+
+        let formatter = output::formatter(format, layout, self.color, self.width);
+        self.show(|out| {
+            match layout.group_by {
+                Some(group_by) => formatter.write_groups(out, &group::groups(&todos, group_by))?,
+                None => formatter.write_todos(out, &todos)?,
+            }
+            // Only the table is for humans, the other formats are for tools
+            if format == Format::Plain && !output::is_quiet() {
+                writeln!(out, "{}", output::summary(&todos, now))?;
+            }
+
+            Ok(())
+        })
+    }
+
+    /// Prints the groups as sections of a table, in their order.
+    pub fn print_groups(&self, groups: &[Group], layout: &Layout) -> io::Result<()> {
+        let formatter = output::formatter(Format::Plain, layout, self.color, self.width);
+        self.show(|out| formatter.write_groups(out, groups))
+    }
+
+    /// Prints what `write` writes, through the pager when it does not fit the
+    /// terminal.
+    fn show(&self, write: impl Fn(&mut dyn Write) -> io::Result<()>) -> io::Result<()> {
+        let Some(height) = self.page_height else {
+            return write(&mut io::stdout().lock());
+        };
+
+        let mut buffer = Vec::new();
+        write(&mut buffer)?;
+        if buffer.iter().filter(|&&b| b == b'\n').count() < height {
+            return io::stdout().lock().write_all(&buffer);
+        }
+
+        page(&buffer)
+    }
+}
+
+/// Shows `output` in `$PAGER`, or `less` without it, falling back to
+/// printing it when the pager cannot be started.
+fn page(output: &[u8]) -> io::Result<()> {
+    let pager = env::var("PAGER").unwrap_or_else(|_| "less".to_string());
+    // Allow pagers with arguments, e.g. `less -S`
+    let mut words = pager.split_whitespace();
+    let Some(program) = words.next().filter(|&program| program != "cat") else {
+        return io::stdout().lock().write_all(output);
+    };
+
+    let mut command = Command::new(program);
+    command.args(words).stdin(Stdio::piped());
+    // Like git: quit when everything fits after all, keep the colors and
+    // leave the listing on the screen
+    if env::var_os("LESS").is_none() {
+        command.env("LESS", "FRX");
+    }
+
+    let mut child = match command.spawn() {
+        Ok(child) => child,
+        Err(e) => {
+            log::debug!("Could not start the pager {:?}: {}", pager, e);
+            return io::stdout().lock().write_all(output);
+        }
+    };
+
+    let written = child
+        .stdin
+        .take()
+        .map_or(Ok(()), |mut stdin| stdin.write_all(output));
+    child.wait()?;
+
+    // Quitting the pager before the end is not an error
+    match written {
+        Err(e) if e.kind() == io::ErrorKind::BrokenPipe => Ok(()),
+        written => written,
+    }
+}
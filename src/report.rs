@@ -0,0 +1,126 @@
+//! Standalone reports of the list for sharing and printing.
+
+use crate::todo::{Priority, Status, Todo};
+use chrono::prelude::*;
+use clap::ValueEnum;
+use std::io::{self, Write};
+
+const STYLE: &str = "
+body { font-family: system-ui, sans-serif; margin: 2em auto; max-width: 60em; color: #222; }
+h1 { font-size: 1.5em; }
+.summary { display: flex; gap: 1em; padding: 0; list-style: none; }
+.summary li { border: 1px solid #ccc; border-radius: 4px; padding: 0.5em 1em; }
+.summary strong { display: block; font-size: 1.4em; }
+table { border-collapse: collapse; width: 100%; }
+th, td { border-bottom: 1px solid #ddd; padding: 0.4em; text-align: left; vertical-align: top; }
+th { background: #f4f4f4; }
+.tag { background: #e8eef8; border-radius: 3px; padding: 0 0.3em; margin-right: 0.2em; }
+.closed { color: #888; text-decoration: line-through; }
+.overdue { color: #c00; }
+.priority-high { color: #c00; font-weight: bold; }
+.priority-medium { color: #b80; }
+.priority-low { color: #36c; }
+@media print { body { margin: 0; } }
+";
+
+/// Writes a page with the number of todos per status followed by a table of
+/// all todos.
+pub fn write_html(out: &mut dyn Write, todos: &[Todo]) -> io::Result<()> {
+    let now = Local::now();
+    let generated = now.format("%d.%m.%Y %H:%M");
+
+    writeln!(out, "<!DOCTYPE html>")?;
+    writeln!(out, "<html lang=\"en\">")?;
+    writeln!(out, "<head>")?;
+    writeln!(out, "<meta charset=\"utf-8\">")?;
+    writeln!(out, "<title>Todos</title>")?;
+    writeln!(out, "<style>{}</style>", STYLE)?;
+    writeln!(out, "</head>")?;
+    writeln!(out, "<body>")?;
+    writeln!(out, "<h1>Todos</h1>")?;
+    writeln!(out, "<p>Generated on {}</p>", generated)?;
+
+    writeln!(out, "<ul class=\"summary\">")?;
+    write_count(out, "total", todos.len())?;
+    for &status in Status::value_variants() {
+        let count = todos.iter().filter(|t| t.status == status).count();
+        write_count(out, status.as_str(), count)?;
+    }
+    let overdue = todos.iter().filter(|t| t.is_overdue(now)).count();
+    write_count(out, "overdue", overdue)?;
+    writeln!(out, "</ul>")?;
+
+    writeln!(out, "<table>")?;
+    writeln!(
+        out,
+        "<tr><th>ID</th><th>Text</th><th>Priority</th><th>Status</th>\
+        <th>Created</th><th>Due</th><th>Completed</th></tr>"
+    )?;
+    for todo in todos {
+        write_row(out, todo, now)?;
+    }
+    writeln!(out, "</table>")?;
+
+    writeln!(out, "</body>")?;
+    writeln!(out, "</html>")
+}
+
+fn write_count(out: &mut dyn Write, label: &str, count: usize) -> io::Result<()> {
+    writeln!(out, "<li><strong>{}</strong>{}</li>", count, label)
+}
+
+fn write_row(out: &mut dyn Write, todo: &Todo, now: DateTime<Local>) -> io::Result<()> {
+    let format_date = |date: Option<DateTime<Local>>| {
+        date.map(|d| d.format("%d.%m.%Y").to_string())
+            .unwrap_or_default()
+    };
+
+    let class = if todo.status.is_closed() {
+        " class=\"closed\""
+    } else if todo.is_overdue(now) {
+        " class=\"overdue\""
+    } else {
+        ""
+    };
+    let mut text = escape_html(&todo.text);
+    for tag in &todo.tags {
+        text.push_str(&format!(
+            " <span class=\"tag\">+{}</span>",
+            escape_html(tag)
+        ));
+    }
+    let priority = match todo.priority {
+        Priority::None => String::new(),
+        priority => format!("<span class=\"priority-{0}\">{0}</span>", priority.as_str()),
+    };
+    let completed_at = todo.completed_at.filter(|_| todo.is_completed());
+
+    writeln!(
+        out,
+        "<tr{}><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td><td>{}</td></tr>",
+        class,
+        todo.id,
+        text,
+        priority,
+        todo.status.as_str(),
+        format_date(Some(todo.created_at)),
+        format_date(todo.due_at),
+        format_date(completed_at)
+    )
+}
+
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            c => escaped.push(c),
+        }
+    }
+
+    escaped
+}
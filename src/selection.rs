@@ -0,0 +1,196 @@
+use crate::database::Database;
+use crate::error::TodoError;
+use crate::output;
+use crate::prompt;
+use crate::todo::Todo;
+use std::str::FromStr;
+
+/// An id, an inclusive range of ids or a text as given on the command line,
+/// e.g. `3`, `7-9` or `milk`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum IdSelector {
+    Single(u32),
+    Range(u32, u32),
+    /// Lowercase text fuzzily matched against the texts of the todos
+    Text(String),
+}
+
+impl FromStr for IdSelector {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let parse = |id: &str| {
+            id.trim()
+                .parse::<u32>()
+                .map_err(|_| format!("'{}' is not a valid id", id))
+        };
+
+        // Anything that does not look like ids is a text
+        if !s
+            .chars()
+            .all(|c| c.is_ascii_digit() || c == '-' || c == ' ')
+        {
+            return Ok(IdSelector::Text(s.to_lowercase()));
+        }
+
+        match s.split_once('-') {
+            Some((start, end)) => {
+                let (start, end) = (parse(start)?, parse(end)?);
+                if start > end {
+                    return Err(format!("range {} is reversed", s));
+                }
+                Ok(IdSelector::Range(start, end))
+            }
+            None => Ok(IdSelector::Single(parse(s)?)),
+        }
+    }
+}
+
+/// Resolves the selectors to the ids of existing todos, without duplicates
+/// and in the order given. A single id or text that matches nothing is an
+/// error, while a range only needs to match at least one todo. When a text
+/// matches several todos, the user picks one. Ranges and texts only match
+/// todos on the active list, like the listings show them.
+pub fn resolve_ids(db: &Database, selectors: &[IdSelector]) -> Result<Vec<u32>, TodoError> {
+    let mut ids = Vec::new();
+
+    for selector in selectors {
+        let matched: Vec<u32> = match selector {
+            &IdSelector::Single(id) => vec![db.find(id).ok_or(TodoError::NotFound(id))?.id],
+            IdSelector::Text(query) => resolve_text(db, query)?.into_iter().collect(),
+            &IdSelector::Range(start, end) => {
+                let matched: Vec<u32> = db
+                    .active_todos()
+                    .map(|t| t.id)
+                    .filter(|id| (start..=end).contains(id))
+                    .collect();
+                if matched.is_empty() {
+                    return Err(TodoError::NotFound(start));
+                }
+                matched
+            }
+        };
+
+        for id in matched {
+            if !ids.contains(&id) {
+                ids.push(id);
+            }
+        }
+    }
+
+    Ok(ids)
+}
+
+/// The todo whose text matches `query` best, taken without asking when it is
+/// the only candidate; otherwise the user picks one, and `None` means they
+/// cancelled.
+fn resolve_text(db: &Database, query: &str) -> Result<Option<u32>, TodoError> {
+    let candidates = candidates(db.active_todos(), query);
+
+    match candidates.as_slice() {
+        [] => Err(TodoError::NoMatch(query.to_string())),
+        [todo] => Ok(Some(todo.id)),
+        candidates => {
+            println!("'{}' matches several todos:", query);
+            for todo in candidates {
+                println!("{:>4}: {} ({})", todo.id, todo.text, todo.status.as_str());
+            }
+            let id = prompt::ask("Which one? Id (empty to skip):", |input| {
+                input
+                    .parse::<u32>()
+                    .ok()
+                    .filter(|id| candidates.iter().any(|todo| todo.id == *id))
+                    .ok_or_else(|| format!("'{}' is not one of the ids above", input))
+            })?;
+            if id.is_none() {
+                output::status(format!("Skipped '{}'", query));
+            }
+
+            Ok(id)
+        }
+    }
+}
+
+/// The todos matching the lowercase `query`, the best first. A todo with
+/// exactly the query as its text is the only candidate; otherwise those
+/// containing the query are preferred over those only containing its
+/// characters in order.
+fn candidates<'a>(todos: impl Iterator<Item = &'a Todo>, query: &str) -> Vec<&'a Todo> {
+    let mut candidates: Vec<(&Todo, u32)> = todos
+        .filter_map(|todo| fuzzy_score(query, &todo.text).map(|score| (todo, score)))
+        .collect();
+    let exact: Vec<&Todo> = candidates
+        .iter()
+        .map(|&(todo, _)| todo)
+        .filter(|todo| todo.text.to_lowercase() == query)
+        .collect();
+    if let [todo] = exact.as_slice() {
+        return vec![todo];
+    }
+    if candidates
+        .iter()
+        .any(|(todo, _)| todo.text.to_lowercase().contains(query))
+    {
+        candidates.retain(|(todo, _)| todo.text.to_lowercase().contains(query));
+    }
+    candidates.sort_by_key(|&(_, score)| std::cmp::Reverse(score));
+
+    candidates.into_iter().map(|(todo, _)| todo).collect()
+}
+
+/// How well the lowercase `query` matches `text`, or `None` if the characters
+/// of the query do not all appear in it in order. Characters following each
+/// other or starting a word count more.
+pub fn fuzzy_score(query: &str, text: &str) -> Option<u32> {
+    let mut score = 0;
+    let mut previous_matched = false;
+    let mut previous: Option<char> = None;
+    let mut query = query.chars().peekable();
+
+    for c in text.chars().flat_map(char::to_lowercase) {
+        let Some(&wanted) = query.peek() else {
+            break;
+        };
+        if c == wanted {
+            query.next();
+            score += 1;
+            if previous_matched {
+                score += 4;
+            }
+            if previous.is_none_or(|p| !p.is_alphanumeric()) {
+                score += 2;
+            }
+            previous_matched = true;
+        } else {
+            previous_matched = false;
+        }
+        previous = Some(c);
+    }
+
+    query.peek().is_none().then_some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(todos: &[Todo], query: &str) -> Vec<String> {
+        candidates(todos.iter(), query)
+            .iter()
+            .map(|todo| todo.text.clone())
+            .collect()
+    }
+
+    #[test]
+    fn an_exact_match_is_the_only_candidate() {
+        let todos = [
+            Todo::new(1, "buy milk and eggs"),
+            Todo::new(2, "Buy milk"),
+            Todo::new(3, "bake"),
+        ];
+
+        assert_eq!(texts(&todos, "buy milk"), ["Buy milk"]);
+        assert_eq!(texts(&todos, "milk"), ["buy milk and eggs", "Buy milk"]);
+        assert_eq!(texts(&todos, "bk").len(), 3);
+    }
+}
@@ -0,0 +1,111 @@
+use crate::todo::{Metadata, Todo};
+use clap::ValueEnum;
+use std::io;
+use std::path::{Path, PathBuf};
+
+mod atomic;
+mod flat_file;
+mod json;
+mod migrations;
+mod sqlite;
+mod todo_txt;
+
+pub use flat_file::FlatFileStorage;
+pub use json::JsonStorage;
+pub use sqlite::SqliteStorage;
+pub use todo_txt::TodoTxtStorage;
+
+pub const FLAT_FILE_DB: &str = "todos_db.txt";
+pub const JSON_DB: &str = "todos.json";
+pub const SQLITE_DB: &str = "todos.db";
+pub const TODO_TXT_DB: &str = "todo.txt";
+
+pub trait Storage {
+    fn load_metadata(&self) -> io::Result<Metadata>;
+    fn load_todos(&self) -> io::Result<Vec<Todo>>;
+    fn save_todos(&mut self, metadata: &Metadata, todos: &[Todo]) -> io::Result<()>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+pub enum Backend {
+    /// SQLite database in `todos.db`
+    Sqlite,
+    /// Line based text file in `todos_db.txt`
+    File,
+    /// JSON document in `todos.json`
+    Json,
+    /// todo.txt file, shared with other todo.txt apps, in `todo.txt`
+    TodoTxt,
+}
+
+impl Backend {
+    pub fn file_name(self) -> &'static str {
+        match self {
+            Backend::Sqlite => SQLITE_DB,
+            Backend::File => FLAT_FILE_DB,
+            Backend::Json => JSON_DB,
+            Backend::TodoTxt => TODO_TXT_DB,
+        }
+    }
+}
+
+/// Finds the database to use when no `--db` is given. Like git, the current
+/// directory and its parents are searched for a local database first; the
+/// global one is used if there is none or `global` is set.
+pub fn resolve_path(backend: Backend, global: bool) -> io::Result<PathBuf> {
+    if !global {
+        let cwd = std::env::current_dir()?;
+        let local = cwd
+            .ancestors()
+            .map(|dir| dir.join(backend.file_name()))
+            .find(|path| path.is_file());
+
+        if let Some(path) = local {
+            return Ok(path);
+        }
+    }
+
+    Ok(default_path(backend))
+}
+
+/// Location of the global database:
+/// `$XDG_DATA_HOME/rust-todo/<file name>`, falling back to the current
+/// directory on systems without a data directory.
+pub fn default_path(backend: Backend) -> PathBuf {
+    match dirs::data_dir() {
+        Some(dir) => dir.join("rust-todo").join(backend.file_name()),
+        None => PathBuf::from(backend.file_name()),
+    }
+}
+
+/// Opens the database at `path` with the given backend. When the database
+/// does not exist yet, a `todos_db.txt` left in the current directory by
+/// older versions is imported into it. `lenient` makes the text backend skip
+/// malformed lines instead of failing.
+pub fn open(backend: Backend, path: &Path, lenient: bool) -> io::Result<Box<dyn Storage>> {
+    let legacy_path = Path::new(FLAT_FILE_DB);
+    let needs_migration = !path.exists() && legacy_path.exists() && path != legacy_path;
+
+    log::info!("Opening {:?} database at {}", backend, path.display());
+    let mut storage: Box<dyn Storage> = match backend {
+        Backend::Sqlite => Box::new(SqliteStorage::open(path)?),
+        Backend::File if lenient => Box::new(FlatFileStorage::lenient(path)),
+        Backend::File => Box::new(FlatFileStorage::new(path)),
+        Backend::Json => Box::new(JsonStorage::new(path)),
+        Backend::TodoTxt => Box::new(TodoTxtStorage::new(path)),
+    };
+
+    if needs_migration {
+        log::info!("Importing {} into the new database", legacy_path.display());
+        migrate(&FlatFileStorage::new(legacy_path), storage.as_mut())?;
+    }
+
+    Ok(storage)
+}
+
+pub fn migrate(from: &dyn Storage, to: &mut dyn Storage) -> io::Result<()> {
+    let metadata = from.load_metadata()?;
+    let todos = from.load_todos()?;
+
+    to.save_todos(&metadata, &todos)
+}
@@ -0,0 +1,61 @@
+use std::ffi::OsString;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Replaces the file at `path` with `contents` without ever leaving a
+/// truncated file behind. The data is written to `<path>.tmp` and synced
+/// first, the current file is kept as `<path>.bak`, and only then is the
+/// temporary file renamed into place.
+pub fn write_atomically(path: &Path, contents: &[u8]) -> io::Result<()> {
+    let tmp_path = with_suffix(path, ".tmp");
+    log::debug!("Writing {} via {}", path.display(), tmp_path.display());
+
+    let mut f = File::create(&tmp_path)?;
+    f.write_all(contents)?;
+    f.sync_all()?;
+
+    if path.exists() {
+        fs::rename(path, backup_path(path))?;
+    }
+    fs::rename(&tmp_path, path)?;
+
+    // Persist the renames themselves
+    let dir = path
+        .parent()
+        .filter(|d| !d.as_os_str().is_empty())
+        .unwrap_or(Path::new("."));
+    File::open(dir)?.sync_all()?;
+
+    Ok(())
+}
+
+/// Loads the file at `path`, falling back to the backup written by
+/// `write_atomically` when the file is missing or unreadable.
+pub fn load_with_fallback<T>(path: &Path, load: impl Fn(&Path) -> io::Result<T>) -> io::Result<T> {
+    let backup = backup_path(path);
+
+    match load(path) {
+        Ok(value) => Ok(value),
+        Err(e) if backup.exists() => {
+            log::warn!(
+                "Could not load {} ({}), falling back to {}",
+                path.display(),
+                e,
+                backup.display()
+            );
+            load(&backup)
+        }
+        Err(e) => Err(e),
+    }
+}
+
+pub fn backup_path(path: &Path) -> PathBuf {
+    with_suffix(path, ".bak")
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut name = OsString::from(path.as_os_str());
+    name.push(suffix);
+    PathBuf::from(name)
+}
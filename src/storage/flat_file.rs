@@ -0,0 +1,201 @@
+use super::{atomic, migrations, Storage};
+use crate::error::TodoError;
+use crate::todo::{Metadata, Todo};
+use serde_json::{json, Value};
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// Line based text file: the metadata on the first line, followed by one todo
+/// per line. Every line is a JSON object so commas and newlines in the todo
+/// text survive a round-trip. Lines in the old comma separated format are
+/// still understood when loading.
+///
+/// In lenient mode malformed lines are moved to `<path>.rejected` instead of
+/// failing the load.
+pub struct FlatFileStorage {
+    path: PathBuf,
+    lenient: bool,
+}
+
+/// A loaded file together with the lines a lenient load skipped.
+type Loaded = (Metadata, Vec<Todo>, Vec<String>);
+
+impl FlatFileStorage {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        FlatFileStorage {
+            path: path.into(),
+            lenient: false,
+        }
+    }
+
+    pub fn lenient(path: impl Into<PathBuf>) -> Self {
+        FlatFileStorage {
+            lenient: true,
+            ..FlatFileStorage::new(path)
+        }
+    }
+
+    fn load_document(&self) -> io::Result<Loaded> {
+        if !self.path.exists() && !atomic::backup_path(&self.path).exists() {
+            let (metadata, todos) = migrations::empty_database();
+            return Ok((metadata, todos, Vec::new()));
+        }
+
+        if self.lenient {
+            atomic::load_with_fallback(&self.path, load_lenient)
+        } else {
+            atomic::load_with_fallback(&self.path, load)
+                .map(|(metadata, todos)| (metadata, todos, Vec::new()))
+        }
+    }
+
+    /// Appends the rejected lines to `<path>.rejected` and rewrites the file
+    /// without them, so they are only reported once.
+    fn quarantine(
+        &self,
+        metadata: &Metadata,
+        todos: &[Todo],
+        rejected: &[String],
+    ) -> io::Result<()> {
+        let mut rejected_path = self.path.clone().into_os_string();
+        rejected_path.push(".rejected");
+        let rejected_path = PathBuf::from(rejected_path);
+
+        let mut f = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(&rejected_path)?;
+        for line in rejected {
+            writeln!(f, "{}", line)?;
+        }
+        f.sync_all()?;
+        log::warn!(
+            "Skipped {} malformed line(s) of {}, moved them to {}",
+            rejected.len(),
+            self.path.display(),
+            rejected_path.display()
+        );
+
+        save(&self.path, metadata, todos)
+    }
+}
+
+impl Storage for FlatFileStorage {
+    fn load_metadata(&self) -> io::Result<Metadata> {
+        Ok(self.load_document()?.0)
+    }
+
+    fn load_todos(&self) -> io::Result<Vec<Todo>> {
+        let (metadata, todos, rejected) = self.load_document()?;
+        if !rejected.is_empty() {
+            self.quarantine(&metadata, &todos, &rejected)?;
+        }
+
+        Ok(todos)
+    }
+
+    fn save_todos(&mut self, metadata: &Metadata, todos: &[Todo]) -> io::Result<()> {
+        save(&self.path, metadata, todos)
+    }
+}
+
+fn save(path: &Path, metadata: &Metadata, todos: &[Todo]) -> io::Result<()> {
+    let mut lines = vec![serde_json::to_string(metadata)?];
+    for todo in todos {
+        lines.push(serde_json::to_string(todo)?);
+    }
+
+    atomic::write_atomically(path, lines.join("\n").as_bytes())
+}
+
+fn load(path: &Path) -> io::Result<(Metadata, Vec<Todo>)> {
+    let contents = fs::read_to_string(path)?;
+    if contents.trim().is_empty() {
+        return Ok(migrations::empty_database());
+    }
+    let mut lines = contents.lines();
+
+    // The first line holds the metadata
+    let metadata =
+        parse_metadata(lines.next().unwrap_or_default().trim()).map_err(|e| line_error(1, e))?;
+    let todos = lines
+        .enumerate()
+        .map(|(index, line)| parse_todo(line).map_err(|e| line_error(index + 2, e)))
+        .collect::<io::Result<Vec<Value>>>()?;
+
+    migrations::from_document(json!({ "metadata": metadata, "todos": todos }))
+}
+
+/// Like `load`, but skips the lines that cannot be parsed. Without a valid
+/// metadata line the ids of the loaded todos decide the next id.
+fn load_lenient(path: &Path) -> io::Result<Loaded> {
+    let contents = fs::read_to_string(path)?;
+    let mut lines = contents.lines().filter(|line| !line.trim().is_empty());
+    let mut rejected = Vec::new();
+
+    let metadata = match lines.next().map(|line| (line, parse_metadata(line.trim()))) {
+        Some((_, Ok(metadata))) => metadata,
+        Some((line, Err(_))) => {
+            rejected.push(line.to_string());
+            json!({ "seq_id": 0 })
+        }
+        None => json!({ "seq_id": 0 }),
+    };
+
+    let mut todos = Vec::new();
+    for line in lines {
+        // Every todo is migrated on its own so that one bad line cannot spoil
+        // the others
+        let todo = parse_todo(line).and_then(|todo| {
+            migrations::from_document(json!({ "metadata": metadata, "todos": [todo] }))
+        });
+        match todo {
+            Ok((_, mut todo)) => todos.append(&mut todo),
+            Err(e) => {
+                log::debug!("Rejecting line {:?}: {}", line, e);
+                rejected.push(line.to_string());
+            }
+        }
+    }
+
+    let (mut metadata, _) =
+        migrations::from_document(json!({ "metadata": metadata, "todos": [] }))?;
+    metadata.seq_id = todos.iter().map(|t| t.id).fold(metadata.seq_id, u32::max);
+
+    Ok((metadata, todos, rejected))
+}
+
+/// Marks the error as being about the given 1-based line of the file.
+fn line_error(line: usize, e: io::Error) -> io::Error {
+    let error = TodoError::Parse {
+        line: Some(line),
+        reason: e.to_string(),
+    };
+
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+fn parse_metadata(line: &str) -> io::Result<Value> {
+    if line.starts_with('{') {
+        return Ok(serde_json::from_str(line)?);
+    }
+
+    let metadata = line
+        .parse::<Metadata>()
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid metadata line"))?;
+
+    Ok(serde_json::to_value(metadata)?)
+}
+
+fn parse_todo(line: &str) -> io::Result<Value> {
+    if line.starts_with('{') {
+        return Ok(serde_json::from_str(line)?);
+    }
+
+    let todo = line
+        .parse::<Todo>()
+        .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "invalid todo line"))?;
+
+    Ok(serde_json::to_value(todo)?)
+}
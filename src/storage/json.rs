@@ -0,0 +1,56 @@
+use super::{atomic, migrations, Storage};
+use crate::todo::{Metadata, Todo};
+use serde::Serialize;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+pub struct JsonStorage {
+    path: PathBuf,
+}
+
+#[derive(Serialize)]
+struct DocumentRef<'a> {
+    metadata: &'a Metadata,
+    todos: &'a [Todo],
+}
+
+impl JsonStorage {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        JsonStorage { path: path.into() }
+    }
+
+    fn load_document(&self) -> io::Result<(Metadata, Vec<Todo>)> {
+        if !self.path.exists() && !atomic::backup_path(&self.path).exists() {
+            return Ok(migrations::empty_database());
+        }
+
+        atomic::load_with_fallback(&self.path, load)
+    }
+}
+
+impl Storage for JsonStorage {
+    fn load_metadata(&self) -> io::Result<Metadata> {
+        Ok(self.load_document()?.0)
+    }
+
+    fn load_todos(&self) -> io::Result<Vec<Todo>> {
+        Ok(self.load_document()?.1)
+    }
+
+    fn save_todos(&mut self, metadata: &Metadata, todos: &[Todo]) -> io::Result<()> {
+        let mut buf = serde_json::to_vec_pretty(&DocumentRef { metadata, todos })?;
+        buf.push(b'\n');
+
+        atomic::write_atomically(&self.path, &buf)
+    }
+}
+
+fn load(path: &Path) -> io::Result<(Metadata, Vec<Todo>)> {
+    let contents = fs::read(path)?;
+    if contents.trim_ascii().is_empty() {
+        return Ok(migrations::empty_database());
+    }
+
+    migrations::from_document(serde_json::from_slice(&contents)?)
+}
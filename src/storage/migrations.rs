@@ -0,0 +1,310 @@
+//! Upgrades databases written by older versions of the app.
+//!
+//! Every change to the data model bumps `SCHEMA_VERSION` and adds one entry
+//! to both `DOCUMENT_MIGRATIONS` (for the text and JSON backends) and
+//! `SQLITE_MIGRATIONS`, where the entry at index `i` upgrades version `i` to
+//! version `i + 1`.
+
+use crate::todo::{Metadata, Todo};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::io;
+use uuid::Uuid;
+
+pub const SCHEMA_VERSION: u32 = 16;
+
+/// Migrations on a document of the shape `{"metadata": {..}, "todos": [..]}`.
+const DOCUMENT_MIGRATIONS: &[fn(&mut Value)] = &[
+    // 0 -> 1: files before versioning only lack the version number
+    |_| {},
+    // 1 -> 2: todos get an optional due date
+    |document| add_todo_field(document, "due_at", Value::Null),
+    // 2 -> 3: todos get a priority
+    |document| add_todo_field(document, "priority", "none".into()),
+    // 3 -> 4: todos get tags
+    |document| add_todo_field(document, "tags", Value::Array(Vec::new())),
+    // 4 -> 5: completion times are tracked; unknown for older todos
+    |document| add_todo_field(document, "completed_at", Value::Null),
+    // 5 -> 6: todos get notes
+    |document| add_todo_field(document, "notes", "".into()),
+    // 6 -> 7: the completed flag becomes a status
+    |document| {
+        for_each_todo(document, |todo| {
+            let is_completed = todo.remove("is_completed").and_then(|v| v.as_bool());
+            let status = if is_completed == Some(true) {
+                "done"
+            } else {
+                "open"
+            };
+            todo.entry("status").or_insert_with(|| status.into());
+        })
+    },
+    // 7 -> 8: modification times are tracked; the last known change is the
+    // completion or else the creation
+    |document| {
+        for_each_todo(document, |todo| {
+            let updated_at = match (todo.get("completed_at"), todo.get("created_at")) {
+                (Some(completed_at @ Value::String(_)), _) => completed_at.clone(),
+                (_, Some(created_at)) => created_at.clone(),
+                (_, None) => Value::Null,
+            };
+            todo.entry("updated_at").or_insert(updated_at);
+        })
+    },
+    // 8 -> 9: todos get a UUID
+    |document| {
+        for_each_todo(document, |todo| {
+            todo.entry("uuid")
+                .or_insert_with(|| Uuid::new_v4().to_string().into());
+        })
+    },
+    // 9 -> 10: todos get custom fields
+    |document| add_todo_field(document, "fields", Value::Object(Map::new())),
+    // 10 -> 11: todos can be pinned
+    |document| add_todo_field(document, "pinned", false.into()),
+    // 11 -> 12: todos can be subtasks of others
+    |document| add_todo_field(document, "parent", Value::Null),
+    // 12 -> 13: deleted todos are kept in the trash
+    |document| add_todo_field(document, "deleted_at", Value::Null),
+    // 13 -> 14: todos can be snoozed
+    |document| add_todo_field(document, "snoozed_until", Value::Null),
+    // 14 -> 15: todos can be scheduled to start later
+    |document| add_todo_field(document, "scheduled_at", Value::Null),
+    // 15 -> 16: todos can be on named lists
+    |document| add_todo_field(document, "list", Value::Null),
+];
+
+const SQLITE_MIGRATIONS: &[&str] = &[
+    // 0 -> 1: the initial schema, which databases before versioning
+    // already have
+    "CREATE TABLE IF NOT EXISTS metadata (
+        id INTEGER PRIMARY KEY CHECK (id = 1),
+        seq_id INTEGER NOT NULL
+    );
+    INSERT OR IGNORE INTO metadata (id, seq_id) VALUES (1, 0);
+    CREATE TABLE IF NOT EXISTS todos (
+        id INTEGER PRIMARY KEY,
+        created_at TEXT NOT NULL,
+        text TEXT NOT NULL,
+        is_completed INTEGER NOT NULL
+    );",
+    // 1 -> 2
+    "ALTER TABLE todos ADD COLUMN due_at TEXT;",
+    // 2 -> 3
+    "ALTER TABLE todos ADD COLUMN priority TEXT NOT NULL DEFAULT 'none';",
+    // 3 -> 4
+    "CREATE TABLE tags (
+        todo_id INTEGER NOT NULL,
+        tag TEXT NOT NULL,
+        PRIMARY KEY (todo_id, tag)
+    );",
+    // 4 -> 5
+    "ALTER TABLE todos ADD COLUMN completed_at TEXT;",
+    // 5 -> 6
+    "ALTER TABLE todos ADD COLUMN notes TEXT NOT NULL DEFAULT '';",
+    // 6 -> 7
+    "ALTER TABLE todos ADD COLUMN status TEXT NOT NULL DEFAULT 'open';
+    UPDATE todos SET status = 'done' WHERE is_completed;
+    ALTER TABLE todos DROP COLUMN is_completed;",
+    // 7 -> 8
+    "ALTER TABLE todos ADD COLUMN updated_at TEXT NOT NULL DEFAULT '';
+    UPDATE todos SET updated_at = COALESCE(completed_at, created_at);",
+    // 8 -> 9: random version 4 UUIDs, formatted like `Uuid` does
+    "ALTER TABLE todos ADD COLUMN uuid TEXT NOT NULL DEFAULT '';
+    UPDATE todos SET uuid = lower(
+        hex(randomblob(4)) || '-' || hex(randomblob(2)) || '-4' ||
+        substr(hex(randomblob(2)), 2) || '-' ||
+        substr('89ab', 1 + abs(random()) % 4, 1) || substr(hex(randomblob(2)), 2) || '-' ||
+        hex(randomblob(6))
+    );",
+    // 9 -> 10
+    "CREATE TABLE fields (
+        todo_id INTEGER NOT NULL,
+        key TEXT NOT NULL,
+        value TEXT NOT NULL,
+        PRIMARY KEY (todo_id, key)
+    );",
+    // 10 -> 11
+    "ALTER TABLE todos ADD COLUMN pinned INTEGER NOT NULL DEFAULT 0;",
+    // 11 -> 12
+    "ALTER TABLE todos ADD COLUMN parent INTEGER;",
+    // 12 -> 13
+    "ALTER TABLE todos ADD COLUMN deleted_at TEXT;",
+    // 13 -> 14
+    "ALTER TABLE todos ADD COLUMN snoozed_until TEXT;",
+    // 14 -> 15
+    "ALTER TABLE todos ADD COLUMN scheduled_at TEXT;",
+    // 15 -> 16
+    "ALTER TABLE todos ADD COLUMN list TEXT;",
+];
+
+fn for_each_todo(document: &mut Value, mut f: impl FnMut(&mut Map<String, Value>)) {
+    if let Some(todos) = document["todos"].as_array_mut() {
+        todos
+            .iter_mut()
+            .filter_map(Value::as_object_mut)
+            .for_each(&mut f);
+    }
+}
+
+/// Sets `field` on every todo that does not have it yet.
+fn add_todo_field(document: &mut Value, field: &str, value: Value) {
+    for_each_todo(document, |todo| {
+        todo.entry(field).or_insert_with(|| value.clone());
+    });
+}
+
+/// What a database that does not exist yet, or is an empty file, holds.
+pub fn empty_database() -> (Metadata, Vec<Todo>) {
+    let metadata = Metadata {
+        seq_id: 0,
+        version: SCHEMA_VERSION,
+    };
+
+    (metadata, Vec::new())
+}
+
+/// Brings a document up to `SCHEMA_VERSION`. Files without a version number
+/// are treated as version 0.
+pub fn migrate_document(document: &mut Value) -> io::Result<()> {
+    let version = document["metadata"]["version"].as_u64().unwrap_or(0) as u32;
+    check_version(version)?;
+
+    if version < SCHEMA_VERSION {
+        log::info!(
+            "Migrating database from version {} to {}",
+            version,
+            SCHEMA_VERSION
+        );
+    }
+    for migration in &DOCUMENT_MIGRATIONS[version as usize..] {
+        migration(document);
+    }
+    document["metadata"]["version"] = SCHEMA_VERSION.into();
+
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct Document {
+    metadata: Metadata,
+    todos: Vec<Todo>,
+}
+
+/// Migrates a document loaded from disk and turns it into the data model.
+pub fn from_document(mut document: Value) -> io::Result<(Metadata, Vec<Todo>)> {
+    migrate_document(&mut document)?;
+    let document: Document = serde_json::from_value(document)?;
+
+    Ok((document.metadata, document.todos))
+}
+
+/// The SQL statements that bring a database from `version` up to
+/// `SCHEMA_VERSION`, each paired with the version it results in.
+pub fn sqlite_migrations(version: u32) -> io::Result<impl Iterator<Item = (u32, &'static str)>> {
+    check_version(version)?;
+
+    Ok(SQLITE_MIGRATIONS
+        .iter()
+        .enumerate()
+        .skip(version as usize)
+        .map(|(i, sql)| (i as u32 + 1, *sql)))
+}
+
+fn check_version(version: u32) -> io::Result<()> {
+    if version > SCHEMA_VERSION {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!(
+                "database has version {}, but this app only supports up to version {}",
+                version, SCHEMA_VERSION
+            ),
+        ));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::storage::{SqliteStorage, Storage};
+    use crate::todo::{Priority, Status};
+    use chrono::prelude::*;
+    use serde_json::json;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    const CREATED_AT: &str = "2023-01-01T10:00:00+01:00";
+
+    #[test]
+    fn upgrades_a_document_from_version_0() {
+        let document = json!({
+            "metadata": { "seq_id": 2 },
+            "todos": [
+                { "id": 1, "created_at": CREATED_AT, "text": "pay rent", "is_completed": true },
+                { "id": 2, "created_at": CREATED_AT, "text": "call mom", "is_completed": false },
+            ],
+        });
+
+        let (metadata, todos) = from_document(document).unwrap();
+        assert_eq!(metadata.version, SCHEMA_VERSION);
+        assert_eq!(metadata.seq_id, 2);
+        assert_eq!(todos.len(), 2);
+        assert_eq!((todos[0].id, todos[0].text.as_str()), (1, "pay rent"));
+        assert_eq!(todos[0].status, Status::Done);
+        assert_eq!(todos[1].status, Status::Open);
+        assert_eq!(
+            todos[1].created_at,
+            CREATED_AT.parse::<DateTime<Local>>().unwrap()
+        );
+        assert_eq!(todos[1].updated_at, todos[1].created_at);
+        assert_ne!(todos[0].uuid, todos[1].uuid);
+        assert_eq!(todos[1].priority, Priority::None);
+        assert!(todos[1].tags.is_empty() && todos[1].fields.is_empty());
+        assert!(todos[1].deleted_at.is_none() && todos[1].list.is_none());
+    }
+
+    #[test]
+    fn upgrades_a_sqlite_schema_from_version_0() {
+        let path = env::temp_dir().join(format!("rust-todo-migrate-{}.db", process::id()));
+        let _ = fs::remove_file(&path);
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        conn.execute_batch(SQLITE_MIGRATIONS[0]).unwrap();
+        conn.execute_batch(&format!(
+            "UPDATE metadata SET seq_id = 2;
+            INSERT INTO todos (id, created_at, text, is_completed) VALUES
+                (1, '{0}', 'pay rent', 1),
+                (2, '{0}', 'call mom', 0);",
+            CREATED_AT
+        ))
+        .unwrap();
+        drop(conn);
+
+        let storage = SqliteStorage::open(&path).unwrap();
+        let metadata = storage.load_metadata().unwrap();
+        let todos = storage.load_todos().unwrap();
+        let conn = rusqlite::Connection::open(&path).unwrap();
+        let version: u32 = conn
+            .query_row("PRAGMA user_version", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(version, SCHEMA_VERSION);
+        assert_eq!(metadata.seq_id, 2);
+        assert_eq!(todos.len(), 2);
+        assert_eq!((todos[0].id, todos[0].text.as_str()), (1, "pay rent"));
+        assert_eq!(todos[0].status, Status::Done);
+        assert_eq!(todos[1].status, Status::Open);
+        assert_eq!(
+            todos[1].created_at,
+            CREATED_AT.parse::<DateTime<Local>>().unwrap()
+        );
+        assert_eq!(todos[1].updated_at, todos[1].created_at);
+        assert_ne!(todos[0].uuid, todos[1].uuid);
+        assert_eq!(todos[1].priority, Priority::None);
+        assert!(todos[1].deleted_at.is_none() && todos[1].list.is_none());
+
+        drop((storage, conn));
+        fs::remove_file(&path).unwrap();
+    }
+}
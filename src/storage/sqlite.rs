@@ -0,0 +1,244 @@
+use super::{migrations, Storage};
+use crate::dates::utc;
+use crate::todo::{Metadata, Todo};
+use chrono::prelude::*;
+use clap::ValueEnum;
+use rusqlite::{params, Connection, Row};
+use std::collections::BTreeMap;
+use std::io;
+use std::path::Path;
+use uuid::Uuid;
+
+pub struct SqliteStorage {
+    conn: Connection,
+}
+
+impl SqliteStorage {
+    pub fn open(path: impl AsRef<Path>) -> io::Result<Self> {
+        let mut conn = Connection::open(path).map_err(to_io_error)?;
+        migrate(&mut conn)?;
+
+        Ok(SqliteStorage { conn })
+    }
+}
+
+/// Applies the pending schema migrations, tracking the schema version in
+/// `PRAGMA user_version`.
+fn migrate(conn: &mut Connection) -> io::Result<()> {
+    let version: u32 = conn
+        .query_row("PRAGMA user_version", [], |row| row.get(0))
+        .map_err(to_io_error)?;
+
+    for (version, sql) in migrations::sqlite_migrations(version)? {
+        log::info!("Migrating SQLite schema to version {}", version);
+        let tx = conn.transaction().map_err(to_io_error)?;
+        tx.execute_batch(sql).map_err(to_io_error)?;
+        tx.pragma_update(None, "user_version", version)
+            .map_err(to_io_error)?;
+        tx.commit().map_err(to_io_error)?;
+    }
+
+    Ok(())
+}
+
+impl Storage for SqliteStorage {
+    fn load_metadata(&self) -> io::Result<Metadata> {
+        self.conn
+            .query_row("SELECT seq_id FROM metadata WHERE id = 1", [], |row| {
+                Ok(Metadata {
+                    seq_id: row.get(0)?,
+                    version: migrations::SCHEMA_VERSION,
+                })
+            })
+            .map_err(to_io_error)
+    }
+
+    fn load_todos(&self) -> io::Result<Vec<Todo>> {
+        let mut stmt = self
+            .conn
+            .prepare(
+                "SELECT id, uuid, created_at, updated_at, text, status, completed_at, due_at,
+                priority, notes, pinned, parent, deleted_at, snoozed_until,
+                scheduled_at, list FROM todos
+                ORDER BY id",
+            )
+            .map_err(to_io_error)?;
+
+        let rows = stmt
+            .query_map([], |row| {
+                Ok(Todo {
+                    id: row.get("id")?,
+                    uuid: get_uuid(row, "uuid")?,
+                    created_at: get_datetime(row, "created_at")?,
+                    updated_at: get_datetime(row, "updated_at")?,
+                    text: row.get("text")?,
+                    status: get_enum(row, "status")?,
+                    completed_at: get_optional_datetime(row, "completed_at")?,
+                    due_at: get_optional_datetime(row, "due_at")?,
+                    priority: get_enum(row, "priority")?,
+                    tags: Vec::new(),
+                    fields: BTreeMap::new(),
+                    notes: row.get("notes")?,
+                    pinned: row.get("pinned")?,
+                    parent: row.get("parent")?,
+                    deleted_at: get_optional_datetime(row, "deleted_at")?,
+                    snoozed_until: get_optional_datetime(row, "snoozed_until")?,
+                    scheduled_at: get_optional_datetime(row, "scheduled_at")?,
+                    list: row.get("list")?,
+                })
+            })
+            .map_err(to_io_error)?;
+        let mut todos = rows
+            .collect::<Result<Vec<Todo>, _>>()
+            .map_err(to_io_error)?;
+
+        let mut stmt = self
+            .conn
+            .prepare("SELECT todo_id, tag FROM tags ORDER BY rowid")
+            .map_err(to_io_error)?;
+        let tags = stmt
+            .query_map([], |row| {
+                Ok((row.get::<_, u32>(0)?, row.get::<_, String>(1)?))
+            })
+            .map_err(to_io_error)?;
+        for tag in tags {
+            let (todo_id, tag) = tag.map_err(to_io_error)?;
+            if let Some(todo) = todos.iter_mut().find(|t| t.id == todo_id) {
+                todo.tags.push(tag);
+            }
+        }
+
+        let mut stmt = self
+            .conn
+            .prepare("SELECT todo_id, key, value FROM fields")
+            .map_err(to_io_error)?;
+        let fields = stmt
+            .query_map([], |row| {
+                Ok((
+                    row.get::<_, u32>(0)?,
+                    row.get::<_, String>(1)?,
+                    row.get::<_, String>(2)?,
+                ))
+            })
+            .map_err(to_io_error)?;
+        for field in fields {
+            let (todo_id, key, value) = field.map_err(to_io_error)?;
+            if let Some(todo) = todos.iter_mut().find(|t| t.id == todo_id) {
+                todo.fields.insert(key, value);
+            }
+        }
+
+        Ok(todos)
+    }
+
+    fn save_todos(&mut self, metadata: &Metadata, todos: &[Todo]) -> io::Result<()> {
+        let tx = self.conn.transaction().map_err(to_io_error)?;
+
+        tx.execute(
+            "UPDATE metadata SET seq_id = ?1 WHERE id = 1",
+            [metadata.seq_id],
+        )
+        .map_err(to_io_error)?;
+        tx.execute("DELETE FROM todos", []).map_err(to_io_error)?;
+        tx.execute("DELETE FROM tags", []).map_err(to_io_error)?;
+        tx.execute("DELETE FROM fields", []).map_err(to_io_error)?;
+
+        {
+            let mut stmt = tx
+                .prepare(
+                    "INSERT INTO todos
+                    (id, uuid, created_at, updated_at, text, status, completed_at,
+                    due_at, priority, notes, pinned, parent, deleted_at, snoozed_until,
+                    scheduled_at, list)
+                    VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16)",
+                )
+                .map_err(to_io_error)?;
+
+            for todo in todos {
+                stmt.execute(params![
+                    todo.id,
+                    todo.uuid.to_string(),
+                    utc::format(todo.created_at),
+                    utc::format(todo.updated_at),
+                    todo.text,
+                    todo.status.as_str(),
+                    todo.completed_at.map(utc::format),
+                    todo.due_at.map(utc::format),
+                    todo.priority.as_str(),
+                    todo.notes,
+                    todo.pinned,
+                    todo.parent,
+                    todo.deleted_at.map(utc::format),
+                    todo.snoozed_until.map(utc::format),
+                    todo.scheduled_at.map(utc::format),
+                    todo.list,
+                ])
+                .map_err(to_io_error)?;
+            }
+
+            let mut stmt = tx
+                .prepare("INSERT INTO tags (todo_id, tag) VALUES (?1, ?2)")
+                .map_err(to_io_error)?;
+
+            for todo in todos {
+                for tag in &todo.tags {
+                    stmt.execute(params![todo.id, tag]).map_err(to_io_error)?;
+                }
+            }
+
+            let mut stmt = tx
+                .prepare("INSERT INTO fields (todo_id, key, value) VALUES (?1, ?2, ?3)")
+                .map_err(to_io_error)?;
+
+            for todo in todos {
+                for (key, value) in &todo.fields {
+                    stmt.execute(params![todo.id, key, value])
+                        .map_err(to_io_error)?;
+                }
+            }
+        }
+
+        tx.commit().map_err(to_io_error)
+    }
+}
+
+fn get_datetime(row: &Row, column: &str) -> rusqlite::Result<DateTime<Local>> {
+    get_optional_datetime(row, column)?.ok_or(rusqlite::Error::InvalidColumnType(
+        0,
+        column.to_string(),
+        rusqlite::types::Type::Null,
+    ))
+}
+
+/// Timestamps are stored as RFC 3339 text, in UTC; older versions wrote them
+/// with the local offset.
+fn get_optional_datetime(row: &Row, column: &str) -> rusqlite::Result<Option<DateTime<Local>>> {
+    let Some(value) = row.get::<_, Option<String>>(column)? else {
+        return Ok(None);
+    };
+
+    value.parse::<DateTime<Local>>().map(Some).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e))
+    })
+}
+
+fn get_uuid(row: &Row, column: &str) -> rusqlite::Result<Uuid> {
+    let value: String = row.get(column)?;
+
+    Uuid::parse_str(&value).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, Box::new(e))
+    })
+}
+
+/// Enums like `Status` and `Priority` are stored by their name.
+fn get_enum<T: ValueEnum>(row: &Row, column: &str) -> rusqlite::Result<T> {
+    let value: String = row.get(column)?;
+
+    T::from_str(&value, false).map_err(|e| {
+        rusqlite::Error::FromSqlConversionFailure(0, rusqlite::types::Type::Text, e.into())
+    })
+}
+
+fn to_io_error(e: rusqlite::Error) -> io::Error {
+    io::Error::other(e)
+}
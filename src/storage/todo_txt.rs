@@ -0,0 +1,390 @@
+use super::{atomic, migrations, Storage};
+use crate::dates;
+use crate::todo::{Metadata, Priority, Status, Todo};
+use chrono::prelude::*;
+use clap::ValueEnum;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use uuid::Uuid;
+
+/// In UTC, like `2024-07-01T10:00Z`; older versions wrote the local time
+/// without the `Z`.
+const SNOOZED_FORMAT: &str = "%Y-%m-%dT%H:%M";
+
+/// A file in the todo.txt format (<https://github.com/todotxt/todo.txt>), so
+/// the todos can also be edited with other todo.txt apps:
+///
+/// ```text
+/// x 2024-07-02 2024-07-01 pay rent +home due:2024-07-01 id:3 uuid:…
+/// (A) 2024-07-01 call @mom about the trip id:4 uuid:…
+/// ```
+///
+/// Priorities become `(A)` to `(C)`, tags `+project` words, done and
+/// cancelled todos are marked with `x`. What the format has no place for is
+/// kept in `key:value` extensions, with `%20` for spaces. Dates only keep the
+/// day. Lines written by other apps get an id when loaded; `@context` words
+/// simply stay in the text.
+///
+/// The next id is kept next to the file in `<file>.meta.json`, so ids of
+/// todos removed from the file are not given out again.
+pub struct TodoTxtStorage {
+    path: PathBuf,
+}
+
+impl TodoTxtStorage {
+    pub fn new(path: impl Into<PathBuf>) -> Self {
+        TodoTxtStorage { path: path.into() }
+    }
+
+    fn load_document(&self) -> io::Result<(Metadata, Vec<Todo>)> {
+        let seq_id = self
+            .load_stored_metadata()?
+            .map_or(0, |stored| stored.seq_id);
+        if !self.path.exists() && !atomic::backup_path(&self.path).exists() {
+            let (mut metadata, todos) = migrations::empty_database();
+            metadata.seq_id = seq_id;
+            return Ok((metadata, todos));
+        }
+
+        atomic::load_with_fallback(&self.path, |path| load(path, seq_id))
+    }
+
+    fn metadata_path(&self) -> PathBuf {
+        let mut path = self.path.as_os_str().to_owned();
+        path.push(".meta.json");
+        PathBuf::from(path)
+    }
+
+    fn load_stored_metadata(&self) -> io::Result<Option<Metadata>> {
+        let path = self.metadata_path();
+        if !path.exists() && !atomic::backup_path(&path).exists() {
+            return Ok(None);
+        }
+
+        atomic::load_with_fallback(&path, |path| {
+            let contents = fs::read(path)?;
+            Ok(Some(serde_json::from_slice(&contents)?))
+        })
+    }
+}
+
+impl Storage for TodoTxtStorage {
+    fn load_metadata(&self) -> io::Result<Metadata> {
+        Ok(self.load_document()?.0)
+    }
+
+    fn load_todos(&self) -> io::Result<Vec<Todo>> {
+        Ok(self.load_document()?.1)
+    }
+
+    fn save_todos(&mut self, metadata: &Metadata, todos: &[Todo]) -> io::Result<()> {
+        // Written first, so the next id is never behind the file
+        atomic::write_atomically(&self.metadata_path(), &serde_json::to_vec(metadata)?)?;
+
+        let mut contents: String = todos.iter().map(|todo| format_line(todo) + "\n").collect();
+        if contents.is_empty() {
+            contents.push('\n');
+        }
+
+        atomic::write_atomically(&self.path, contents.as_bytes())
+    }
+}
+
+/// Loads the todos of the file; `seq_id` is the last id given out as far as
+/// the stored metadata knows.
+fn load(path: &Path, seq_id: u32) -> io::Result<(Metadata, Vec<Todo>)> {
+    let contents = fs::read_to_string(path)?;
+    let (mut metadata, _) = migrations::empty_database();
+
+    let lines: Vec<(Option<u32>, Todo)> = contents
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(parse_line)
+        .collect();
+    // Also follows ids written by hand or by older versions without metadata
+    let highest = lines.iter().filter_map(|(id, _)| *id).max().unwrap_or(0);
+    metadata.seq_id = seq_id.max(highest);
+
+    // Lines without an id, e.g. added by other apps, get the next ones
+    let mut todos = Vec::new();
+    for (id, mut todo) in lines {
+        todo.id = id.unwrap_or_else(|| {
+            metadata.seq_id += 1;
+            metadata.seq_id
+        });
+        todos.push(todo);
+    }
+
+    Ok((metadata, todos))
+}
+
+fn format_line(todo: &Todo) -> String {
+    let mut words = Vec::new();
+
+    // A completion date is only recognized before the creation date
+    if todo.status.is_closed() {
+        words.push("x".to_string());
+        words.push(format_date(todo.completed_at.unwrap_or(todo.updated_at)));
+    } else if let Some(letter) = priority_letter(todo.priority) {
+        words.push(format!("({})", letter));
+    }
+    words.push(format_date(todo.created_at));
+    words.push(todo.text.clone());
+    words.extend(todo.tags.iter().map(|tag| format!("+{}", tag)));
+
+    let mut extension = |key: &str, value: &str| words.push(format!("{}:{}", key, encode(value)));
+    if todo.status.is_closed() {
+        if let Some(letter) = priority_letter(todo.priority) {
+            extension("pri", &letter.to_string());
+        }
+    }
+    if let Some(due_at) = todo.due_at {
+        extension("due", &format_date(due_at));
+    }
+    if let Some(scheduled_at) = todo.scheduled_at {
+        // The threshold date other todo.txt apps know
+        extension("t", &format_date(scheduled_at));
+    }
+    if !matches!(todo.status, Status::Open | Status::Done) {
+        extension("status", todo.status.as_str());
+    }
+    if todo.pinned {
+        extension("pinned", "yes");
+    }
+    if let Some(parent) = todo.parent {
+        extension("parent", &parent.to_string());
+    }
+    if let Some(list) = &todo.list {
+        extension("list", list);
+    }
+    if !todo.notes.is_empty() {
+        extension("notes", &todo.notes);
+    }
+    if let Some(deleted_at) = todo.deleted_at {
+        extension("deleted", &format_date(deleted_at));
+    }
+    if let Some(snoozed_until) = todo.snoozed_until {
+        // Snoozing for a few hours needs the time
+        let snoozed_until = snoozed_until.with_timezone(&Utc).format(SNOOZED_FORMAT);
+        extension("snoozed", &format!("{}Z", snoozed_until));
+    }
+    for (key, value) in &todo.fields {
+        extension(key, value);
+    }
+    // Unlike the other dates with the time, for telling which change is newer
+    extension("updated", &dates::utc::format(todo.updated_at));
+    extension("id", &todo.id.to_string());
+    extension("uuid", &todo.uuid.to_string());
+
+    words.join(" ")
+}
+
+/// Parses a line into the todo and its id, if it has one. Every line is a
+/// todo; the extensions this backend writes itself stay in the text when
+/// their value is invalid, all others become fields.
+fn parse_line(line: &str) -> (Option<u32>, Todo) {
+    let mut todo = Todo::new(0, "");
+    let mut words = line.split_whitespace().peekable();
+
+    let done = words.next_if_eq(&"x").is_some();
+    if let Some(priority) = words.peek().and_then(|word| parse_priority(word)) {
+        todo.priority = priority;
+        words.next();
+    }
+    let mut dates = Vec::new();
+    while dates.len() < if done { 2 } else { 1 } {
+        match words.peek().and_then(|word| parse_date(word)) {
+            Some(date) => dates.push(date),
+            None => break,
+        }
+        words.next();
+    }
+    let created_at = if done {
+        todo.status = Status::Done;
+        todo.completed_at = dates.first().copied();
+        dates.get(1)
+    } else {
+        dates.first()
+    };
+    // Without a creation date, a done todo was created by the time it was
+    // done at the latest
+    if let Some(created_at) = created_at.copied().or(todo.completed_at) {
+        todo.created_at = created_at;
+    }
+
+    let mut id = None;
+    let mut updated_at = None;
+    let mut text = Vec::new();
+    for word in words {
+        let Some((key, value)) = split_extension(word) else {
+            text.push(word);
+            continue;
+        };
+        let value = decode(value);
+
+        let known = match key {
+            "id" => value.parse().map(|v| id = Some(v)).is_ok(),
+            "uuid" => value.parse().map(|v: Uuid| todo.uuid = v).is_ok(),
+            "updated" => DateTime::parse_from_rfc3339(&value)
+                .map(|v| updated_at = Some(v.with_timezone(&Local)))
+                .is_ok(),
+            "due" => dates::parse_datetime(&value)
+                .map(|v| todo.due_at = Some(v))
+                .is_ok(),
+            "list" => {
+                todo.list = Some(value);
+                true
+            }
+            "t" => parse_date(&value)
+                .map(|v| todo.scheduled_at = Some(v))
+                .is_some(),
+            "pri" => parse_letter(&value).map(|v| todo.priority = v).is_some(),
+            "status" => Status::from_str(&value, true)
+                .map(|status| {
+                    // The `x` says whether the todo is closed
+                    if status.is_closed() == done {
+                        todo.status = status;
+                    }
+                })
+                .is_ok(),
+            "pinned" => {
+                todo.pinned = value == "yes";
+                true
+            }
+            "parent" => value.parse().map(|v| todo.parent = Some(v)).is_ok(),
+            "notes" => {
+                todo.notes = value;
+                true
+            }
+            "deleted" => parse_date(&value)
+                .map(|v| todo.deleted_at = Some(v))
+                .is_some(),
+            "snoozed" => parse_snoozed(&value)
+                .map(|v| todo.snoozed_until = Some(v))
+                .is_some(),
+            key => {
+                todo.fields.insert(key.to_lowercase(), value);
+                true
+            }
+        };
+        if !known {
+            text.push(word);
+        }
+    }
+    todo.set_text(&text.join(" "));
+    // Lines of other apps only tell when the todo was created or done
+    todo.updated_at = updated_at.unwrap_or(todo.completed_at.unwrap_or(todo.created_at));
+
+    (id, todo)
+}
+
+/// Splits a `key:value` word. Words like `10:30` or URLs are not extensions.
+fn split_extension(word: &str) -> Option<(&str, &str)> {
+    let (key, value) = word.split_once(':')?;
+    let is_key = key.starts_with(|c: char| c.is_ascii_alphabetic())
+        && key
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-');
+
+    (is_key && !value.is_empty() && !value.starts_with('/')).then_some((key, value))
+}
+
+fn priority_letter(priority: Priority) -> Option<char> {
+    match priority {
+        Priority::None => None,
+        Priority::Low => Some('C'),
+        Priority::Medium => Some('B'),
+        Priority::High => Some('A'),
+    }
+}
+
+/// Parses a priority like `(A)`.
+fn parse_priority(word: &str) -> Option<Priority> {
+    parse_letter(word.strip_prefix('(')?.strip_suffix(')')?)
+}
+
+/// `A` and `B` are high and medium, all letters after them low.
+fn parse_letter(letter: &str) -> Option<Priority> {
+    match letter {
+        "A" => Some(Priority::High),
+        "B" => Some(Priority::Medium),
+        _ if letter.len() == 1 && letter.chars().all(|c| c.is_ascii_uppercase()) => {
+            Some(Priority::Low)
+        }
+        _ => None,
+    }
+}
+
+fn format_date(datetime: DateTime<Local>) -> String {
+    datetime.format("%Y-%m-%d").to_string()
+}
+
+fn parse_snoozed(value: &str) -> Option<DateTime<Local>> {
+    match value.strip_suffix('Z') {
+        Some(utc) => NaiveDateTime::parse_from_str(utc, SNOOZED_FORMAT)
+            .ok()
+            .map(|v| Utc.from_utc_datetime(&v).with_timezone(&Local)),
+        None => NaiveDateTime::parse_from_str(value, SNOOZED_FORMAT)
+            .ok()
+            .and_then(|v| Local.from_local_datetime(&v).earliest()),
+    }
+}
+
+/// Parses a `YYYY-MM-DD` date as the start of that day.
+fn parse_date(word: &str) -> Option<DateTime<Local>> {
+    let date = NaiveDate::parse_from_str(word, "%Y-%m-%d").ok()?;
+
+    Local
+        .from_local_datetime(&date.and_time(NaiveTime::MIN))
+        .earliest()
+}
+
+/// Makes a value fit into a single word.
+fn encode(value: &str) -> String {
+    value
+        .replace('%', "%25")
+        .replace(' ', "%20")
+        .replace('\t', "%09")
+        .replace('\r', "%0D")
+        .replace('\n', "%0A")
+}
+
+fn decode(value: &str) -> String {
+    value
+        .replace("%20", " ")
+        .replace("%09", "\t")
+        .replace("%0D", "\r")
+        .replace("%0A", "\n")
+        .replace("%25", "%")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn line_round_trips() {
+        let mut todo = Todo::with_tags_from_text(3, "pay rent +home");
+        todo.priority = Priority::High;
+        todo.list = Some("chores".to_string());
+        todo.notes = "before the 3rd".to_string();
+        todo.updated_at = todo.created_at + Duration::minutes(90);
+
+        let (id, parsed) = parse_line(&format_line(&todo));
+        assert_eq!(id, Some(3));
+        assert_eq!(parsed.uuid, todo.uuid);
+        assert_eq!(parsed.text, "pay rent");
+        assert_eq!(parsed.tags, ["home"]);
+        assert_eq!(parsed.priority, Priority::High);
+        assert_eq!(parsed.list.as_deref(), Some("chores"));
+        assert_eq!(parsed.notes, todo.notes);
+        assert_eq!(parsed.updated_at, todo.updated_at);
+
+        // Lines of other apps were last changed when created
+        let (id, parsed) = parse_line("2024-07-01 call mom");
+        assert_eq!(id, None);
+        assert_eq!(parsed.updated_at, parsed.created_at);
+    }
+}
@@ -0,0 +1,303 @@
+//! Moving todos from and to Taskwarrior through its JSON format, for `todo
+//! import taskwarrior` and `todo export --format taskwarrior`.
+
+use crate::database::Database;
+use crate::error::TodoError;
+use crate::journal::Operation;
+use crate::output;
+use crate::todo::{self, Priority, Status, Todo};
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use std::collections::{BTreeMap, HashSet};
+use std::fs;
+use std::io::{self, Write};
+use std::path::Path;
+use uuid::Uuid;
+
+/// Attributes Taskwarrior gives a meaning to; all others are user defined
+/// and exchanged as fields.
+const ATTRIBUTES: &[&str] = &[
+    "uuid",
+    "id",
+    "description",
+    "status",
+    "entry",
+    "modified",
+    "end",
+    "due",
+    "start",
+    "wait",
+    "until",
+    "scheduled",
+    "recur",
+    "mask",
+    "imask",
+    "parent",
+    "depends",
+    "priority",
+    "tags",
+    "annotations",
+    "project",
+    "urgency",
+];
+
+/// A task as `task export` writes and `task import` reads it.
+#[derive(Serialize, Deserialize)]
+struct Task {
+    uuid: Uuid,
+    description: String,
+    status: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    entry: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    modified: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    start: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    end: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    due: Option<String>,
+    /// Until when the task is hidden, like a snoozed todo
+    #[serde(skip_serializing_if = "Option::is_none")]
+    wait: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    scheduled: Option<String>,
+    /// `H`, `M` or `L`
+    #[serde(skip_serializing_if = "Option::is_none")]
+    priority: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    project: Option<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    tags: Vec<String>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    annotations: Vec<Annotation>,
+    #[serde(flatten)]
+    other: BTreeMap<String, Value>,
+}
+
+#[derive(Serialize, Deserialize)]
+struct Annotation {
+    #[serde(default)]
+    entry: String,
+    description: String,
+}
+
+/// Creates a todo for every task of a `task export` file whose uuid is not
+/// in the database yet, deleted tasks in the trash. Recurring tasks are
+/// represented by their instances, so their templates are skipped.
+pub fn import(db: &mut Database, path: &Path) -> Result<(), TodoError> {
+    let contents = fs::read_to_string(path).map_err(|e| TodoError::from(e).in_file(path))?;
+    let tasks = parse(&contents).map_err(|e| e.in_file(path))?;
+
+    let known: HashSet<Uuid> = db.todos.iter().chain(&db.trash).map(|t| t.uuid).collect();
+    let (mut imported, mut skipped) = (0, 0);
+    for task in tasks {
+        if known.contains(&task.uuid) || task.status == "recurring" {
+            skipped += 1;
+            continue;
+        }
+
+        let text: String = task.description.chars().take(db.max_text_length).collect();
+        let text = todo::sanitize_text(&text, db.max_text_length)
+            .unwrap_or_else(|_| "(no description)".to_string());
+        let mut todo = db.new_todo(&text)?;
+        let deleted_at = take_over(&mut todo, task);
+        let id = todo.id;
+        db.apply(Operation::Add { todo })?;
+        if let Some(deleted_at) = deleted_at {
+            db.apply(Operation::Delete { id, deleted_at })?;
+        }
+        imported += 1;
+    }
+    output::status(format!(
+        "Imported {} task(s), skipped {} already imported or recurring",
+        imported, skipped
+    ));
+
+    Ok(db.save()?)
+}
+
+/// Reads a JSON array of tasks, or one task per line like older versions of
+/// Taskwarrior write them.
+fn parse(contents: &str) -> Result<Vec<Task>, TodoError> {
+    if contents.trim_start().starts_with('[') {
+        // The error already names the line
+        return serde_json::from_str(contents).map_err(|e| TodoError::parse(e.to_string()));
+    }
+
+    contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(index, line)| {
+            serde_json::from_str(line.trim().trim_end_matches(',')).map_err(|e| TodoError::Parse {
+                line: Some(index + 1),
+                reason: e.to_string(),
+            })
+        })
+        .collect()
+}
+
+/// Gives the todo what the task says about it. Returns when the task was
+/// deleted, if it was.
+fn take_over(todo: &mut Todo, task: Task) -> Option<DateTime<Local>> {
+    todo.uuid = task.uuid;
+    if let Some(created_at) = task.entry.as_deref().and_then(parse_date) {
+        todo.created_at = created_at;
+    }
+    let end = task.end.as_deref().and_then(parse_date);
+    todo.status = match task.status.as_str() {
+        "completed" => Status::Done,
+        _ if task.start.is_some() => Status::InProgress,
+        _ => Status::Open,
+    };
+    if todo.status == Status::Done {
+        todo.completed_at = Some(end.unwrap_or(todo.created_at));
+    }
+    todo.due_at = task.due.as_deref().and_then(parse_date);
+    todo.snoozed_until = task.wait.as_deref().and_then(parse_date);
+    todo.scheduled_at = task.scheduled.as_deref().and_then(parse_date);
+    todo.priority = match task.priority.as_deref() {
+        Some("H") => Priority::High,
+        Some("M") => Priority::Medium,
+        Some("L") => Priority::Low,
+        _ => Priority::None,
+    };
+    for tag in &task.tags {
+        todo.add_tag(tag);
+    }
+    let annotations: Vec<&str> = task
+        .annotations
+        .iter()
+        .map(|a| a.description.as_str())
+        .collect();
+    todo.notes = annotations.join("\n");
+    if let Some(project) = task.project {
+        todo.fields.insert("project".to_string(), project);
+    }
+    for (key, value) in task.other {
+        if let (false, Value::String(value)) = (ATTRIBUTES.contains(&key.as_str()), value) {
+            todo.fields.insert(key.to_lowercase(), value);
+        }
+    }
+    todo.updated_at = task
+        .modified
+        .as_deref()
+        .and_then(parse_date)
+        .unwrap_or(todo.created_at);
+
+    (task.status == "deleted").then(|| end.unwrap_or(todo.updated_at))
+}
+
+/// Writes the todos as a JSON array for `task import`. Cancelled todos
+/// become deleted tasks, as Taskwarrior has no such status, and the lines of
+/// the notes annotations.
+pub fn write_tasks(out: &mut dyn Write, todos: &[Todo]) -> io::Result<()> {
+    let tasks: Vec<Task> = todos.iter().map(task).collect();
+    serde_json::to_writer_pretty(&mut *out, &tasks)?;
+
+    writeln!(out)
+}
+
+fn task(todo: &Todo) -> Task {
+    let status = match todo.status {
+        Status::Done => "completed",
+        Status::Cancelled => "deleted",
+        Status::Open | Status::InProgress | Status::Blocked => "pending",
+    };
+    let annotations = todo
+        .notes
+        .lines()
+        .filter(|line| !line.trim().is_empty())
+        .map(|line| Annotation {
+            entry: format_date(todo.updated_at),
+            description: line.to_string(),
+        })
+        .collect();
+    let mut fields = todo.fields.clone();
+    let project = fields.remove("project");
+    let other = fields
+        .into_iter()
+        .filter(|(key, _)| !ATTRIBUTES.contains(&key.as_str()))
+        .map(|(key, value)| (key, Value::String(value)))
+        .collect();
+
+    Task {
+        uuid: todo.uuid,
+        description: todo.text.clone(),
+        status: status.to_string(),
+        entry: Some(format_date(todo.created_at)),
+        modified: Some(format_date(todo.updated_at)),
+        start: (todo.status == Status::InProgress).then(|| format_date(todo.updated_at)),
+        end: match todo.status {
+            Status::Done => Some(format_date(todo.completed_at.unwrap_or(todo.updated_at))),
+            Status::Cancelled => Some(format_date(todo.updated_at)),
+            _ => None,
+        },
+        due: todo.due_at.map(format_date),
+        wait: todo.snoozed_until.map(format_date),
+        scheduled: todo.scheduled_at.map(format_date),
+        priority: match todo.priority {
+            Priority::High => Some("H".to_string()),
+            Priority::Medium => Some("M".to_string()),
+            Priority::Low => Some("L".to_string()),
+            Priority::None => None,
+        },
+        project,
+        tags: todo.tags.clone(),
+        annotations,
+        other,
+    }
+}
+
+fn format_date(time: DateTime<Local>) -> String {
+    time.with_timezone(&Utc)
+        .format("%Y%m%dT%H%M%SZ")
+        .to_string()
+}
+
+/// Parses Taskwarrior's `20240701T120000Z`, or an ISO 8601 time as `task
+/// import` accepts it too.
+fn parse_date(value: &str) -> Option<DateTime<Local>> {
+    if let Ok(time) = NaiveDateTime::parse_from_str(value, "%Y%m%dT%H%M%SZ") {
+        return Some(Utc.from_utc_datetime(&time).with_timezone(&Local));
+    }
+
+    DateTime::parse_from_rfc3339(value)
+        .ok()
+        .map(|time| time.with_timezone(&Local))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn task_round_trips() {
+        let mut todo = Todo::new(1, "fix the bike");
+        todo.priority = Priority::High;
+        todo.tags = vec!["bike".to_string()];
+        todo.notes = "buy a tube\nand a pump".to_string();
+        todo.fields
+            .insert("project".to_string(), "home".to_string());
+        todo.fields.insert("estimate".to_string(), "2h".to_string());
+        todo.fields
+            .insert("urgency".to_string(), "high".to_string());
+        todo.set_status(Status::Done, Local::now());
+
+        let json = serde_json::to_string(&task(&todo)).unwrap();
+        let mut imported = Todo::new(2, "");
+        let deleted_at = take_over(&mut imported, serde_json::from_str(&json).unwrap());
+        assert!(deleted_at.is_none());
+        assert_eq!(imported.uuid, todo.uuid);
+        assert_eq!(imported.priority, Priority::High);
+        assert_eq!(imported.tags, todo.tags);
+        assert_eq!(imported.notes, todo.notes);
+        assert_eq!(imported.status, Status::Done);
+        assert_eq!(imported.fields["project"], "home");
+        assert_eq!(imported.fields["estimate"], "2h");
+        // Taskwarrior computes the urgency itself
+        assert!(!imported.fields.contains_key("urgency"));
+    }
+}
@@ -0,0 +1,495 @@
+use crate::csv;
+use crate::dates::utc;
+use chrono::prelude::*;
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::fmt;
+use std::str::FromStr;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Todo {
+    pub id: u32,
+    /// Identifies the todo across machines and renumbering, unlike `id`
+    #[serde(default = "Uuid::new_v4")]
+    pub uuid: Uuid,
+    #[serde(default)]
+    pub status: Status,
+    pub text: String,
+    #[serde(serialize_with = "utc::serialize")]
+    pub created_at: DateTime<Local>,
+    /// When the todo was last changed; journals from before this was tracked
+    /// are replayed now
+    #[serde(default = "Local::now", serialize_with = "utc::serialize")]
+    pub updated_at: DateTime<Local>,
+    #[serde(default, serialize_with = "utc::serialize_option")]
+    pub completed_at: Option<DateTime<Local>>,
+    #[serde(default, serialize_with = "utc::serialize_option")]
+    pub due_at: Option<DateTime<Local>>,
+    /// When work on the todo can start; it is hidden from listings until
+    /// then, but unlike a due date nothing is late after it
+    #[serde(default, serialize_with = "utc::serialize_option")]
+    pub scheduled_at: Option<DateTime<Local>>,
+    #[serde(default)]
+    pub priority: Priority,
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Longer, possibly multi-line description
+    #[serde(default)]
+    pub notes: String,
+    /// User defined `key:value` pairs like `ticket:JIRA-123`; keys are
+    /// lowercase
+    #[serde(default)]
+    pub fields: BTreeMap<String, String>,
+    /// Pinned todos are listed before all others
+    #[serde(default)]
+    pub pinned: bool,
+    /// Id of the todo this is a subtask of
+    #[serde(default)]
+    pub parent: Option<u32>,
+    /// When the todo was moved to the trash; the database keeps trashed todos
+    /// apart from the others
+    #[serde(default, serialize_with = "utc::serialize_option")]
+    pub deleted_at: Option<DateTime<Local>>,
+    /// Hidden from listings until then
+    #[serde(default, serialize_with = "utc::serialize_option")]
+    pub snoozed_until: Option<DateTime<Local>>,
+    /// The named list the todo is on, like `work`; `None` for no list
+    #[serde(default)]
+    pub list: Option<String>,
+}
+
+/// Used when the config does not set `max_text_length`.
+pub const DEFAULT_MAX_TEXT_LENGTH: usize = 200;
+
+/// Cleans up the text of a new or edited todo: control characters are
+/// dropped and runs of whitespace become a single space, so pasted text
+/// cannot break the table or the storage. Texts that are empty afterwards or
+/// longer than `max_length` characters are rejected.
+pub fn sanitize_text(text: &str, max_length: usize) -> Result<String, String> {
+    let text: String = text
+        .chars()
+        .map(|c| if c.is_whitespace() { ' ' } else { c })
+        .filter(|c| !c.is_control())
+        .collect();
+    let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+
+    let length = text.chars().count();
+    if length == 0 {
+        Err("The text of a todo cannot be empty".to_string())
+    } else if length > max_length {
+        Err(format!(
+            "The text is {} characters long, at most {} are allowed",
+            length, max_length
+        ))
+    } else {
+        Ok(text)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+#[serde(rename_all = "kebab-case")]
+pub enum Status {
+    #[default]
+    Open,
+    InProgress,
+    Blocked,
+    Done,
+    Cancelled,
+}
+
+impl Status {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Status::Open => "open",
+            Status::InProgress => "in-progress",
+            Status::Blocked => "blocked",
+            Status::Done => "done",
+            Status::Cancelled => "cancelled",
+        }
+    }
+
+    /// Done and cancelled todos need no more work.
+    pub fn is_closed(self) -> bool {
+        matches!(self, Status::Done | Status::Cancelled)
+    }
+}
+
+/// Ordered from least to most important.
+#[derive(
+    Debug, Default, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ValueEnum,
+)]
+#[serde(rename_all = "lowercase")]
+pub enum Priority {
+    #[default]
+    None,
+    Low,
+    Medium,
+    High,
+}
+
+impl Priority {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Priority::None => "none",
+            Priority::Low => "low",
+            Priority::Medium => "medium",
+            Priority::High => "high",
+        }
+    }
+}
+
+impl Todo {
+    /// A new open todo created now.
+    pub fn new(id: u32, text: impl Into<String>) -> Self {
+        let now = Local::now();
+
+        Todo {
+            id,
+            uuid: Uuid::new_v4(),
+            status: Status::Open,
+            text: text.into(),
+            created_at: now,
+            updated_at: now,
+            completed_at: None,
+            due_at: None,
+            priority: Priority::None,
+            tags: Vec::new(),
+            notes: String::new(),
+            fields: BTreeMap::new(),
+            pinned: false,
+            parent: None,
+            deleted_at: None,
+            snoozed_until: None,
+            scheduled_at: None,
+            list: None,
+        }
+    }
+
+    /// Creates a todo from text that may contain `+tag` words anywhere. The
+    /// tags are taken out of the text and attached to the todo.
+    pub fn with_tags_from_text(id: u32, text: &str) -> Self {
+        let mut todo = Todo::new(id, "");
+        todo.set_text(text);
+
+        todo
+    }
+
+    /// Replaces the text; `+tag` words are added as tags like for a new todo,
+    /// the existing tags are kept.
+    pub fn set_text(&mut self, text: &str) {
+        let mut words = Vec::new();
+        for word in text.split_whitespace() {
+            match word.strip_prefix('+') {
+                Some(tag) if !tag.is_empty() => self.add_tag(tag),
+                _ => words.push(word),
+            }
+        }
+
+        self.text = words.join(" ");
+    }
+
+    pub fn has_tag(&self, tag: &str) -> bool {
+        self.tags.iter().any(|t| t.eq_ignore_ascii_case(tag))
+    }
+
+    pub fn add_tag(&mut self, tag: &str) {
+        if !self.has_tag(tag) {
+            self.tags.push(tag.to_string());
+        }
+    }
+
+    pub fn remove_tag(&mut self, tag: &str) {
+        self.tags.retain(|t| !t.eq_ignore_ascii_case(tag));
+    }
+
+    pub fn is_completed(&self) -> bool {
+        self.status == Status::Done
+    }
+
+    /// Changes the status, keeping track of when the todo was completed.
+    pub fn set_status(&mut self, status: Status, now: DateTime<Local>) {
+        if status == Status::Done && self.status != Status::Done {
+            self.completed_at = Some(now);
+        } else if status != Status::Done {
+            self.completed_at = None;
+        }
+
+        self.status = status;
+    }
+
+    pub fn is_overdue(&self, now: DateTime<Local>) -> bool {
+        !self.status.is_closed() && self.due_at.is_some_and(|due_at| due_at < now)
+    }
+
+    /// Whether the todo is still snoozed; it wakes up by itself once the
+    /// time has passed.
+    pub fn is_snoozed(&self, now: DateTime<Local>) -> bool {
+        self.snoozed_until.is_some_and(|until| until > now)
+    }
+
+    /// Whether the todo is on `list`; without a list, every todo is.
+    pub fn in_list(&self, list: Option<&str>) -> bool {
+        match list {
+            Some(list) => self.list.as_deref() == Some(list),
+            None => true,
+        }
+    }
+
+    /// Whether the todo is scheduled to start later.
+    pub fn is_scheduled_later(&self, now: DateTime<Local>) -> bool {
+        self.scheduled_at.is_some_and(|start| start > now)
+    }
+
+    /// Whether the todo is out of sight for now, snoozed or scheduled later.
+    pub fn is_deferred(&self, now: DateTime<Local>) -> bool {
+        self.is_snoozed(now) || self.is_scheduled_later(now)
+    }
+
+    /// Takes over what `other` knows about the same task: its notes are
+    /// appended, tags and fields are added and the earlier creation date, the
+    /// earlier due date and the higher priority are kept. The text and status
+    /// stay as they are.
+    pub fn merge(&mut self, other: &Todo) {
+        if !other.notes.is_empty() {
+            if !self.notes.is_empty() {
+                self.notes.push_str("\n\n");
+            }
+            self.notes.push_str(&other.notes);
+        }
+        for tag in &other.tags {
+            self.add_tag(tag);
+        }
+        for (key, value) in &other.fields {
+            self.fields
+                .entry(key.clone())
+                .or_insert_with(|| value.clone());
+        }
+
+        self.created_at = self.created_at.min(other.created_at);
+        self.due_at = match (self.due_at, other.due_at) {
+            (Some(a), Some(b)) => Some(a.min(b)),
+            (a, b) => a.or(b),
+        };
+        self.priority = self.priority.max(other.priority);
+        self.pinned |= other.pinned;
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+pub struct Metadata {
+    pub seq_id: u32,
+    #[serde(default)]
+    pub version: u32,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParseTodoError;
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct ParseMetadataError;
+
+impl FromStr for Todo {
+    type Err = ParseTodoError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let elements = csv::split(s).ok_or(ParseTodoError)?;
+
+        if elements.len() != 4 {
+            return Err(ParseTodoError);
+        }
+
+        let id = elements[0].parse::<u32>().map_err(|_| ParseTodoError)?;
+        let created_at = elements[1]
+            .parse::<DateTime<Local>>()
+            .map_err(|_| ParseTodoError)?;
+        let text = elements[2].as_str();
+        let is_completed = elements[3].parse::<bool>().map_err(|_| ParseTodoError)?;
+
+        let mut todo = Todo::new(id, text);
+        todo.created_at = created_at;
+        todo.updated_at = created_at;
+        if is_completed {
+            todo.status = Status::Done;
+        }
+
+        Ok(todo)
+    }
+}
+
+impl FromStr for Metadata {
+    type Err = ParseMetadataError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if !s.starts_with("seq_id:") {
+            return Err(ParseMetadataError);
+        }
+
+        let elements: Vec<&str> = s.split(':').collect();
+
+        if elements.len() != 2 {
+            return Err(ParseMetadataError);
+        }
+
+        let seq_id = elements[1].parse::<u32>().map_err(|_| ParseMetadataError)?;
+
+        // The comma separated format predates versioning
+        Ok(Metadata { seq_id, version: 0 })
+    }
+}
+
+impl fmt::Display for Todo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{},{},{},{}",
+            self.id,
+            // Historic local offsets can have seconds, which the parser does
+            // not accept, so the time is written in UTC
+            utc::format(self.created_at),
+            csv::quote(&self.text),
+            self.is_completed()
+        )
+    }
+}
+
+impl fmt::Display for Metadata {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "seq_id:{}", self.seq_id)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use proptest::prelude::*;
+
+    fn round_trip(text: &str) -> Todo {
+        let todo = Todo::new(7, text);
+        let parsed: Todo = todo.to_string().parse().unwrap();
+
+        assert_eq!(parsed.id, todo.id);
+        assert_eq!(parsed.created_at, todo.created_at);
+        assert_eq!(parsed.text, text);
+        parsed
+    }
+
+    #[test]
+    fn text_with_delimiters_round_trips() {
+        round_trip("buy milk, eggs, bread");
+        round_trip(r#"read "The Rust Book""#);
+        round_trip(r#""quoted", then more"#);
+        round_trip("first line\nsecond line");
+        round_trip("windows\r\nline ending");
+        round_trip("");
+        round_trip("ünïcødé ✓ 🦀");
+    }
+
+    #[test]
+    fn completion_round_trips() {
+        let mut todo = Todo::new(1, "done, finally");
+        todo.status = Status::Done;
+
+        let parsed: Todo = todo.to_string().parse().unwrap();
+        assert_eq!(parsed.status, Status::Done);
+    }
+
+    #[test]
+    fn parses_lines_written_by_older_versions() {
+        let todo: Todo = r#"3,2023-01-01T10:00:00+01:00,say "hi",true"#.parse().unwrap();
+
+        assert_eq!(todo.id, 3);
+        assert_eq!(todo.text, r#"say "hi""#);
+        assert_eq!(todo.status, Status::Done);
+    }
+
+    #[test]
+    fn times_are_stored_in_utc() {
+        let mut todo = Todo::new(1, "fly home");
+        let offset = FixedOffset::east_opt(9 * 3600).unwrap();
+        let due_at = offset.with_ymd_and_hms(2024, 7, 1, 18, 30, 0).unwrap();
+        todo.due_at = Some(due_at.with_timezone(&Local));
+
+        let json = serde_json::to_value(&todo).unwrap();
+        assert_eq!(json["due_at"], "2024-07-01T09:30:00Z");
+        let parsed: Todo = serde_json::from_value(json).unwrap();
+        assert_eq!(parsed.due_at, todo.due_at);
+    }
+
+    #[test]
+    fn merge_keeps_the_information_of_both() {
+        let mut todo = Todo::with_tags_from_text(1, "call bank +money");
+        todo.notes = "ask about fees".to_string();
+        let mut other = Todo::with_tags_from_text(2, "phone the bank +urgent +money");
+        other.notes = "account 1234".to_string();
+        other.created_at = todo.created_at - chrono::Duration::days(3);
+        other.priority = Priority::High;
+
+        todo.merge(&other);
+        assert_eq!(todo.text, "call bank");
+        assert_eq!(todo.tags, ["money", "urgent"]);
+        assert_eq!(todo.notes, "ask about fees\n\naccount 1234");
+        assert_eq!(todo.created_at, other.created_at);
+        assert_eq!(todo.priority, Priority::High);
+    }
+
+    #[test]
+    fn rejects_malformed_lines() {
+        let cases = [
+            "",
+            "1,2023-01-01T10:00:00+01:00,a,b,false",
+            "x,2023-01-01T10:00:00+01:00,a,false",
+            "1,yesterday,a,false",
+            "1,2023-01-01T10:00:00+01:00,\"unterminated,false",
+            "1,2023-01-01T10:00:00+01:00,a,maybe",
+        ];
+
+        for line in cases {
+            assert_eq!(line.parse::<Todo>().err(), Some(ParseTodoError), "{}", line);
+        }
+    }
+
+    /// Any instant chrono can represent in the local time zone, with a day
+    /// of margin on both ends for the offset.
+    fn any_datetime() -> impl Strategy<Value = DateTime<Local>> {
+        let min = DateTime::<Utc>::MIN_UTC.timestamp() + 86_400;
+        let max = DateTime::<Utc>::MAX_UTC.timestamp() - 86_400;
+
+        (min..=max, 0..1_000_000_000u32).prop_map(|(secs, nanos)| {
+            Utc.timestamp_opt(secs, nanos)
+                .unwrap()
+                .with_timezone(&Local)
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn display_round_trips(
+            id in any::<u32>(),
+            text in any::<String>(),
+            created_at in any_datetime(),
+            is_completed in any::<bool>(),
+        ) {
+            let mut todo = Todo::new(id, text);
+            todo.created_at = created_at;
+            if is_completed {
+                todo.status = Status::Done;
+            }
+
+            let parsed: Todo = todo.to_string().parse().unwrap();
+            prop_assert_eq!(parsed.id, todo.id);
+            prop_assert_eq!(&parsed.text, &todo.text);
+            prop_assert_eq!(parsed.created_at, todo.created_at);
+            prop_assert_eq!(parsed.status, todo.status);
+        }
+
+        #[test]
+        fn metadata_display_round_trips(seq_id in any::<u32>()) {
+            let metadata = Metadata { seq_id, version: 0 };
+            let parsed: Metadata = metadata.to_string().parse().unwrap();
+
+            prop_assert_eq!(parsed.seq_id, seq_id);
+        }
+    }
+}
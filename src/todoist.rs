@@ -0,0 +1,395 @@
+//! Two-way sync with Todoist through its API, for `todo sync todoist`.
+
+use crate::database::Database;
+use crate::dates;
+use crate::error::TodoError;
+use crate::journal::Operation;
+use crate::output;
+use crate::todo::{self, Priority, Status, Todo};
+use chrono::prelude::*;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+use uuid::Uuid;
+
+const API_URL: &str = "https://api.todoist.com/api/v1";
+
+/// A task as Todoist returns it, with the parts that are synced.
+#[derive(Debug, Deserialize)]
+struct Task {
+    id: String,
+    content: String,
+    #[serde(default)]
+    description: String,
+    /// From 1 for none to 4 for urgent
+    #[serde(default)]
+    priority: u8,
+    #[serde(default)]
+    labels: Vec<String>,
+    due: Option<Due>,
+    #[serde(default, alias = "is_completed")]
+    checked: bool,
+    updated_at: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Due {
+    /// `YYYY-MM-DD`, or a date and time with or without `Z`
+    date: String,
+}
+
+#[derive(Deserialize)]
+struct Page {
+    results: Vec<Task>,
+    next_cursor: Option<String>,
+}
+
+/// What the last sync left behind, kept next to the database as
+/// `<db>.todoist.json`.
+#[derive(Default, Serialize, Deserialize)]
+struct SyncState {
+    synced_at: Option<DateTime<Local>>,
+    /// The Todoist task of every synced todo, by the todo's uuid
+    tasks: HashMap<Uuid, String>,
+}
+
+impl SyncState {
+    fn path(db_path: &Path) -> PathBuf {
+        let mut path = db_path.as_os_str().to_owned();
+        path.push(".todoist.json");
+        PathBuf::from(path)
+    }
+
+    fn load(path: &Path) -> Result<Self, TodoError> {
+        match fs::read(path) {
+            Ok(contents) => serde_json::from_slice(&contents)
+                .map_err(|e| TodoError::parse(e.to_string()).in_file(path)),
+            Err(e) if e.kind() == io::ErrorKind::NotFound => Ok(SyncState::default()),
+            Err(e) => Err(TodoError::from(e).in_file(path)),
+        }
+    }
+
+    fn save(&self, path: &Path) -> Result<(), TodoError> {
+        let write = || fs::write(path, serde_json::to_vec_pretty(self)?);
+        write().map_err(|e| TodoError::from(e).in_file(path))
+    }
+}
+
+struct Client {
+    agent: ureq::Agent,
+    authorization: String,
+}
+
+impl Client {
+    fn new(token: &str) -> Self {
+        let agent = ureq::Agent::config_builder()
+            .timeout_global(Some(Duration::from_secs(30)))
+            .build()
+            .into();
+
+        Client {
+            agent,
+            authorization: format!("Bearer {}", token),
+        }
+    }
+
+    fn active_tasks(&self) -> Result<Vec<Task>, TodoError> {
+        let mut tasks = Vec::new();
+        let mut cursor = None;
+        loop {
+            let mut request = self
+                .agent
+                .get(format!("{}/tasks", API_URL))
+                .header("Authorization", &self.authorization);
+            if let Some(cursor) = &cursor {
+                request = request.query("cursor", cursor);
+            }
+            let page: Page = request
+                .call()
+                .and_then(|mut response| response.body_mut().read_json())
+                .map_err(remote_error)?;
+
+            tasks.extend(page.results);
+            match page.next_cursor {
+                Some(next) => cursor = Some(next),
+                None => return Ok(tasks),
+            }
+        }
+    }
+
+    /// The task with the id, also when it is completed; `None` when it was
+    /// deleted.
+    fn task(&self, id: &str) -> Result<Option<Task>, TodoError> {
+        let result = self
+            .agent
+            .get(format!("{}/tasks/{}", API_URL, id))
+            .header("Authorization", &self.authorization)
+            .call()
+            .and_then(|mut response| response.body_mut().read_json());
+
+        match result {
+            Ok(task) => Ok(Some(task)),
+            Err(ureq::Error::StatusCode(404)) => Ok(None),
+            Err(e) => Err(remote_error(e)),
+        }
+    }
+
+    fn create(&self, todo: &Todo) -> Result<Task, TodoError> {
+        self.agent
+            .post(format!("{}/tasks", API_URL))
+            .header("Authorization", &self.authorization)
+            .send_json(task_body(todo))
+            .and_then(|mut response| response.body_mut().read_json())
+            .map_err(remote_error)
+    }
+
+    fn update(&self, id: &str, todo: &Todo) -> Result<(), TodoError> {
+        self.post(&format!("tasks/{}", id), Some(task_body(todo)))
+    }
+
+    fn close(&self, id: &str) -> Result<(), TodoError> {
+        self.post(&format!("tasks/{}/close", id), None)
+    }
+
+    fn reopen(&self, id: &str) -> Result<(), TodoError> {
+        self.post(&format!("tasks/{}/reopen", id), None)
+    }
+
+    fn delete(&self, id: &str) -> Result<(), TodoError> {
+        self.agent
+            .delete(format!("{}/tasks/{}", API_URL, id))
+            .header("Authorization", &self.authorization)
+            .call()
+            .map(drop)
+            .map_err(remote_error)
+    }
+
+    fn post(&self, path: &str, body: Option<Value>) -> Result<(), TodoError> {
+        let request = self
+            .agent
+            .post(format!("{}/{}", API_URL, path))
+            .header("Authorization", &self.authorization);
+        let response = match body {
+            Some(body) => request.send_json(body),
+            None => request.send_empty(),
+        };
+
+        response.map(drop).map_err(remote_error)
+    }
+}
+
+fn remote_error(e: ureq::Error) -> TodoError {
+    TodoError::Remote(format!("Todoist: {}", e))
+}
+
+/// How many todos and tasks a sync changed.
+#[derive(Default)]
+struct Counts {
+    pushed: usize,
+    pulled: usize,
+}
+
+/// Pushes the local changes since the last sync to Todoist and pulls the
+/// remote ones. When a todo changed on both sides, the newer change wins.
+/// Only open todos are sent to Todoist the first time. Every task created on
+/// either side is written to the sync state right away, so a sync failing
+/// halfway does not create it again the next time.
+pub fn sync(db: &mut Database, db_path: &Path, token: &str) -> Result<(), TodoError> {
+    let state_path = SyncState::path(db_path);
+    let mut state = SyncState::load(&state_path)?;
+    let client = Client::new(token);
+
+    // The todos taken over before a failure are kept, like their tasks in the
+    // sync state
+    let result = exchange(db, &client, &mut state, &state_path);
+    db.save()?;
+    let counts = result?;
+
+    state.synced_at = Some(Local::now());
+    state.save(&state_path)?;
+    output::status(format!(
+        "Sent {} change(s) to Todoist and took over {}",
+        counts.pushed, counts.pulled
+    ));
+
+    Ok(())
+}
+
+fn exchange(
+    db: &mut Database,
+    client: &Client,
+    state: &mut SyncState,
+    state_path: &Path,
+) -> Result<Counts, TodoError> {
+    let synced_at = state.synced_at;
+    let changed_since_sync =
+        |time: DateTime<Local>| synced_at.is_none_or(|synced_at| time > synced_at);
+
+    let mut remote: HashMap<String, Task> = client
+        .active_tasks()?
+        .into_iter()
+        .map(|task| (task.id.clone(), task))
+        .collect();
+    let mut counts = Counts::default();
+
+    let synced: Vec<(Uuid, String)> = state.tasks.clone().into_iter().collect();
+    for (uuid, task_id) in &synced {
+        let todo = db.todos.iter().find(|t| t.uuid == *uuid).cloned();
+        let task = remote.remove(task_id);
+
+        let keep = match (todo, task) {
+            (Some(todo), Some(task)) => {
+                let local_changed = changed_since_sync(todo.updated_at);
+                let remote_time = task.updated_at.map(|time| time.with_timezone(&Local));
+                let remote_changed = remote_time.is_some_and(changed_since_sync);
+
+                if local_changed && remote_time.is_none_or(|time| todo.updated_at >= time) {
+                    client.update(task_id, &todo)?;
+                    if todo.status.is_closed() {
+                        client.close(task_id)?;
+                    }
+                    counts.pushed += 1;
+                } else if remote_changed {
+                    db.apply(Operation::Update {
+                        todo: pulled(todo, &task, db.max_text_length),
+                    })?;
+                    counts.pulled += 1;
+                }
+                true
+            }
+            // The task was completed or deleted in Todoist
+            (Some(todo), None) => match client.task(task_id)? {
+                Some(task) if task.checked => {
+                    if todo.status.is_closed() {
+                        // Both done, nothing left to sync
+                        false
+                    } else if changed_since_sync(todo.updated_at) {
+                        client.reopen(task_id)?;
+                        client.update(task_id, &todo)?;
+                        counts.pushed += 1;
+                        true
+                    } else {
+                        db.apply(Operation::complete(todo.id))?;
+                        counts.pulled += 1;
+                        false
+                    }
+                }
+                Some(_) => true,
+                None => {
+                    db.apply(Operation::delete(todo.id))?;
+                    counts.pulled += 1;
+                    false
+                }
+            },
+            // The todo was deleted or archived here
+            (None, Some(_)) => {
+                if db.trash.iter().any(|t| t.uuid == *uuid) {
+                    client.delete(task_id)?;
+                } else {
+                    client.close(task_id)?;
+                }
+                counts.pushed += 1;
+                false
+            }
+            (None, None) => false,
+        };
+        if !keep {
+            state.tasks.remove(uuid);
+        }
+    }
+
+    let new_todos: Vec<Todo> = db
+        .todos
+        .iter()
+        .filter(|t| !t.status.is_closed() && !state.tasks.contains_key(&t.uuid))
+        .cloned()
+        .collect();
+    for todo in new_todos {
+        let task = client.create(&todo)?;
+        state.tasks.insert(todo.uuid, task.id);
+        state.save(state_path)?;
+        counts.pushed += 1;
+    }
+
+    // What is left was created in Todoist since the last sync
+    for task in remote.into_values() {
+        let todo = db.new_todo(&task_text(&task, db.max_text_length))?;
+        let todo = pulled(todo, &task, db.max_text_length);
+        let uuid = todo.uuid;
+        db.apply(Operation::Add { todo })?;
+        state.tasks.insert(uuid, task.id);
+        state.save(state_path)?;
+        counts.pulled += 1;
+    }
+
+    Ok(counts)
+}
+
+/// The content of the task, shortened to what a todo can hold.
+fn task_text(task: &Task, max_text_length: usize) -> String {
+    let text: String = task.content.chars().take(max_text_length).collect();
+
+    todo::sanitize_text(&text, max_text_length).unwrap_or_else(|_| "(no text)".to_string())
+}
+
+/// The todo with the text, notes, priority, labels and due date of the task.
+fn pulled(mut todo: Todo, task: &Task, max_text_length: usize) -> Todo {
+    todo.text = task_text(task, max_text_length);
+    todo.notes = task.description.clone();
+    todo.priority = match task.priority {
+        4 => Priority::High,
+        3 => Priority::Medium,
+        2 => Priority::Low,
+        _ => Priority::None,
+    };
+    todo.tags = task.labels.clone();
+    todo.due_at = task.due.as_ref().and_then(|due| parse_due(&due.date));
+    if todo.status.is_closed() && !task.checked {
+        todo.set_status(Status::Open, Local::now());
+    }
+
+    todo
+}
+
+fn task_body(todo: &Todo) -> Value {
+    let priority = match todo.priority {
+        Priority::High => 4,
+        Priority::Medium => 3,
+        Priority::Low => 2,
+        Priority::None => 1,
+    };
+    let mut body = json!({
+        "content": todo.text,
+        "description": todo.notes,
+        "priority": priority,
+        "labels": todo.tags,
+    });
+
+    // Due dates given without a time are at the end of the day
+    match todo.due_at {
+        Some(due_at) if Some(due_at.time()) == NaiveTime::from_hms_opt(23, 59, 59) => {
+            body["due_date"] = json!(due_at.format("%Y-%m-%d").to_string());
+        }
+        Some(due_at) => body["due_datetime"] = json!(due_at.with_timezone(&Utc).to_rfc3339()),
+        None => body["due_string"] = json!("no date"),
+    }
+
+    body
+}
+
+/// Parses the date of a due date, which Todoist gives as a day, a time in UTC
+/// or a time in the local time zone.
+fn parse_due(date: &str) -> Option<DateTime<Local>> {
+    if let Ok(datetime) = DateTime::parse_from_rfc3339(date) {
+        return Some(datetime.with_timezone(&Local));
+    }
+    if let Ok(datetime) = NaiveDateTime::parse_from_str(date, "%Y-%m-%dT%H:%M:%S") {
+        return Local.from_local_datetime(&datetime).earliest();
+    }
+
+    dates::parse_datetime(date).ok()
+}
@@ -0,0 +1,80 @@
+//! Subtasks drawn below their parents, for `todo list --tree`.
+
+use crate::todo::Todo;
+use std::collections::HashSet;
+
+/// A todo in the tree together with what is drawn around its text.
+pub struct Node<'a> {
+    pub todo: &'a Todo,
+    /// Box-drawing lines connecting the todo to its parent
+    pub prefix: String,
+    /// Number of closed subtasks, direct or not, and of all subtasks
+    pub progress: Option<(usize, usize)>,
+}
+
+/// Orders `todos` so that subtasks follow their parent. Todos whose parent
+/// is not among them are shown at the top level; otherwise the order of
+/// `todos` is kept.
+pub fn flatten<'a>(todos: &[&'a Todo]) -> Vec<Node<'a>> {
+    let ids: HashSet<u32> = todos.iter().map(|t| t.id).collect();
+    let is_root = |todo: &Todo| todo.parent.is_none_or(|parent| !ids.contains(&parent));
+
+    let mut nodes = Vec::with_capacity(todos.len());
+    let mut visited = HashSet::new();
+    for todo in todos.iter().copied().filter(|t| is_root(t)) {
+        visit(todos, todo, "", None, &mut visited, &mut nodes);
+    }
+    // Todos in a cycle of parents cannot be reached from the top level
+    for todo in todos.iter().copied() {
+        if !visited.contains(&todo.id) {
+            visit(todos, todo, "", None, &mut visited, &mut nodes);
+        }
+    }
+
+    nodes
+}
+
+/// Adds `todo` and its subtasks to `nodes`. `last` is whether it is the last
+/// subtask of its parent, or `None` at the top level. Returns the closed and
+/// total number of todos in the subtree, including `todo`.
+fn visit<'a>(
+    todos: &[&'a Todo],
+    todo: &'a Todo,
+    indent: &str,
+    last: Option<bool>,
+    visited: &mut HashSet<u32>,
+    nodes: &mut Vec<Node<'a>>,
+) -> (usize, usize) {
+    visited.insert(todo.id);
+    let (prefix, child_indent) = match last {
+        None => (String::new(), String::new()),
+        Some(false) => (format!("{}├─ ", indent), format!("{}│  ", indent)),
+        Some(true) => (format!("{}└─ ", indent), format!("{}   ", indent)),
+    };
+
+    let index = nodes.len();
+    nodes.push(Node {
+        todo,
+        prefix,
+        progress: None,
+    });
+
+    let children: Vec<&Todo> = todos
+        .iter()
+        .copied()
+        .filter(|t| t.parent == Some(todo.id) && !visited.contains(&t.id))
+        .collect();
+    let (mut closed, mut total) = (0, 0);
+    for (i, child) in children.iter().enumerate() {
+        let last = i + 1 == children.len();
+        let (child_closed, child_total) =
+            visit(todos, child, &child_indent, Some(last), visited, nodes);
+        closed += child_closed;
+        total += child_total;
+    }
+    if total > 0 {
+        nodes[index].progress = Some((closed, total));
+    }
+
+    (closed + usize::from(todo.status.is_closed()), total + 1)
+}
@@ -0,0 +1,244 @@
+//! Full-screen interface for daily use, for `todo ui`.
+
+use crate::database::Database;
+use crate::error::TodoError;
+use crate::filter::Filter;
+use crate::journal::Operation;
+use crate::todo::Todo;
+use chrono::prelude::*;
+use ratatui::crossterm::event::{self, Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
+use ratatui::layout::{Constraint, Layout, Position};
+use ratatui::style::{Color, Modifier, Style, Stylize};
+use ratatui::text::{Line, Span};
+use ratatui::widgets::{List, ListItem, ListState, Paragraph};
+use ratatui::{DefaultTerminal, Frame};
+use std::cmp::Reverse;
+
+const HELP: &str = "j/k move  space done  a add  d delete  / filter  q quit";
+
+/// What the keys currently do.
+enum Mode {
+    /// Moving through the list
+    Normal,
+    /// Typing the text of a new todo
+    Adding(String),
+    /// Typing into the filter box; the list follows every key
+    Filtering,
+}
+
+struct App {
+    mode: Mode,
+    /// Lowercase text the listed todos contain in their text or notes
+    filter: String,
+    list: ListState,
+    /// Shown in the status bar until the next key
+    message: Option<String>,
+}
+
+/// Runs the interface until it is quit. Every change is applied right away,
+/// like with the subcommands.
+pub fn run(db: &mut Database) -> Result<(), TodoError> {
+    let mut terminal = ratatui::try_init()?;
+    let mut app = App {
+        mode: Mode::Normal,
+        filter: String::new(),
+        list: ListState::default(),
+        message: None,
+    };
+    let result = app.run(&mut terminal, db);
+    ratatui::restore();
+
+    result?;
+    Ok(db.save()?)
+}
+
+impl App {
+    fn run(&mut self, terminal: &mut DefaultTerminal, db: &mut Database) -> Result<(), TodoError> {
+        loop {
+            let ids = self.visible_ids(db);
+            // Changes and filtering can leave the selection past the end
+            match self.list.selected() {
+                _ if ids.is_empty() => self.list.select(None),
+                None => self.list.select(Some(0)),
+                Some(index) if index >= ids.len() => self.list.select(Some(ids.len() - 1)),
+                Some(_) => {}
+            }
+            terminal.draw(|frame| self.draw(frame, db, &ids))?;
+
+            let Event::Key(key) = event::read()? else {
+                continue;
+            };
+            if key.kind != KeyEventKind::Press {
+                continue;
+            }
+            if key.code == KeyCode::Char('c') && key.modifiers.contains(KeyModifiers::CONTROL) {
+                return Ok(());
+            }
+
+            self.message = None;
+            let selected = self
+                .list
+                .selected()
+                .and_then(|index| ids.get(index).copied());
+            if !self.handle_key(key, db, selected)? {
+                return Ok(());
+            }
+        }
+    }
+
+    /// Ids of the todos to list: open ones first, then by priority.
+    fn visible_ids(&self, db: &Database) -> Vec<u32> {
+        let filter = Filter::search(&self.filter);
+        let mut todos: Vec<&Todo> = db.todos.iter().filter(|t| filter.matches(t)).collect();
+        todos.sort_by_key(|t| (t.status.is_closed(), !t.pinned, Reverse(t.priority), t.id));
+
+        todos.iter().map(|t| t.id).collect()
+    }
+
+    /// Returns whether to keep running.
+    fn handle_key(
+        &mut self,
+        key: KeyEvent,
+        db: &mut Database,
+        selected: Option<u32>,
+    ) -> Result<bool, TodoError> {
+        match &mut self.mode {
+            Mode::Normal => match key.code {
+                KeyCode::Char('q') | KeyCode::Esc => return Ok(false),
+                KeyCode::Char('j') | KeyCode::Down => self.list.select_next(),
+                KeyCode::Char('k') | KeyCode::Up => self.list.select_previous(),
+                KeyCode::Char('g') | KeyCode::Home => self.list.select_first(),
+                KeyCode::Char('G') | KeyCode::End => self.list.select_last(),
+                KeyCode::Char(' ') => {
+                    if let Some(todo) = selected.and_then(|id| db.find(id)) {
+                        db.apply(Operation::toggle(todo))?;
+                    }
+                }
+                KeyCode::Char('a') => self.mode = Mode::Adding(String::new()),
+                KeyCode::Char('d') => {
+                    if let Some(todo) = selected.and_then(|id| db.find(id)) {
+                        self.message = Some(format!("Moved \"{}\" to the trash", todo.text));
+                        db.apply(Operation::delete(todo.id))?;
+                    }
+                }
+                KeyCode::Char('/') => self.mode = Mode::Filtering,
+                _ => {}
+            },
+            Mode::Adding(text) => match key.code {
+                KeyCode::Char(c) => text.push(c),
+                KeyCode::Backspace => {
+                    text.pop();
+                }
+                KeyCode::Enter => {
+                    match db.new_todo(text) {
+                        Ok(todo) => {
+                            self.message = Some(format!("Created todo {}", todo.id));
+                            db.apply(Operation::Add { todo })?;
+                        }
+                        Err(e) => self.message = Some(e.to_string()),
+                    }
+                    self.mode = Mode::Normal;
+                }
+                KeyCode::Esc => self.mode = Mode::Normal,
+                _ => {}
+            },
+            Mode::Filtering => match key.code {
+                KeyCode::Char(c) => {
+                    self.filter.extend(c.to_lowercase());
+                    self.list.select_first();
+                }
+                KeyCode::Backspace => {
+                    self.filter.pop();
+                    self.list.select_first();
+                }
+                KeyCode::Enter => self.mode = Mode::Normal,
+                KeyCode::Esc => {
+                    self.filter.clear();
+                    self.mode = Mode::Normal;
+                }
+                _ => {}
+            },
+        }
+
+        Ok(true)
+    }
+
+    fn draw(&mut self, frame: &mut Frame, db: &Database, ids: &[u32]) {
+        let [filter_area, list_area, status_area] = Layout::vertical([
+            Constraint::Length(1),
+            Constraint::Min(1),
+            Constraint::Length(1),
+        ])
+        .areas(frame.area());
+
+        let filter_style = match self.mode {
+            Mode::Filtering => Style::new().bold(),
+            _ => Style::new().dim(),
+        };
+        let filter_line = format!("Filter: {}", self.filter);
+        if let Mode::Filtering = self.mode {
+            let x = filter_area.x + filter_line.chars().count() as u16;
+            frame.set_cursor_position(Position::new(x, filter_area.y));
+        }
+        frame.render_widget(Paragraph::new(filter_line).style(filter_style), filter_area);
+
+        let now = Local::now();
+        let items: Vec<ListItem> = ids
+            .iter()
+            .filter_map(|&id| db.find(id))
+            .map(|todo| ListItem::new(todo_line(todo, now)))
+            .collect();
+        let list = List::new(items).highlight_style(Style::new().add_modifier(Modifier::REVERSED));
+        frame.render_stateful_widget(list, list_area, &mut self.list);
+
+        let status = match &self.mode {
+            Mode::Adding(text) => {
+                let prompt = format!("New todo: {}", text);
+                let x = status_area.x + prompt.chars().count() as u16;
+                frame.set_cursor_position(Position::new(x, status_area.y));
+                Line::from(prompt)
+            }
+            Mode::Filtering => Line::from("Enter to keep the filter, Esc to clear it").dim(),
+            Mode::Normal => match &self.message {
+                Some(message) => Line::from(message.as_str()),
+                None => Line::from(HELP).dim(),
+            },
+        };
+        frame.render_widget(Paragraph::new(status), status_area);
+    }
+}
+
+/// A checkbox with the text, tags and due date of the todo.
+fn todo_line(todo: &Todo, now: DateTime<Local>) -> Line<'static> {
+    let check = if todo.status.is_closed() {
+        "[x] "
+    } else {
+        "[ ] "
+    };
+    let text_style = if todo.status.is_closed() {
+        Style::new().dim().crossed_out()
+    } else {
+        Style::new()
+    };
+
+    let mut spans = vec![
+        Span::raw(check),
+        Span::styled(todo.text.clone(), text_style),
+    ];
+    for tag in &todo.tags {
+        spans.push(Span::styled(format!(" +{}", tag), Color::Cyan));
+    }
+    if let Some(due_at) = todo.due_at {
+        let style = if todo.is_overdue(now) {
+            Style::new().fg(Color::Red)
+        } else {
+            Style::new().dim()
+        };
+        spans.push(Span::styled(
+            format!("  due {}", due_at.format("%Y-%m-%d")),
+            style,
+        ));
+    }
+
+    Line::from(spans)
+}
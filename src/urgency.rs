@@ -0,0 +1,151 @@
+//! How urgent a todo is, a score like Taskwarrior's urgency that listings
+//! are sorted by unless other sort keys are given.
+//!
+//! Every factor is a number between 0 and 1 multiplied by its weight from
+//! `[urgency]` in the config, and the score is the sum:
+//!
+//! - the priority, with a weight for each of high, medium and low
+//! - the due date: 0.2 for two weeks or more away, rising to 1 a week after
+//!   it has passed
+//! - the age, reaching 1 after `max_age` days
+//! - having tags: 0.8 for one tag, 0.9 for two and 1 for more, plus the
+//!   weight of every tag listed under `[urgency.tag]`
+
+use crate::todo::{Priority, Todo};
+use chrono::prelude::*;
+use serde::{Deserialize, Deserializer};
+use std::collections::HashMap;
+
+/// The weights of the factors, by default the ones Taskwarrior uses.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct Weights {
+    pub priority_high: f64,
+    pub priority_medium: f64,
+    pub priority_low: f64,
+    pub due: f64,
+    pub age: f64,
+    /// Days after which a todo is as old as it gets for its score
+    pub max_age: u32,
+    pub tags: f64,
+    /// Weights of single tags, e.g. `next = 15.0`; tags match in any case
+    #[serde(deserialize_with = "lowercase_keys")]
+    pub tag: HashMap<String, f64>,
+}
+
+fn lowercase_keys<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<String, f64>, D::Error> {
+    let weights = HashMap::<String, f64>::deserialize(deserializer)?;
+
+    Ok(weights
+        .into_iter()
+        .map(|(tag, weight)| (tag.to_lowercase(), weight))
+        .collect())
+}
+
+impl Default for Weights {
+    fn default() -> Self {
+        Weights {
+            priority_high: 6.0,
+            priority_medium: 3.9,
+            priority_low: 1.8,
+            due: 12.0,
+            age: 2.0,
+            max_age: 365,
+            tags: 1.0,
+            tag: HashMap::new(),
+        }
+    }
+}
+
+/// The urgency of the todo; done and cancelled todos have none.
+pub fn score(todo: &Todo, weights: &Weights, now: DateTime<Local>) -> f64 {
+    if todo.status.is_closed() {
+        return 0.0;
+    }
+
+    let priority = match todo.priority {
+        Priority::High => weights.priority_high,
+        Priority::Medium => weights.priority_medium,
+        Priority::Low => weights.priority_low,
+        Priority::None => 0.0,
+    };
+    let due = todo
+        .due_at
+        .map_or(0.0, |due_at| weights.due * due_factor(due_at, now));
+    let age = match weights.max_age {
+        0 => 1.0,
+        max_age => {
+            let days = (now - todo.created_at).num_seconds() as f64 / 86_400.0;
+            (days / max_age as f64).clamp(0.0, 1.0)
+        }
+    };
+    let tags = match todo.tags.len() {
+        0 => 0.0,
+        1 => 0.8,
+        2 => 0.9,
+        _ => 1.0,
+    };
+    let tag: f64 = todo
+        .tags
+        .iter()
+        .filter_map(|tag| weights.tag.get(&tag.to_lowercase()))
+        .sum();
+
+    priority + due + weights.age * age + weights.tags * tags + tag
+}
+
+/// From 0.2 for two weeks or more before the due date to 1 a week after it.
+fn due_factor(due_at: DateTime<Local>, now: DateTime<Local>) -> f64 {
+    let days_overdue = (now - due_at).num_seconds() as f64 / 86_400.0;
+    if days_overdue >= 7.0 {
+        1.0
+    } else if days_overdue >= -14.0 {
+        (days_overdue + 14.0) * 0.8 / 21.0 + 0.2
+    } else {
+        0.2
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn due_and_important_todos_are_more_urgent() {
+        let now = Local::now();
+        let weights = Weights::default();
+        let plain = Todo::new(1, "some day");
+        let mut important = Todo::new(2, "file taxes");
+        important.priority = Priority::High;
+        let mut due = important.clone();
+        due.due_at = Some(now + Duration::days(1));
+        let mut overdue = due.clone();
+        overdue.due_at = Some(now - Duration::days(10));
+
+        let scores: Vec<f64> = [&plain, &important, &due, &overdue]
+            .iter()
+            .map(|todo| score(todo, &weights, now))
+            .collect();
+        assert!(
+            scores.windows(2).all(|pair| pair[0] < pair[1]),
+            "{:?}",
+            scores
+        );
+        assert!((scores[3] - 18.0).abs() < 0.01);
+    }
+
+    #[test]
+    fn tag_weights_match_in_any_case() {
+        let weights: Weights = toml::from_str("[tag]\nWork = 5.0").unwrap();
+        let now = Local::now();
+        let mut todo = Todo::new(1, "report");
+        todo.created_at = now;
+        let plain = score(&todo, &weights, now);
+        todo.tags = vec!["work".to_string()];
+
+        assert!((score(&todo, &weights, now) - plain - 5.8).abs() < 0.01);
+    }
+}
@@ -0,0 +1,107 @@
+//! HTTP POST requests announcing changes to the todos, for wiring the app
+//! into chat rooms or home automation.
+
+use crate::config::WebhookConfig;
+use crate::journal::Operation;
+use crate::todo::{Status, Todo};
+use chrono::prelude::*;
+use clap::ValueEnum;
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::time::Duration;
+
+/// The changes a webhook can be sent for.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum Event {
+    Created,
+    Completed,
+    Deleted,
+}
+
+impl Event {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Event::Created => "created",
+            Event::Completed => "completed",
+            Event::Deleted => "deleted",
+        }
+    }
+
+    /// The event `operation` is, if it is one; to be called before it is
+    /// applied to the todos. Completing a closed todo again is none.
+    pub fn of(operation: &Operation, todos: &[Todo]) -> Option<(Event, u32)> {
+        let is_open = |id: u32| todos.iter().any(|t| t.id == id && !t.status.is_closed());
+
+        match operation {
+            Operation::Add { todo } => Some((Event::Created, todo.id)),
+            Operation::Complete { id, .. } if is_open(*id) => Some((Event::Completed, *id)),
+            Operation::Update { todo } if todo.status == Status::Done && is_open(todo.id) => {
+                Some((Event::Completed, todo.id))
+            }
+            Operation::Delete { id, .. } if todos.iter().any(|t| t.id == *id) => {
+                Some((Event::Deleted, *id))
+            }
+            _ => None,
+        }
+    }
+}
+
+pub struct Webhook {
+    agent: ureq::Agent,
+    url: String,
+    events: Vec<Event>,
+    headers: HashMap<String, String>,
+}
+
+impl Webhook {
+    pub fn new(config: &WebhookConfig) -> Self {
+        // Keeps commands from hanging on a server that does not answer
+        let agent = ureq::Agent::config_builder()
+            .timeout_global(Some(Duration::from_secs(5)))
+            .build()
+            .into();
+
+        Webhook {
+            agent,
+            url: config.url.clone(),
+            events: config.events.clone(),
+            headers: config.headers.clone(),
+        }
+    }
+
+    /// Posts the event with the todo as it is after the change. A failed
+    /// request is only logged, the change is made either way.
+    pub fn send(&self, event: Event, todo: &Todo) {
+        if !self.events.contains(&event) {
+            return;
+        }
+
+        // `text` is what Slack and similar chat services show
+        let payload = json!({
+            "event": event.as_str(),
+            "text": format!("Todo {} {}: {}", todo.id, event.as_str(), todo.text),
+            "at": Local::now().to_rfc3339(),
+            "todo": todo,
+        });
+        let mut request = self.agent.post(&self.url);
+        for (name, value) in &self.headers {
+            request = request.header(name, value);
+        }
+
+        log::debug!(
+            "Sending the {} webhook for todo {}",
+            event.as_str(),
+            todo.id
+        );
+        if let Err(e) = request.send_json(payload) {
+            log::warn!(
+                "Could not send the {} webhook to {}: {}",
+                event.as_str(),
+                self.url,
+                e
+            );
+        }
+    }
+}